@@ -3,6 +3,7 @@ mod configuration;
 mod state;
 mod store;
 mod server_proxy;
+mod interpolation;
 mod terminal;
 
 use application::{Application};
@@ -13,6 +14,7 @@ use crate::logger::{self};
 use clap::{App, Arg, ArgMatches};
 
 use std::net::{SocketAddr};
+use std::time::{Duration};
 
 lazy_static! {
     static ref DEFAULT_LOG_FILE: String = format!(
@@ -48,6 +50,11 @@ pub fn configure_cli<'a, 'b>() -> App<'a, 'b> {
             })
             .help("Set the player's character. Must be unique in the server")
         )
+        .arg(Arg::with_name("password")
+            .long("password")
+            .value_name("PASSWORD")
+            .help("Set the password for the servers that require it")
+        )
         .arg(Arg::with_name("host")
             .long("host")
             .short("h")
@@ -58,16 +65,67 @@ pub fn configure_cli<'a, 'b>() -> App<'a, 'b> {
             })
             .help("Set the server address (ip and port). Format example: 192.168.0.56:3549")
         )
+        .arg(Arg::with_name("registry")
+            .long("registry")
+            .value_name("ADDR")
+            .validator(|addr| match addr.parse::<SocketAddr>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("Registry must be a valid network address".into()),
+            })
+            .help("List in the menu the servers registered in this registry (ip and port), \
+                see 'asciiarena registry'. Format example: 192.168.0.56:3551")
+        )
+        .arg(Arg::with_name("interpolation-delay")
+            .long("interpolation-delay")
+            .value_name("MILLISECONDS")
+            .default_value("100")
+            .validator(|delay| match delay.parse::<u64>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("The value must be a positive number".into()),
+            })
+            .help("Set how far behind the server the other entities and spells are rendered. \
+                A bigger value smooths the movement but adds latency. Use 0 to disable it")
+        )
+        .arg(Arg::with_name("server-timeout")
+            .long("server-timeout")
+            .value_name("SECONDS")
+            .default_value("5")
+            .validator(|value| match value.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => Ok(()),
+                _ => Err("The value must be a number > 0".into()),
+            })
+            .help("Consider the connection lost if the server does not send any message in this time")
+        )
+        .arg(Arg::with_name("reconnection-attempts")
+            .long("reconnection-attempts")
+            .value_name("NUMBER")
+            .default_value("6")
+            .validator(|value| match value.parse::<usize>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("The value must be a positive number".into()),
+            })
+            .help("Attempts to reconnect to the server if the connection is lost during a session. \
+                The time between attempts grows exponentially. Use 0 to disable it")
+        )
 }
 
 pub fn run(matches: &ArgMatches) {
     let level = matches.value_of("log").unwrap().parse().unwrap();
     let file_name = matches.value_of("log-file").unwrap();
-    logger::init(level, logger::Output::File(file_name));
+    logger::init(level, logger::Output::File(file_name), logger::Format::Text);
 
     let config = Config {
         character: matches.value_of("character").map(|name| name.chars().next().unwrap()),
+        password: matches.value_of("password").map(|password| password.into()),
         server_addr: matches.value_of("host").map(|addr| addr.parse().unwrap()),
+        interpolation_delay: Duration::from_millis(
+            matches.value_of("interpolation-delay").unwrap().parse().unwrap()
+        ),
+        server_timeout: Duration::from_secs(
+            matches.value_of("server-timeout").unwrap().parse().unwrap()
+        ),
+        reconnection_attempts: matches.value_of("reconnection-attempts").unwrap().parse().unwrap(),
+        registry_addr: matches.value_of("registry").map(|addr| addr.parse().unwrap()),
     };
 
     Application::new(config).run();
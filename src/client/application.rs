@@ -35,7 +35,7 @@ impl Application {
         let mut event_queue = EventQueue::new();
 
         let event_sender = event_queue.sender().clone();
-        let mut server = ServerProxy::new(move |server_event| {
+        let mut server = ServerProxy::new(&config, move |server_event| {
             event_sender.send(AppEvent::ServerEvent(server_event))
         });
 
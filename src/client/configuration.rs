@@ -1,6 +1,12 @@
 use std::net::{SocketAddr};
+use std::time::{Duration};
 
 pub struct Config {
     pub server_addr: Option<SocketAddr>,
     pub character: Option<char>,
+    pub password: Option<String>,
+    pub interpolation_delay: Duration,
+    pub server_timeout: Duration,
+    pub reconnection_attempts: usize,
+    pub registry_addr: Option<SocketAddr>,
 }
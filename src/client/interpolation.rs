@@ -0,0 +1,253 @@
+use crate::message::{EntityData, SpellData};
+use crate::vec2::{Vec2};
+use crate::ids::{EntityId, SpellId};
+
+use std::time::{Instant, Duration};
+use std::collections::{HashMap, VecDeque};
+
+const MAX_SNAPSHOTS: usize = 32;
+
+struct Snapshot {
+    timestamp: Instant,
+    entities: HashMap<EntityId, EntityData>,
+    spells: HashMap<SpellId, SpellData>,
+}
+
+/// Keeps the last received frames in order to render the arena some time behind the server.
+/// The positions between two frames are interpolated to smooth the movement
+/// when frames arrive unevenly.
+pub struct InterpolationBuffer {
+    delay: Duration,
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl InterpolationBuffer {
+    pub fn new(delay: Duration) -> InterpolationBuffer {
+        InterpolationBuffer {
+            delay,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    pub fn push(
+        &mut self,
+        timestamp: Instant,
+        entities: &HashMap<EntityId, EntityData>,
+        spells: &HashMap<SpellId, SpellData>,
+    ) {
+        self.snapshots.push_back(Snapshot {
+            timestamp,
+            entities: entities.clone(),
+            spells: spells.clone(),
+        });
+
+        // Only one snapshot older than the render time is needed to interpolate from it.
+        let render_time = self.render_time(timestamp);
+        while self.snapshots.len() > MAX_SNAPSHOTS
+        || (self.snapshots.len() > 2 && self.snapshots[1].timestamp <= render_time) {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Entities as they should be rendered at `now`.
+    pub fn entities(&self, now: Instant) -> Vec<EntityData> {
+        let render_time = self.render_time(now);
+        match self.bounds(render_time) {
+            Some((from, to)) => from.entities
+                .values()
+                .map(|entity| {
+                    let mut entity = entity.clone();
+                    if let Some(to) = to {
+                        if let Some(next) = to.entities.get(&entity.id) {
+                            entity.position = Self::interpolate(
+                                entity.position, from.timestamp,
+                                next.position, to.timestamp,
+                                render_time
+                            );
+                        }
+                    }
+                    entity
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Spells as they should be rendered at `now`.
+    pub fn spells(&self, now: Instant) -> Vec<SpellData> {
+        let render_time = self.render_time(now);
+        match self.bounds(render_time) {
+            Some((from, to)) => from.spells
+                .values()
+                .map(|spell| {
+                    let mut spell = spell.clone();
+                    if let Some(to) = to {
+                        if let Some(next) = to.spells.get(&spell.id) {
+                            spell.position = Self::interpolate(
+                                spell.position, from.timestamp,
+                                next.position, to.timestamp,
+                                render_time
+                            );
+                        }
+                    }
+                    spell
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn render_time(&self, now: Instant) -> Instant {
+        now.checked_sub(self.delay).unwrap_or(now)
+    }
+
+    /// Returns the last snapshot previous to the render time and the next one if exists.
+    /// If the render time is older than all snapshots, the oldest one is used.
+    fn bounds(&self, render_time: Instant) -> Option<(&Snapshot, Option<&Snapshot>)> {
+        let next_index = self.snapshots
+            .iter()
+            .position(|snapshot| snapshot.timestamp > render_time)
+            .unwrap_or(self.snapshots.len());
+
+        match next_index {
+            0 => self.snapshots.front().map(|snapshot| (snapshot, None)),
+            _ => Some((&self.snapshots[next_index - 1], self.snapshots.get(next_index))),
+        }
+    }
+
+    fn interpolate(
+        from: Vec2,
+        from_time: Instant,
+        to: Vec2,
+        to_time: Instant,
+        render_time: Instant
+    ) -> Vec2 {
+        let total = to_time.duration_since(from_time).as_secs_f32();
+        if total <= 0.0 {
+            return to
+        }
+
+        let progress = render_time.duration_since(from_time).as_secs_f32() / total;
+        let progress = progress.clamp(0.0, 1.0);
+        Vec2::xy(
+            (from.x as f32 + (to.x - from.x) as f32 * progress).round() as i32,
+            (from.y as f32 + (to.y - from.y) as f32 * progress).round() as i32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::character::{CharacterId};
+
+    const DELAY: Duration = Duration::from_millis(100);
+
+    fn entities(x: i32) -> HashMap<EntityId, EntityData> {
+        let entity = EntityData {
+            id: EntityId(1),
+            character_id: CharacterId::Player('A'),
+            position: Vec2::xy(x, 0),
+            health: 10,
+            energy: 10,
+        };
+        vec![(entity.id, entity)].into_iter().collect()
+    }
+
+    fn position_x(buffer: &InterpolationBuffer, now: Instant) -> i32 {
+        buffer.entities(now)[0].position.x
+    }
+
+    #[test]
+    fn empty_buffer() {
+        let buffer = InterpolationBuffer::new(DELAY);
+        assert!(buffer.entities(Instant::now()).is_empty());
+        assert!(buffer.spells(Instant::now()).is_empty());
+    }
+
+    #[test]
+    fn interpolate_between_snapshots() {
+        let start = Instant::now();
+        let mut buffer = InterpolationBuffer::new(DELAY);
+        buffer.push(start, &entities(0), &HashMap::new());
+        buffer.push(start + DELAY, &entities(10), &HashMap::new());
+
+        assert_eq!(position_x(&buffer, start + DELAY), 0);
+        assert_eq!(position_x(&buffer, start + DELAY + DELAY / 2), 5);
+        assert_eq!(position_x(&buffer, start + DELAY * 2), 10);
+    }
+
+    #[test]
+    fn render_time_out_of_the_snapshots() {
+        let start = Instant::now();
+        let mut buffer = InterpolationBuffer::new(DELAY);
+        buffer.push(start, &entities(0), &HashMap::new());
+        buffer.push(start + DELAY, &entities(10), &HashMap::new());
+
+        // Older than all the snapshots: the oldest one.
+        assert_eq!(position_x(&buffer, start), 0);
+
+        // Newer than all the snapshots: the newest one, without extrapolation.
+        assert_eq!(position_x(&buffer, start + DELAY * 5), 10);
+    }
+
+    #[test]
+    fn entity_missing_in_the_next_snapshot() {
+        let start = Instant::now();
+        let mut buffer = InterpolationBuffer::new(DELAY);
+        buffer.push(start, &entities(0), &HashMap::new());
+        buffer.push(start + DELAY, &HashMap::new(), &HashMap::new());
+
+        assert_eq!(position_x(&buffer, start + DELAY + DELAY / 2), 0);
+        assert!(buffer.entities(start + DELAY * 2).is_empty());
+    }
+
+    #[test]
+    fn push_removes_the_snapshots_behind_the_render_time() {
+        let start = Instant::now();
+        let mut buffer = InterpolationBuffer::new(DELAY);
+        for frame in 0..10 {
+            buffer.push(start + DELAY * frame, &entities(frame as i32), &HashMap::new());
+        }
+
+        // The snapshot previous to the render time is kept to interpolate from it.
+        assert_eq!(buffer.snapshots.len(), 2);
+        assert_eq!(buffer.snapshots[0].timestamp, start + DELAY * 8);
+    }
+
+    #[test]
+    fn push_keeps_a_maximum_of_snapshots() {
+        let start = Instant::now();
+        let mut buffer = InterpolationBuffer::new(Duration::from_secs(3600));
+        for frame in 0..(MAX_SNAPSHOTS as u32 + 8) {
+            buffer.push(start + DELAY * frame, &entities(0), &HashMap::new());
+        }
+
+        assert_eq!(buffer.snapshots.len(), MAX_SNAPSHOTS);
+        assert_eq!(buffer.snapshots[0].timestamp, start + DELAY * 8);
+    }
+
+    #[test]
+    fn interpolate_clamps_the_progress() {
+        let start = Instant::now();
+        let (from, to) = (Vec2::xy(0, 0), Vec2::xy(4, -4));
+        let interpolate = |render_time| InterpolationBuffer::interpolate(
+            from, start,
+            to, start + DELAY,
+            render_time
+        );
+
+        assert_eq!(interpolate(start), from);
+        assert_eq!(interpolate(start + DELAY / 4), Vec2::xy(1, -1));
+        assert_eq!(interpolate(start + DELAY * 2), to);
+
+        // Snapshots with the same timestamp.
+        let same_time = InterpolationBuffer::interpolate(from, start, to, start, start + DELAY);
+        assert_eq!(same_time, to);
+    }
+}
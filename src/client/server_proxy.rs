@@ -1,18 +1,31 @@
+use super::configuration::{Config};
+
 use crate::message::{LoginStatus, ServerInfo, ClientMessage, ServerMessage,
-    LoggedKind, GameInfo, ArenaInfo, Frame, GameEvent};
-use crate::version::{self, Compatibility};
+    LoggedKind, GameInfo, ArenaInfo, Frame, GameEvent, DiscoveryInfo, RoomSettings, RoomInfo,
+    RoomStatus, QueuePreferences, QueueStatus, DISCOVERY_ADDR};
+use crate::version::{self, Compatibility, ProtocolVersion, Capabilities, Capability};
 use crate::direction::{Direction};
-use crate::ids::{SkillId};
+use crate::ids::{SkillId, PingId, SessionToken, TeamId};
+use crate::util::{self};
 
 use message_io::events::{EventQueue, EventSender};
 use message_io::network::{Network, NetEvent, Endpoint};
 
+use itertools::{Itertools};
+
 use std::net::{IpAddr, SocketAddr};
 use std::thread::{self, JoinHandle};
 use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
-use std::time::{Duration};
+use std::time::{Duration, Instant};
+use std::collections::{VecDeque, HashMap};
 
 const UDP_HANDSHAKE_MAX_ATTEMPS: usize = 10;
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+const PING_HISTORY: usize = 20;
+const RECONNECTION_BASE_DELAY: Duration = Duration::from_millis(500);
+const RECONNECTION_MAX_DELAY: Duration = Duration::from_secs(8);
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(2);
+const DISCOVERY_EXPIRATION: Duration = Duration::from_secs(5);
 
 lazy_static! {
     static ref EVENT_SAMPLING_TIMEOUT: Duration = Duration::from_millis(50);
@@ -23,10 +36,15 @@ lazy_static! {
 pub enum ApiCall {
     Connect(SocketAddr),
     Disconnect,
-    CheckVersion(String),
+    CheckVersion,
     SubscribeInfo,
-    Login(char),
+    Login(char, Option<String>), // Character, password
     Logout,
+    JoinRoom(String),
+    CreateRoom(String, RoomSettings),
+    JoinQueue(char, QueuePreferences), // Character, preferences
+    LeaveQueue,
+    ChooseTeam(TeamId),
     MovePlayer(Direction),
     CastSkill(Direction, SkillId),
 }
@@ -46,6 +64,15 @@ pub enum ServerEvent {
     GameStep(Frame),
     WaitArena(Duration),
     StartArena(ArenaInfo),
+    NetworkStats(NetworkStats),
+    Reconnecting(usize, Duration), // Attempt number, time until the attempt
+    ServerList(Vec<ListedServer>),
+    Broadcast(String),
+    Shutdown(String), // Reason. If the server restarts, a reconnection is notified after it
+    RoomList(Vec<RoomInfo>),
+    RoomStatus(String, RoomStatus),
+    QueueStatus(QueueStatus),
+    TeamChoices(Vec<(char, TeamId)>),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -65,11 +92,95 @@ impl ConnectionStatus {
     }
 }
 
+/// Where a listed server was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerSource {
+    Lan,
+    Registry,
+}
+
+/// Server found in the LAN by discovery or listed by a registry
+#[derive(Debug, Clone)]
+pub struct ListedServer {
+    pub addr: SocketAddr,
+    pub source: ServerSource,
+    pub version: String,
+    pub compatibility: Compatibility,
+    pub ping: Option<Duration>,
+    pub info: ServerInfo,
+}
+
+/// Connection quality measured from the client side
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NetworkStats {
+    pub tcp_ping: Option<Duration>,
+    pub udp_ping: Option<Duration>,
+    pub jitter: Option<Duration>,
+    pub udp_loss: Option<f32>, // Ratio of lost udp pings
+}
+
 #[derive(Debug)]
 enum Event {
     Api(ApiCall),
     Network(NetEvent<ServerMessage>),
     HelloUdp(usize),
+    Ping,
+    Reconnect(usize),
+    Discover,
+}
+
+/// Measures the round trip time of the pings sent by one channel.
+/// A ping not answered before sending the next one is considered lost.
+#[derive(Default)]
+struct PingTracker {
+    last_id: PingId,
+    pending: Option<(PingId, Instant)>,
+    rtt: Option<Duration>,
+    jitter: Option<Duration>,
+    history: VecDeque<bool>, // Received or lost
+}
+
+impl PingTracker {
+    fn next_ping(&mut self, now: Instant) -> PingId {
+        if self.pending.take().is_some() {
+            self.record(false);
+        }
+        self.last_id = PingId::next(self.last_id);
+        self.pending = Some((self.last_id, now));
+        self.last_id
+    }
+
+    fn pong(&mut self, id: PingId, now: Instant) {
+        if let Some((pending_id, ping_time)) = self.pending {
+            if pending_id == id {
+                self.pending = None;
+                let rtt = now.duration_since(ping_time);
+                if let Some(last_rtt) = self.rtt {
+                    // Smoothed as the interarrival jitter of RFC 3550
+                    let variation = rtt.abs_diff(last_rtt);
+                    let jitter = self.jitter.unwrap_or(variation).as_secs_f32();
+                    let jitter = jitter + (variation.as_secs_f32() - jitter) / 16.0;
+                    self.jitter = Some(Duration::from_secs_f32(jitter));
+                }
+                self.rtt = Some(rtt);
+                self.record(true);
+            }
+        }
+    }
+
+    fn record(&mut self, received: bool) {
+        self.history.push_back(received);
+        if self.history.len() > PING_HISTORY {
+            self.history.pop_front();
+        }
+    }
+
+    fn loss(&self) -> Option<f32> {
+        match self.history.len() {
+            0 => None,
+            len => Some(self.history.iter().filter(|&&received| !received).count() as f32 / len as f32),
+        }
+    }
 }
 
 pub struct ServerProxy {
@@ -79,10 +190,14 @@ pub struct ServerProxy {
 }
 
 impl ServerProxy {
-    pub fn new(event_callback: impl Fn(ServerEvent) + Send + 'static) -> ServerProxy {
+    pub fn new(config: &Config, event_callback: impl Fn(ServerEvent) + Send + 'static) -> ServerProxy {
         let mut event_queue = EventQueue::new();
         let event_sender = event_queue.sender().clone();
 
+        let server_timeout = config.server_timeout;
+        let reconnection_attempts = config.reconnection_attempts;
+        let registry_addr = config.registry_addr;
+
         let proxy_thread_running = Arc::new(AtomicBool::new(true));
         let proxy_thread_handle = {
             let running = proxy_thread_running.clone();
@@ -90,7 +205,14 @@ impl ServerProxy {
                 .name("asciiarena: server event collector".into())
                 .spawn(move || {
                 let sender = event_queue.sender().clone();
-                let mut connection = ServerConnection::new(sender, event_callback);
+                let mut connection = ServerConnection::new(
+                    sender,
+                    server_timeout,
+                    reconnection_attempts,
+                    registry_addr,
+                    event_callback,
+                );
+                connection.process_event(Event::Discover);
                 while running.load(Ordering::Relaxed) {
                     if let Some(event) = event_queue.receive_timeout(*EVENT_SAMPLING_TIMEOUT) {
                         connection.process_event(event);
@@ -141,19 +263,54 @@ struct ConnectionInfo {
     tcp: Option<Endpoint>,
     udp: Option<Endpoint>,
     has_udp_hasdshake: bool,
-    session_token: Option<usize>,
+    session_token: Option<SessionToken>,
+}
+
+/// Listed server with the state needed to keep it updated
+struct ListedServerEntry {
+    server: ListedServer,
+    capabilities: Capabilities,
+    last_seen: Instant,
+    ping_endpoint: Option<Endpoint>, // By udp, created at the first ping
+    ping: PingTracker,
+}
+
+/// Reconnection in progress to the server of a lost session.
+/// It finishes when the character logs again.
+struct Reconnection {
+    addr: SocketAddr,
+    attempt: usize,
 }
 
 struct ServerConnection<C> {
     event_sender: EventSender<Event>,
     network: Network,
     connection: ConnectionInfo,
+    tcp_ping: PingTracker,
+    udp_ping: PingTracker,
+    is_pinging: bool,
+    server_timeout: Duration,
+    last_server_activity: Instant,
+    capabilities: Option<Capabilities>, // Negotiated with the server
+    reconnection_attempts: usize,
+    reconnection: Option<Reconnection>,
+    addr: Option<SocketAddr>,
+    discovery: Option<(Endpoint, u16)>, // Multicast endpoint, listening port for the answers
+    registry_addr: Option<SocketAddr>,
+    registry: Option<Endpoint>,
+    listed_servers: HashMap<SocketAddr, ListedServerEntry>,
     event_callback: C,
 }
 
 impl<C> ServerConnection<C>
 where C: Fn(ServerEvent) {
-    pub fn new(event_sender: EventSender<Event>, event_callback: C) -> ServerConnection<C> {
+    pub fn new(
+        event_sender: EventSender<Event>,
+        server_timeout: Duration,
+        reconnection_attempts: usize,
+        registry_addr: Option<SocketAddr>,
+        event_callback: C,
+    ) -> ServerConnection<C> {
         let sender = event_sender.clone();
         let network = Network::new(move |net_event| sender.send(Event::Network(net_event)));
 
@@ -168,6 +325,19 @@ where C: Fn(ServerEvent) {
                 has_udp_hasdshake: false,
                 session_token: None,
             },
+            tcp_ping: PingTracker::default(),
+            udp_ping: PingTracker::default(),
+            is_pinging: false,
+            server_timeout,
+            last_server_activity: Instant::now(),
+            capabilities: None,
+            reconnection_attempts,
+            reconnection: None,
+            addr: None,
+            discovery: None,
+            registry_addr,
+            registry: None,
+            listed_servers: HashMap::new(),
             event_callback
         }
     }
@@ -179,6 +349,12 @@ where C: Fn(ServerEvent) {
                 log::info!("Connected to server by tcp on {}", addr);
                 self.connection.tcp = Some(tcp_endpoint);
                 self.connection.ip = Some(addr.ip());
+                self.addr = Some(addr);
+                self.last_server_activity = Instant::now();
+                if !self.is_pinging {
+                    self.is_pinging = true;
+                    self.event_sender.send(Event::Ping);
+                }
                 ConnectionStatus::Connected
             },
             Err(_) => {
@@ -194,6 +370,10 @@ where C: Fn(ServerEvent) {
         self.connection.udp_port = None;
         self.connection.udp = None;
         self.connection.ip = None;
+        self.addr = None;
+        self.capabilities = None;
+        self.tcp_ping = PingTracker::default();
+        self.udp_ping = PingTracker::default();
         if let Some(endpoint) = self.connection.tcp {
             self.network.remove_resource(endpoint.resource_id());
             self.connection.tcp = None;
@@ -205,6 +385,7 @@ where C: Fn(ServerEvent) {
         self.connection.has_udp_hasdshake = false;
         self.connection.session_token = None;
         self.connection.udp = None;
+        self.udp_ping = PingTracker::default();
         let tcp = *self.connection.tcp.as_ref().unwrap();
         self.network.send(tcp, ClientMessage::Logout);
     }
@@ -215,28 +396,58 @@ where C: Fn(ServerEvent) {
             Event::Api(api_event) => {
                 match api_event {
                     ApiCall::Connect(addr) => {
+                        self.reconnection = None;
                         let result = self.connect(addr);
                         (self.event_callback)(ServerEvent::ConnectionResult(result));
                     },
                     ApiCall::Disconnect => {
+                        self.reconnection = None;
                         let result = self.disconnect();
                         (self.event_callback)(ServerEvent::ConnectionResult(result));
                     },
-                    ApiCall::CheckVersion(version) => {
+                    ApiCall::CheckVersion => {
                         let tcp = *self.connection.tcp.as_ref().unwrap();
+                        let version = ProtocolVersion::current();
                         self.network.send(tcp, ClientMessage::Version(version));
                     },
                     ApiCall::SubscribeInfo => {
                         let tcp = *self.connection.tcp.as_ref().unwrap();
                         self.network.send(tcp, ClientMessage::SubscribeServerInfo);
+                        if self.has_capability(Capability::Rooms) {
+                            self.network.send(tcp, ClientMessage::ListRooms);
+                        }
                     },
-                    ApiCall::Login(character) => {
+                    ApiCall::Login(character, password) => {
                         let tcp = *self.connection.tcp.as_ref().unwrap();
-                        self.network.send(tcp, ClientMessage::Login(character));
+                        self.network.send(tcp, ClientMessage::Login(character, password));
                     },
                     ApiCall::Logout => {
                         self.logout()
                     },
+                    ApiCall::JoinRoom(name) => {
+                        let tcp = *self.connection.tcp.as_ref().unwrap();
+                        self.network.send(tcp, ClientMessage::JoinRoom(name));
+                    },
+                    ApiCall::CreateRoom(name, settings) => {
+                        let tcp = *self.connection.tcp.as_ref().unwrap();
+                        self.network.send(tcp, ClientMessage::CreateRoom(name, settings));
+                    },
+                    ApiCall::JoinQueue(character, preferences) => {
+                        if !self.has_capability(Capability::Matchmaking) {
+                            let status = QueueStatus::Unavailable;
+                            return (self.event_callback)(ServerEvent::QueueStatus(status))
+                        }
+                        let tcp = *self.connection.tcp.as_ref().unwrap();
+                        self.network.send(tcp, ClientMessage::JoinQueue(character, preferences));
+                    },
+                    ApiCall::LeaveQueue => {
+                        let tcp = *self.connection.tcp.as_ref().unwrap();
+                        self.network.send(tcp, ClientMessage::LeaveQueue);
+                    },
+                    ApiCall::ChooseTeam(team) => {
+                        let tcp = *self.connection.tcp.as_ref().unwrap();
+                        self.network.send(tcp, ClientMessage::ChooseTeam(team));
+                    },
                     ApiCall::MovePlayer(direction) => {
                         let tcp = *self.connection.tcp.as_ref().unwrap();
                         self.network.send(tcp, ClientMessage::MovePlayer(direction));
@@ -248,76 +459,144 @@ where C: Fn(ServerEvent) {
                 }
             },
             Event::Network(net_event) => match net_event {
-                NetEvent::Message(_, message) => match message {
-                    ServerMessage::Version(server_version, server_side_compatibility) => {
-                        self.process_version(server_version, server_side_compatibility);
-                    },
-                    ServerMessage::StaticServerInfo(info) => {
-                        self.process_static_server_info(info);
-                    },
-                    ServerMessage::DynamicServerInfo(players) => {
-                        (self.event_callback)(ServerEvent::DynamicServerInfo(players));
-                    },
-                    ServerMessage::LoginStatus(character, status) => {
-                        self.process_login_status(character, status);
-                    },
-                    ServerMessage::UdpConnected => {
-                        self.process_udp_connected();
-                    },
-                    ServerMessage::StartGame(game_info) => {
-                        (self.event_callback)(ServerEvent::StartGame(game_info));
-                    },
-                    ServerMessage::FinishGame => {
-                        self.process_finish_game();
-                    },
-                    ServerMessage::WaitArena(duration) => {
-                        (self.event_callback)(ServerEvent::WaitArena(duration));
-                    },
-                    ServerMessage::StartArena(arena_info) => {
-                        (self.event_callback)(ServerEvent::StartArena(arena_info));
-                    },
-                    ServerMessage::GameEvent(game_event) => {
-                        (self.event_callback)(ServerEvent::GameEvent(game_event));
-                    },
-                    ServerMessage::GameStep(frame) => {
-                        (self.event_callback)(ServerEvent::GameStep(frame));
-                    },
+                NetEvent::Message(endpoint, message) => {
+                    self.last_server_activity = Instant::now();
+                    match message {
+                        ServerMessage::Version(server_version, server_side_compatibility) => {
+                            self.process_version(server_version, server_side_compatibility);
+                        },
+                        ServerMessage::StaticServerInfo(info) => {
+                            self.process_static_server_info(info);
+                        },
+                        ServerMessage::DynamicServerInfo(players) => {
+                            (self.event_callback)(ServerEvent::DynamicServerInfo(players));
+                        },
+                        ServerMessage::LoginStatus(character, status) => {
+                            self.process_login_status(character, status);
+                        },
+                        ServerMessage::UdpConnected => {
+                            self.process_udp_connected();
+                        },
+                        ServerMessage::Ping(id) => {
+                            self.network.send(endpoint, ClientMessage::Pong(id));
+                        },
+                        ServerMessage::Pong(id) => {
+                            self.process_pong(endpoint, id);
+                        },
+                        ServerMessage::StartGame(game_info) => {
+                            (self.event_callback)(ServerEvent::StartGame(game_info));
+                        },
+                        ServerMessage::FinishGame => {
+                            self.process_finish_game();
+                        },
+                        ServerMessage::WaitArena(duration) => {
+                            (self.event_callback)(ServerEvent::WaitArena(duration));
+                        },
+                        ServerMessage::StartArena(arena_info) => {
+                            (self.event_callback)(ServerEvent::StartArena(arena_info));
+                        },
+                        ServerMessage::GameEvent(game_event) => {
+                            (self.event_callback)(ServerEvent::GameEvent(game_event));
+                        },
+                        ServerMessage::GameStep(frame) => {
+                            (self.event_callback)(ServerEvent::GameStep(frame));
+                        },
+                        ServerMessage::DiscoveryInfo(discovery_info) => {
+                            self.process_discovery_info(endpoint, discovery_info);
+                        },
+                        ServerMessage::RegisteredServers(servers) => {
+                            self.process_registered_servers(servers);
+                        },
+                        ServerMessage::Broadcast(message) => {
+                            log::info!("Message from the server: {}", message);
+                            (self.event_callback)(ServerEvent::Broadcast(message));
+                        },
+                        ServerMessage::Shutdown { reason, restart_in } => {
+                            self.process_shutdown(reason, restart_in);
+                        },
+                        ServerMessage::RoomList(rooms) => {
+                            (self.event_callback)(ServerEvent::RoomList(rooms));
+                        },
+                        ServerMessage::RoomStatus(room, status) => {
+                            log::info!("Room '{}' status: {:?}", room, status);
+                            (self.event_callback)(ServerEvent::RoomStatus(room, status));
+                        },
+                        ServerMessage::QueueStatus(status) => {
+                            log::info!("Queue status: {:?}", status);
+                            (self.event_callback)(ServerEvent::QueueStatus(status));
+                        },
+                        ServerMessage::TeamChoices(choices) => {
+                            (self.event_callback)(ServerEvent::TeamChoices(choices));
+                        },
+                    }
                 },
                 NetEvent::AddedEndpoint(_) => unreachable!(),
                 NetEvent::RemovedEndpoint(_) => {
-                    let result = ConnectionStatus::Lost;
-                    (self.event_callback)(ServerEvent::ConnectionResult(result));
+                    log::warn!("Connection lost with the server");
+                    self.process_connection_lost();
                 },
                 NetEvent::DeserializationError(endpoint) => {
-                    log::error!(
-                        "Server sends an unknown message. Connection rejected. \
-                        Ensure the version compatibility.",
-                    );
-                    self.network.remove_resource(endpoint.resource_id()).unwrap();
+                    if Some(endpoint) == self.connection.tcp {
+                        log::error!(
+                            "Server sends an unknown message. Connection rejected. \
+                            Ensure the version compatibility.",
+                        );
+                        self.network.remove_resource(endpoint.resource_id()).unwrap();
+                    }
+                    else {
+                        log::warn!("Unknown message from {} by udp. Ignored", endpoint.addr());
+                    }
                 }
             },
             Event::HelloUdp(attempt) => {
                 self.process_hello_udp(attempt);
             },
+            Event::Ping => {
+                self.process_ping();
+            },
+            Event::Reconnect(attempt) => {
+                self.process_reconnect(attempt);
+            },
+            Event::Discover => {
+                self.process_discover();
+            },
         }
     }
 
-    fn process_version(&mut self, server_version: String, server_side_compatibility: Compatibility) {
-        let client_side_compatibility = version::check(version::current(), &server_version);
+    fn process_version(
+        &mut self,
+        server_version: ProtocolVersion,
+        server_side_compatibility: Compatibility,
+    ) {
+        let client_version = ProtocolVersion::current();
+        let client_side_compatibility = version::check(&client_version, &server_version);
         let compatibility = std::cmp::min(client_side_compatibility, server_side_compatibility);
         match compatibility {
             Compatibility::Fully => {
-                log::info!("Fully compatible versions {}", version::current());
+                log::info!("Fully compatible versions {}", client_version.tag);
             }
             Compatibility::NotExact => {
-                log::warn!("Compatible server version, but not exact. Client: {}. Server: {}", version::current(), server_version);
+                log::warn!(
+                    "Compatible server version, but not exact. \
+                    Client: {} (protocol {}). Server: {} (protocol {})",
+                    client_version.tag, client_version.protocol,
+                    server_version.tag, server_version.protocol,
+                );
             }
             Compatibility::None => {
-                log::error!("Incompatible server version. Client: {}. Server: {}", version::current(), server_version);
+                log::error!(
+                    "Incompatible server version. Client: {} (protocol {}). Server: {} (protocol {})",
+                    client_version.tag, client_version.protocol,
+                    server_version.tag, server_version.protocol,
+                );
             }
         }
 
-        (self.event_callback)(ServerEvent::CheckedVersion(server_version, compatibility));
+        let capabilities = client_version.capabilities.intersection(server_version.capabilities);
+        log::info!("Capabilities in use: {:?}", capabilities);
+        self.capabilities = Some(capabilities);
+
+        (self.event_callback)(ServerEvent::CheckedVersion(server_version.tag, compatibility));
     }
 
     fn process_static_server_info(&mut self, info: ServerInfo) {
@@ -327,22 +606,35 @@ where C: Fn(ServerEvent) {
 
     fn process_login_status(&mut self, character: char, status: LoginStatus) {
         match status {
+            LoginStatus::AlreadyLogged if self.reconnection.is_some() => {
+                // The server has not detected yet that the previous connection was lost.
+                log::warn!("Character name '{}' still logged by the lost connection", character);
+                self.disconnect();
+                if !self.schedule_reconnection() {
+                    self.reconnection = None;
+                    (self.event_callback)(ServerEvent::ConnectionResult(ConnectionStatus::Lost));
+                }
+                return
+            },
             LoginStatus::Logged(token, kind) => {
+                self.reconnection = None;
                 let kind_str = match kind {
                     LoggedKind::FirstTime => "Logged",
                     LoggedKind::Reconnection => "Reconnected",
                 };
                 log::info!(
-                    "{} with name '{}' successful. Token Id: {}",
+                    "{} with name '{}' successful. Token: {:?}",
                     kind_str, character, token
                 );
 
-                let udp_port = *self.connection.udp_port.as_ref().unwrap();
-                let ip = *self.connection.ip.as_ref().unwrap();
                 self.connection.session_token = Some(token);
-                self.connection.udp = Some(self.network.connect_udp((ip, udp_port)).unwrap());
-                log::info!("Connection by udp on port {}", udp_port);
-                self.event_sender.send(Event::HelloUdp(0));
+                if self.has_capability(Capability::UdpFrames) {
+                    self.connect_udp();
+                }
+                else {
+                    log::warn!("The server does not support frames by udp. Using only tcp");
+                    (self.event_callback)(ServerEvent::UdpReachable(false));
+                }
             },
             LoginStatus::InvalidPlayerName => {
                 log::warn!("Invalid character name {}", character);
@@ -353,10 +645,46 @@ where C: Fn(ServerEvent) {
             LoginStatus::PlayerLimit => {
                 log::error!("Server full");
             },
+            LoginStatus::WrongPassword => {
+                log::warn!("Wrong password for the character '{}'", character);
+            },
+            LoginStatus::Banned(remaining) => {
+                self.reconnection = None; // A lost session can not be resumed
+                match remaining {
+                    Some(remaining) => log::warn!(
+                        "The character '{}' is banned from the server for {} seconds",
+                        character,
+                        remaining.as_secs()
+                    ),
+                    None => log::warn!("The character '{}' is banned from the server", character),
+                }
+            },
         }
         (self.event_callback)(ServerEvent::LoginStatus(status));
     }
 
+    fn connect_udp(&mut self) {
+        let udp_port = *self.connection.udp_port.as_ref().unwrap();
+        let ip = *self.connection.ip.as_ref().unwrap();
+        let udp_addr = SocketAddr::new(ip, udp_port);
+        let connection = match util::udp_connectable_addr(udp_addr) {
+            Some(udp_addr) => self.network.connect_udp(udp_addr).map_err(|error| error.to_string()),
+            None => Err("udp by ipv6 is not supported".into()),
+        };
+
+        match connection {
+            Ok(udp_endpoint) => {
+                log::info!("Connection by udp on {}", udp_addr);
+                self.connection.udp = Some(udp_endpoint);
+                self.event_sender.send(Event::HelloUdp(0));
+            }
+            Err(error) => {
+                log::warn!("Can not connect by udp on {}: {}. Using only tcp", udp_addr, error);
+                (self.event_callback)(ServerEvent::UdpReachable(false));
+            }
+        }
+    }
+
     fn process_hello_udp(&mut self, attempt: usize) {
         if !self.connection.has_udp_hasdshake {
             match self.connection.session_token {
@@ -389,6 +717,319 @@ where C: Fn(ServerEvent) {
         (self.event_callback)(ServerEvent::UdpReachable(true));
     }
 
+    fn has_capability(&self, capability: Capability) -> bool {
+        self.capabilities.is_some_and(|capabilities| capabilities.contains(capability))
+    }
+
+    fn process_ping(&mut self) {
+        // Without latency messages, the server sends no heartbeats to detect the timeout.
+        let has_heartbeats = self.capabilities
+            .is_none_or(|capabilities| capabilities.contains(Capability::Latency));
+
+        match self.connection.tcp {
+            Some(_) if has_heartbeats
+                && self.last_server_activity.elapsed() > self.server_timeout => {
+                log::error!(
+                    "Server has not sent any message in {} seconds. Connection lost",
+                    self.server_timeout.as_secs_f32()
+                );
+                self.is_pinging = false;
+                self.process_connection_lost();
+            }
+            Some(tcp) if self.has_capability(Capability::Latency) => {
+                let now = Instant::now();
+                let id = self.tcp_ping.next_ping(now);
+                self.network.send(tcp, ClientMessage::Ping(id));
+
+                if self.connection.has_udp_hasdshake {
+                    if let Some(udp) = self.connection.udp {
+                        let id = self.udp_ping.next_ping(now);
+                        self.network.send(udp, ClientMessage::Ping(id));
+                    }
+                }
+
+                let network_stats = NetworkStats {
+                    tcp_ping: self.tcp_ping.rtt,
+                    udp_ping: self.udp_ping.rtt,
+                    jitter: self.udp_ping.jitter.or(self.tcp_ping.jitter),
+                    udp_loss: self.udp_ping.loss(),
+                };
+                (self.event_callback)(ServerEvent::NetworkStats(network_stats));
+
+                self.event_sender.send_with_timer(Event::Ping, PING_INTERVAL);
+            }
+            Some(_) => {
+                // Still checking the timeout until the capabilities are negotiated.
+                self.event_sender.send_with_timer(Event::Ping, PING_INTERVAL);
+            }
+            None => self.is_pinging = false,
+        }
+    }
+
+    fn process_shutdown(&mut self, reason: String, restart_in: Option<Duration>) {
+        match restart_in {
+            Some(restart_in) => log::warn!(
+                "Server shutdown, restarting in {} seconds: {}",
+                restart_in.as_secs(),
+                reason
+            ),
+            None => log::warn!("Server shutdown: {}", reason),
+        }
+
+        let addr = self.addr;
+        self.disconnect();
+        (self.event_callback)(ServerEvent::Shutdown(reason));
+
+        // The session is lost with the server, but the character logs again after reconnecting.
+        self.reconnection = match (restart_in, addr) {
+            (Some(_), Some(addr)) if self.reconnection_attempts > 0 => {
+                Some(Reconnection { addr, attempt: 0 })
+            }
+            _ => None,
+        };
+
+        match restart_in {
+            Some(restart_in) if self.reconnection.is_some() => {
+                self.schedule_reconnection_attempt(restart_in);
+            }
+            _ => {
+                let status = ConnectionStatus::NotConnected;
+                (self.event_callback)(ServerEvent::ConnectionResult(status));
+            }
+        }
+    }
+
+    fn process_connection_lost(&mut self) {
+        let was_logged = self.connection.session_token.is_some();
+        let addr = self.addr;
+        self.disconnect();
+
+        if self.reconnection.is_none() && was_logged {
+            self.reconnection = addr.map(|addr| Reconnection { addr, attempt: 0 });
+        }
+
+        if !self.schedule_reconnection() {
+            self.reconnection = None;
+            (self.event_callback)(ServerEvent::ConnectionResult(ConnectionStatus::Lost));
+        }
+    }
+
+    /// Schedules the next reconnection attempt with an exponential backoff.
+    /// Returns false if there is no reconnection in progress or there are no more attempts.
+    fn schedule_reconnection(&mut self) -> bool {
+        match self.reconnection.as_ref() {
+            Some(reconnection) if reconnection.attempt < self.reconnection_attempts => {
+                let delay = RECONNECTION_BASE_DELAY
+                    .checked_mul(1 << reconnection.attempt.min(16))
+                    .map_or(RECONNECTION_MAX_DELAY, |delay| delay.min(RECONNECTION_MAX_DELAY));
+
+                self.schedule_reconnection_attempt(delay);
+                true
+            }
+            Some(_) => {
+                log::error!("Unable to reconnect to the server");
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Schedules the next attempt of the reconnection in progress after `delay`.
+    fn schedule_reconnection_attempt(&mut self, delay: Duration) {
+        let reconnection = self.reconnection.as_mut().unwrap();
+        reconnection.attempt += 1;
+        let attempt = reconnection.attempt;
+        log::info!(
+            "Reconnection attempt {} to {} in {} seconds",
+            attempt, reconnection.addr, delay.as_secs_f32()
+        );
+        self.event_sender.send_with_timer(Event::Reconnect(attempt), delay);
+        (self.event_callback)(ServerEvent::Reconnecting(attempt, delay));
+    }
+
+    fn process_reconnect(&mut self, attempt: usize) {
+        let addr = match &self.reconnection {
+            // Discard attempts of a cancelled or restarted reconnection
+            Some(reconnection) if reconnection.attempt == attempt => reconnection.addr,
+            _ => return,
+        };
+
+        match self.connect(addr) {
+            ConnectionStatus::Connected => {
+                (self.event_callback)(ServerEvent::ConnectionResult(ConnectionStatus::Connected));
+            }
+            _ => if !self.schedule_reconnection() {
+                self.reconnection = None;
+                (self.event_callback)(ServerEvent::ConnectionResult(ConnectionStatus::Lost));
+            }
+        }
+    }
+
+    fn process_discover(&mut self) {
+        // The listed servers are only needed to choose one to connect.
+        if self.connection.tcp.is_none() {
+            if self.discovery.is_none() {
+                self.discovery = self.create_discovery();
+            }
+
+            if let Some((multicast_endpoint, listening_port)) = self.discovery {
+                self.network.send(multicast_endpoint, ClientMessage::DiscoveryProbe(listening_port));
+            }
+
+            if let Some(registry_addr) = self.registry_addr {
+                if self.registry.is_none() {
+                    let connection = match util::udp_connectable_addr(registry_addr) {
+                        Some(udp_addr) => self.network.connect_udp(udp_addr).map_err(|error| error.to_string()),
+                        None => Err("udp by ipv6 is not supported".into()),
+                    };
+
+                    match connection {
+                        Ok(endpoint) => {
+                            log::info!("Querying servers to the registry {}", registry_addr);
+                            self.registry = Some(endpoint);
+                        }
+                        Err(error) => {
+                            log::error!("Can not use the registry {}: {}", registry_addr, error);
+                            self.registry_addr = None;
+                        }
+                    }
+                }
+                if let Some(registry) = self.registry {
+                    self.network.send(registry, ClientMessage::QueryRegistry);
+                }
+            }
+
+            let now = Instant::now();
+            let previous_len = self.listed_servers.len();
+            let network = &mut self.network;
+            self.listed_servers.retain(|_, entry| {
+                let alive = now.duration_since(entry.last_seen) < DISCOVERY_EXPIRATION;
+                if let (false, Some(ping_endpoint)) = (alive, entry.ping_endpoint) {
+                    network.remove_resource(ping_endpoint.resource_id());
+                }
+                alive
+            });
+
+            if self.listed_servers.len() != previous_len {
+                self.notify_listed_servers();
+            }
+
+            self.ping_listed_servers(now);
+        }
+
+        self.event_sender.send_with_timer(Event::Discover, DISCOVERY_INTERVAL);
+    }
+
+    fn create_discovery(&mut self) -> Option<(Endpoint, u16)> {
+        let listening_port = match self.network.listen_udp("0.0.0.0:0") {
+            Ok((_, addr)) => addr.port(),
+            Err(error) => {
+                log::warn!("LAN discovery not available: {}", error);
+                return None
+            }
+        };
+
+        match self.network.connect_udp(DISCOVERY_ADDR) {
+            Ok(multicast_endpoint) => {
+                log::info!("Discovering LAN servers from port {}", listening_port);
+                Some((multicast_endpoint, listening_port))
+            }
+            Err(error) => {
+                log::warn!("LAN discovery not available: {}", error);
+                None
+            }
+        }
+    }
+
+    fn process_discovery_info(&mut self, endpoint: Endpoint, discovery_info: DiscoveryInfo) {
+        let addr = SocketAddr::new(endpoint.addr().ip(), discovery_info.tcp_port);
+        log::trace!("LAN server found at {}", addr);
+        self.update_listed_server(addr, ServerSource::Lan, discovery_info);
+        self.notify_listed_servers();
+    }
+
+    fn process_registered_servers(&mut self, servers: Vec<(SocketAddr, DiscoveryInfo)>) {
+        log::trace!("The registry lists {} servers", servers.len());
+        for (addr, discovery_info) in servers {
+            self.update_listed_server(addr, ServerSource::Registry, discovery_info);
+        }
+        self.notify_listed_servers();
+    }
+
+    fn update_listed_server(&mut self, addr: SocketAddr, source: ServerSource, discovery_info: DiscoveryInfo) {
+        let compatibility = version::check(&ProtocolVersion::current(), &discovery_info.version);
+        let capabilities = discovery_info.version.capabilities;
+        let entry = self.listed_servers.entry(addr).or_insert_with(|| ListedServerEntry {
+            server: ListedServer {
+                addr,
+                source,
+                version: String::new(),
+                compatibility,
+                ping: None,
+                info: discovery_info.info.clone(),
+            },
+            capabilities,
+            last_seen: Instant::now(),
+            ping_endpoint: None,
+            ping: PingTracker::default(),
+        });
+
+        // A server found in the LAN keeps the LAN as source although the registry also lists it.
+        entry.server.version = discovery_info.version.tag;
+        entry.server.compatibility = compatibility;
+        entry.server.info = discovery_info.info;
+        entry.capabilities = capabilities;
+        entry.last_seen = Instant::now();
+    }
+
+    fn ping_listed_servers(&mut self, now: Instant) {
+        for entry in self.listed_servers.values_mut() {
+            if entry.server.compatibility == Compatibility::None
+            || !entry.capabilities.contains(Capability::Latency) {
+                continue
+            }
+
+            if entry.ping_endpoint.is_none() {
+                let udp_addr = SocketAddr::new(entry.server.addr.ip(), entry.server.info.udp_port);
+                let network = &mut self.network;
+                match util::udp_connectable_addr(udp_addr).map(|addr| network.connect_udp(addr)) {
+                    Some(Ok(endpoint)) => entry.ping_endpoint = Some(endpoint),
+                    _ => continue,
+                }
+            }
+
+            let id = entry.ping.next_ping(now);
+            self.network.send(entry.ping_endpoint.unwrap(), ClientMessage::Ping(id));
+        }
+    }
+
+    fn notify_listed_servers(&mut self) {
+        let listed_servers = self.listed_servers
+            .values()
+            .map(|entry| entry.server.clone())
+            .sorted_by_key(|listed_server| listed_server.addr)
+            .collect();
+
+        (self.event_callback)(ServerEvent::ServerList(listed_servers));
+    }
+
+    fn process_pong(&mut self, endpoint: Endpoint, id: PingId) {
+        let now = Instant::now();
+        if Some(endpoint) == self.connection.tcp {
+            self.tcp_ping.pong(id, now);
+        }
+        else if Some(endpoint) == self.connection.udp {
+            self.udp_ping.pong(id, now);
+        }
+        else if let Some(entry) = self.listed_servers
+            .values_mut()
+            .find(|entry| entry.ping_endpoint == Some(endpoint)) {
+            entry.ping.pong(id, now);
+            entry.server.ping = entry.ping.rtt;
+            self.notify_listed_servers();
+        }
+    }
+
     fn process_finish_game(&mut self) {
         self.connection.has_udp_hasdshake = false;
         (self.event_callback)(ServerEvent::FinishGame);
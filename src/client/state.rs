@@ -1,19 +1,22 @@
-use super::server_proxy::{ConnectionStatus};
+use super::server_proxy::{ConnectionStatus, NetworkStats, ListedServer};
 use super::configuration::{Config};
+use super::interpolation::{InterpolationBuffer};
 
 use crate::version::{Compatibility};
-use crate::message::{LoginStatus, EntityData, SpellData, Terrain};
+use crate::message::{LoginStatus, EntityData, SpellData, ObjectData, Terrain, RoomInfo, RoomStatus,
+    QueueStatus, GameModeKind};
 use crate::character::{CharacterId, Character};
 use crate::direction::{Direction};
 use crate::vec2::{Vec2};
-use crate::ids::{EntityId, SpellId};
+use crate::ids::{EntityId, SpellId, SpellSpecId, TeamId};
 
 use std::net::{SocketAddr};
-use std::time::{Instant};
-use std::collections::{HashMap};
+use std::time::{Instant, Duration};
+use std::collections::{HashMap, VecDeque};
 
 pub struct User {
     pub character_symbol: Option<char>,
+    pub password: Option<String>,
     pub login_status: Option<LoginStatus>,
 }
 
@@ -24,6 +27,13 @@ impl User {
         }
         false
     }
+
+    pub fn has_wrong_password(&self) -> bool {
+        if let Some(LoginStatus::WrongPassword) = self.login_status {
+            return true
+        }
+        false
+    }
 }
 
 pub struct VersionInfo {
@@ -35,6 +45,8 @@ pub struct StaticGameInfo {
     pub players_number: usize,
     pub map_size: usize,
     pub winner_points: usize,
+    pub mode: GameModeKind,
+    pub teams: usize, // 0 if the mode has no teams
 }
 
 pub struct UserPlayer {
@@ -46,6 +58,7 @@ pub struct Arena {
     pub user_player: UserPlayer,
     pub entities: HashMap<EntityId, EntityData>,
     pub spells: HashMap<SpellId, SpellData>,
+    pub objects: Vec<ObjectData>, // Of the last frame, without interpolation
     pub size: usize,
     pub ground: Vec<Terrain>,
 }
@@ -70,6 +83,8 @@ pub struct Player {
     pub character_id: CharacterId,
     pub entity_id: EntityId,
     pub points: usize,
+    pub ping: Option<Duration>,
+    pub team: TeamId,
 }
 
 pub struct Game {
@@ -79,6 +94,10 @@ pub struct Game {
     pub arena: Option<Arena>,
     pub characters: HashMap<CharacterId, Character>,
     pub players: Vec<Player>,
+    pub entity_players: HashMap<EntityId, usize>, // All the player entities of the arena, also the dead ones
+    pub interpolation: InterpolationBuffer,
+    pub frame_timestamps: VecDeque<Instant>, // Of the frames received in the last second
+    pub kill_feed: VecDeque<Kill>, // The newest last
 }
 
 impl Game {
@@ -89,16 +108,100 @@ impl Game {
     pub fn arena_mut(&mut self) -> &mut Arena {
         self.arena.as_mut().unwrap()
     }
+
+    pub fn add_frame_timestamp(&mut self, timestamp: Instant) {
+        self.frame_timestamps.push_back(timestamp);
+        while let Some(&oldest) = self.frame_timestamps.front() {
+            match timestamp.duration_since(oldest) > Duration::from_secs(1) {
+                true => self.frame_timestamps.pop_front(),
+                false => break,
+            };
+        }
+    }
+
+    /// Player that owns or owned the entity in the current arena.
+    pub fn player_by_entity(&self, entity_id: EntityId) -> Option<&Player> {
+        self.entity_players.get(&entity_id).map(|&index| &self.players[index])
+    }
+
+    pub fn add_kill(&mut self, kill: Kill) {
+        self.kill_feed.push_back(kill);
+        if self.kill_feed.len() > KILL_FEED_SIZE {
+            self.kill_feed.pop_front();
+        }
+    }
+
+    /// Kills that should be shown.
+    pub fn current_kills(&self) -> impl Iterator<Item = &Kill> {
+        self.kill_feed
+            .iter()
+            .filter(|kill| kill.timestamp.elapsed() < KILL_FEED_DURATION)
+    }
+
+    pub fn frame_rate(&self, now: Instant) -> usize {
+        self.frame_timestamps
+            .iter()
+            .filter(|&&timestamp| now.duration_since(timestamp) <= Duration::from_secs(1))
+            .count()
+    }
+}
+
+/// Time while a kill is shown in the kill feed.
+pub const KILL_FEED_DURATION: Duration = Duration::from_secs(5);
+
+/// Kills kept in the kill feed.
+pub const KILL_FEED_SIZE: usize = 2;
+
+pub struct Kill {
+    pub victim: usize, // Player index
+    pub killer: Option<usize>, // Player index
+    pub spell: SpellSpecId,
+    pub timestamp: Instant,
+}
+
+/// Time while a message broadcast by the server admin is shown.
+pub const BROADCAST_DURATION: Duration = Duration::from_secs(10);
+
+pub struct Broadcast {
+    pub message: String,
+    pub timestamp: Instant,
+}
+
+pub struct Reconnection {
+    pub attempt: usize,
+    pub next_attempt_timestamp: Instant,
+}
+
+/// Matchmaking request of the user.
+pub struct Queue {
+    pub character: char, // Used to login once matched
+    pub status: Option<QueueStatus>, // None until the server answers
+}
+
+impl Queue {
+    pub fn is_waiting(&self) -> bool {
+        matches!(self.status, None | Some(QueueStatus::Queued { .. }))
+    }
 }
 
 pub struct Server {
     pub addr: Option<SocketAddr>,
     pub connection_status: ConnectionStatus,
+    pub reconnection: Option<Reconnection>,
+    pub broadcast: Option<Broadcast>,
+    pub shutdown_reason: Option<String>, // Of the last shutdown announced by the server
     pub udp_port: Option<u16>,
     pub udp_confirmed: Option<bool>,
+    pub network_stats: NetworkStats,
     pub version_info: Option<VersionInfo>,
     pub game_info: Option<StaticGameInfo>,
+    pub password_required: bool,
     pub logged_players: Vec<char>,
+    pub rooms: Vec<RoomInfo>, // Empty if the server has no rooms. The main room first
+    pub room: Option<String>, // Joined room, None for the main room
+    pub room_status: Option<RoomStatus>, // Of the last room request
+    pub queue: Option<Queue>,
+    pub team_choices: HashMap<char, TeamId>, // Of the logged players of the room
     pub game: Game,
 }
 
@@ -119,6 +222,26 @@ impl Server {
         }
     }
 
+    /// Message of the server admin, while it should be shown.
+    pub fn current_broadcast(&self) -> Option<&str> {
+        self.broadcast
+            .as_ref()
+            .filter(|broadcast| broadcast.timestamp.elapsed() < BROADCAST_DURATION)
+            .map(|broadcast| broadcast.message.as_str())
+    }
+
+    /// Name of the room where the user logs, if the server has rooms.
+    pub fn room_name(&self) -> Option<&str> {
+        self.room
+            .as_deref()
+            .or_else(|| self.rooms.first().map(|room| room.name.as_str()))
+    }
+
+    /// Team chosen by a logged player, `TeamId::NONE` if the server chooses it.
+    pub fn team_choice(&self, character: char) -> TeamId {
+        self.team_choices.get(&character).copied().unwrap_or(TeamId::NONE)
+    }
+
     pub fn game_info(&self) -> &StaticGameInfo {
         self.game_info.as_ref().unwrap()
     }
@@ -134,6 +257,7 @@ impl Server {
 pub struct State {
     pub user: User,
     pub server: Server,
+    pub listed_servers: Vec<ListedServer>,
 }
 
 impl State {
@@ -141,25 +265,41 @@ impl State {
         State {
             user: User {
                 character_symbol: config.character,
+                password: config.password.clone(),
                 login_status: None,
             },
             server: Server {
                 addr: config.server_addr,
                 connection_status: ConnectionStatus::NotConnected,
+                reconnection: None,
+                broadcast: None,
+                shutdown_reason: None,
                 udp_port: None,
                 udp_confirmed: None,
+                network_stats: NetworkStats::default(),
                 version_info: None,
                 game_info: None,
+                password_required: false,
                 logged_players: Vec::new(),
+                rooms: Vec::new(),
+                room: None,
+                room_status: None,
+                queue: None,
+                team_choices: HashMap::new(),
                 game: Game {
                     status: GameStatus::NotStarted,
                     arena_number: 0,
                     next_arena_timestamp: None,
                     arena: None,
                     characters: HashMap::new(),
-                    players: Vec::new()
+                    players: Vec::new(),
+                    entity_players: HashMap::new(),
+                    interpolation: InterpolationBuffer::new(config.interpolation_delay),
+                    frame_timestamps: VecDeque::new(),
+                    kill_feed: VecDeque::new(),
                 },
             },
+            listed_servers: Vec::new(),
         }
     }
 }
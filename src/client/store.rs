@@ -1,12 +1,12 @@
 use super::state::{State, StaticGameInfo, VersionInfo, GameStatus, Arena,
-    Player, UserPlayer};
+    Player, UserPlayer, Reconnection, Broadcast, Queue, Kill};
 use super::server_proxy::{ServerApi, ApiCall, ConnectionStatus, ServerEvent};
 
-use crate::message::{GameEvent};
+use crate::message::{GameEvent, LoginStatus, LoggedKind, RoomSettings, RoomStatus,
+    QueuePreferences, QueueStatus};
 use crate::character::{CharacterId};
 use crate::direction::{Direction};
-use crate::ids::{EntityId, SkillId};
-use crate::version::{self};
+use crate::ids::{EntityId, SkillId, TeamId};
 
 use std::net::{SocketAddr};
 use std::time::{Instant};
@@ -19,6 +19,12 @@ pub enum Action {
     Connect(SocketAddr),
     Disconnect,
     Login(char),
+    SetPassword(String),
+    JoinRoom(String),
+    CreateRoom(String, RoomSettings),
+    JoinQueue(char, QueuePreferences),
+    LeaveQueue,
+    ChooseTeam(TeamId),
     Logout,
     CloseGame,
     CloseApp,
@@ -70,8 +76,50 @@ impl Store {
             }
 
             Action::Login(character) => {
+                self.state.server.queue = None;
                 self.state.user.character_symbol = Some(character);
-                self.server.call(ApiCall::Login(character));
+                self.server.call(ApiCall::Login(character, self.state.user.password.clone()));
+            },
+
+            Action::SetPassword(password) => {
+                self.state.user.password = Some(password);
+                if self.state.user.has_wrong_password() {
+                    self.state.user.login_status = None;
+                }
+                if let Some(character) = self.state.user.character_symbol {
+                    if !self.state.user.is_logged() {
+                        self.server.call(ApiCall::Login(character, self.state.user.password.clone()));
+                    }
+                }
+            },
+
+            Action::JoinRoom(name) => {
+                self.state.server.queue = None;
+                self.state.server.room_status = None;
+                self.server.call(ApiCall::JoinRoom(name));
+            },
+
+            Action::CreateRoom(name, settings) => {
+                self.state.server.queue = None;
+                self.state.server.room_status = None;
+                self.server.call(ApiCall::CreateRoom(name, settings));
+            },
+
+            Action::JoinQueue(character, preferences) => {
+                self.state.server.queue = Some(Queue { character, status: None });
+                self.server.call(ApiCall::JoinQueue(character, preferences));
+            },
+
+            Action::LeaveQueue => {
+                match self.state.server.queue.as_ref().map(|queue| queue.is_waiting()) {
+                    Some(true) => self.server.call(ApiCall::LeaveQueue),
+                    Some(false) => self.state.server.queue = None,
+                    None => (),
+                }
+            },
+
+            Action::ChooseTeam(team) => {
+                self.server.call(ApiCall::ChooseTeam(team));
             },
 
             Action::Logout => {
@@ -90,11 +138,17 @@ impl Store {
             }
 
             Action::MovePlayer(direction) => {
+                if self.state.server.reconnection.is_some() {
+                    return
+                }
                 self.state.server.game.arena_mut().user_player.direction = direction;
                 self.server.call(ApiCall::MovePlayer(direction));
             }
 
             Action::CastSkill(id) => {
+                if self.state.server.reconnection.is_some() {
+                    return
+                }
                 let direction = self.state.server.game.arena_mut().user_player.direction;
                 self.server.call(ApiCall::CastSkill(direction, id));
             }
@@ -103,9 +157,18 @@ impl Store {
                 ServerEvent::ConnectionResult(status)  => {
                     self.state.server.connection_status = status;
                     if let ConnectionStatus::Connected = status {
-                        self.server.call(ApiCall::CheckVersion(version::current().into()));
+                        self.state.server.shutdown_reason = None;
+                        self.state.server.room = None; // A new connection starts in the main room
+                        self.state.server.room_status = None;
+                        self.state.server.queue = None;
+                        if self.state.user.character_symbol.is_none() {
+                            // Without character, there is no login to finish the reconnection.
+                            self.state.server.reconnection = None;
+                        }
+                        self.server.call(ApiCall::CheckVersion);
                     }
                     else { //No connected (no matter the reason)
+                        self.state.server.reconnection = None;
                         self.state.server.game.status = GameStatus::Finished;
                         self.state.server.udp_confirmed = None;
                         self.state.user.character_symbol = None;
@@ -113,6 +176,12 @@ impl Store {
                         self.state.server.logged_players = Vec::new();
                         self.state.server.game.arena = None;
                         self.state.server.game_info = None;
+                        self.state.server.password_required = false;
+                        self.state.server.rooms = Vec::new();
+                        self.state.server.room = None;
+                        self.state.server.room_status = None;
+                        self.state.server.queue = None;
+                        self.state.server.team_choices = HashMap::new();
                     }
                 },
 
@@ -134,13 +203,17 @@ impl Store {
                         players_number: info.players_number as usize,
                         map_size: info.map_size as usize,
                         winner_points: info.winner_points as usize,
+                        mode: info.mode,
+                        teams: info.teams as usize,
                     };
                     self.state.server.udp_port = Some(info.udp_port);
                     self.state.server.game_info = Some(game_info);
+                    self.state.server.password_required = info.password_required;
                     self.state.server.logged_players = info.logged_players;
+                    self.state.server.team_choices = HashMap::new(); // Sent after it if there are teams
 
                     if let Some(character) = self.state.user.character_symbol {
-                        self.server.call(ApiCall::Login(character));
+                        self.server.call(ApiCall::Login(character, self.state.user.password.clone()));
                     }
                 },
 
@@ -149,6 +222,17 @@ impl Store {
                 },
 
                 ServerEvent::LoginStatus(status) => {
+                    if self.state.server.reconnection.take().is_some() {
+                        match status {
+                            LoginStatus::Logged(_, LoggedKind::Reconnection) => (),
+                            _ => {
+                                // The game of the lost session no longer exists
+                                self.state.server.game.status = GameStatus::NotStarted;
+                                self.state.server.game.next_arena_timestamp = None;
+                                self.state.server.game.arena = None;
+                            }
+                        }
+                    }
                     self.state.user.login_status = Some(status);
                 },
 
@@ -157,20 +241,30 @@ impl Store {
                 },
 
                 ServerEvent::StartGame(game_info) => {
+                    if let Some(static_game_info) = &mut self.state.server.game_info {
+                        static_game_info.map_size = game_info.map_size as usize;
+                        static_game_info.winner_points = game_info.winner_points as usize;
+                    }
+
                     self.state.server.game.status = GameStatus::Started;
+                    self.state.server.game.kill_feed.clear();
                     self.state.server.game.characters = game_info.characters
                         .into_iter()
                         .map(|character| (character.id(), character))
                         .collect();
 
+                    self.state.server.game.entity_players.clear();
                     self.state.server.game.players = game_info.players
                         .into_iter()
+                        .zip(game_info.teams)
                         .enumerate()
-                        .map(|(index, (character_id, points))| Player {
+                        .map(|(index, ((character_id, points), team))| Player {
                             id: index,
                             character_id,
                             entity_id: EntityId::NONE,
                             points,
+                            ping: None,
+                            team,
                         })
                         .collect();
                 },
@@ -182,6 +276,8 @@ impl Store {
                     self.state.user.login_status = None;
                     self.state.server.game.arena_mut().entities = HashMap::new();
                     self.state.server.game.arena_mut().spells = HashMap::new();
+                    self.state.server.game.arena_mut().objects = Vec::new();
+                    self.state.server.game.interpolation.clear();
                 },
 
                 ServerEvent::WaitArena(duration) => {
@@ -192,15 +288,19 @@ impl Store {
 
                 ServerEvent::StartArena(arena_info) => {
                     self.state.server.game.next_arena_timestamp = None;
+                    self.state.server.game.interpolation.clear();
                     self.state.server.game.arena_number = arena_info.number;
 
+                    self.state.server.game.entity_players.clear();
                     for (i, player) in arena_info.players.into_iter().enumerate() {
                         self.state.server.game.players[i].entity_id = player;
+                        self.state.server.game.entity_players.insert(player, i);
                     }
 
                     self.state.server.game.arena = Some(Arena {
                         entities: HashMap::new(),
                         spells: HashMap::new(),
+                        objects: Vec::new(),
                         user_player: UserPlayer {
                             player_id: self.state.server.game.players
                                 .iter()
@@ -219,23 +319,124 @@ impl Store {
                     });
                 },
 
-                ServerEvent::GameEvent(game_event) => {
-                    let GameEvent::PlayerPointsUpdated(player_points) = game_event;
-                    for (i, points) in player_points.into_iter().enumerate() {
-                        self.state.server.game.players[i].points = points;
+                ServerEvent::GameEvent(game_event) => match game_event {
+                    GameEvent::PlayerPointsUpdated(player_points) => {
+                        for (i, points) in player_points.into_iter().enumerate() {
+                            self.state.server.game.players[i].points = points;
+                        }
                     }
-                }
+                    GameEvent::PlayerPingsUpdated(player_pings) => {
+                        for (i, ping) in player_pings.into_iter().enumerate() {
+                            self.state.server.game.players[i].ping = ping;
+                        }
+                    }
+                    GameEvent::WinnerPointsChanged(winner_points) => {
+                        if let Some(static_game_info) = &mut self.state.server.game_info {
+                            static_game_info.winner_points = winner_points;
+                        }
+                    }
+                    GameEvent::PlayerRespawned(index, entity_id) => {
+                        self.state.server.game.players[index].entity_id = entity_id;
+                        self.state.server.game.entity_players.insert(entity_id, index);
+                    }
+                    GameEvent::EntityKilled { victim, killer, spell } => {
+                        let game = &mut self.state.server.game;
+                        if let Some(victim) = game.player_by_entity(victim).map(|player| player.id) {
+                            let killer = match killer {
+                                EntityId::NONE => None,
+                                killer => game.player_by_entity(killer).map(|player| player.id),
+                            };
+                            let timestamp = Instant::now();
+                            game.add_kill(Kill { victim, killer, spell, timestamp });
+                        }
+                    }
+                },
 
                 ServerEvent::GameStep(frame) => {
-                    self.state.server.game.arena_mut().entities = frame.entities
-                        .into_iter()
-                        .map(|entity| (entity.id, entity))
-                        .collect::<HashMap<_, _>>();
+                    // The frames go by udp, so they could arrive before the arena starts.
+                    if let Some(arena) = self.state.server.game.arena.as_mut() {
+                        arena.entities = frame.entities
+                            .into_iter()
+                            .map(|entity| (entity.id, entity))
+                            .collect::<HashMap<_, _>>();
+
+                        arena.spells = frame.spells
+                            .into_iter()
+                            .map(|spell| (spell.id, spell))
+                            .collect::<HashMap<_, _>>();
+
+                        arena.objects = frame.objects;
+
+                        let now = Instant::now();
+                        self.state.server.game.interpolation.push(
+                            now,
+                            &arena.entities,
+                            &arena.spells,
+                        );
+                        self.state.server.game.add_frame_timestamp(now);
+                    }
+                },
 
-                    self.state.server.game.arena_mut().spells = frame.spells
-                        .into_iter()
-                        .map(|spell| (spell.id, spell))
-                        .collect::<HashMap<_, _>>();
+                ServerEvent::NetworkStats(network_stats) => {
+                    self.state.server.network_stats = network_stats;
+                },
+
+                ServerEvent::ServerList(listed_servers) => {
+                    self.state.listed_servers = listed_servers;
+                },
+
+                ServerEvent::Broadcast(message) => {
+                    self.state.server.broadcast = Some(Broadcast {
+                        message,
+                        timestamp: Instant::now(),
+                    });
+                },
+
+                ServerEvent::Shutdown(reason) => {
+                    self.state.server.shutdown_reason = Some(reason);
+                },
+
+                ServerEvent::RoomList(rooms) => {
+                    self.state.server.rooms = rooms;
+                },
+
+                ServerEvent::RoomStatus(name, status) => {
+                    if let RoomStatus::Joined = status {
+                        self.state.server.room = Some(name);
+                    }
+                    self.state.server.room_status = Some(status);
+                },
+
+                ServerEvent::QueueStatus(status) => match status {
+                    QueueStatus::Matched(_) => {
+                        // The login is done with the server info of the match room.
+                        if let Some(queue) = self.state.server.queue.take() {
+                            self.state.user.character_symbol = Some(queue.character);
+                        }
+                    },
+                    QueueStatus::Left => {
+                        self.state.server.queue = None;
+                    },
+                    status => {
+                        if let Some(queue) = &mut self.state.server.queue {
+                            queue.status = Some(status);
+                        }
+                    },
+                },
+
+                ServerEvent::TeamChoices(choices) => {
+                    self.state.server.team_choices = choices.into_iter().collect();
+                },
+
+                ServerEvent::Reconnecting(attempt, delay) => {
+                    // The character and the game are kept to resume them after login again.
+                    self.state.server.connection_status = ConnectionStatus::Lost;
+                    self.state.server.udp_confirmed = None;
+                    self.state.user.login_status = None;
+                    self.state.server.reconnection = Some(Reconnection {
+                        attempt,
+                        next_attempt_timestamp: Instant::now() + delay,
+                    });
                 },
             },
         }
@@ -1,14 +1,16 @@
 use super::util::{self};
 
-use crate::client::state::{State, GameStatus, Player};
+use crate::client::state::{State, GameStatus, Player, Kill};
 use crate::client::store::{Store, Action};
 use crate::client::terminal::input::{InputEvent};
 use crate::client::configuration::{Config};
 
 use crate::direction::{Direction};
 use crate::character::{CharacterId, Character};
-use crate::message::{EntityData};
-use crate::ids::{SkillId, EntityId};
+use crate::message::{EntityData, Terrain, ObjectKind};
+use crate::vec2::{Vec2};
+use crate::specification::spells::{SPELL_SPECIFICATIONS};
+use crate::ids::{SkillId, EntityId, TeamId};
 
 use tui::buffer::{Buffer};
 use tui::widgets::{Paragraph, Block, Borders, BorderType, Widget};
@@ -18,12 +20,15 @@ use tui::text::{Span, Spans};
 
 use crossterm::event::{KeyCode};
 
+use itertools::{Itertools};
+
 use std::time::{Instant, Duration};
-use std::collections::{HashMap};
+use std::collections::{HashMap, HashSet};
 
 pub struct Arena {
     previous_entities: HashMap<EntityId, EntityData>,
     damaged_entities: HashMap<EntityId, Instant>,
+    show_network: bool,
 }
 
 impl Arena {
@@ -31,6 +36,7 @@ impl Arena {
         Arena {
             previous_entities: HashMap::new(),
             damaged_entities: HashMap::new(),
+            show_network: false,
         }
     }
 
@@ -42,6 +48,9 @@ impl Arena {
                         store.dispatch(Action::CloseGame);
                     }
                 }
+                KeyCode::Char('n') => {
+                    self.show_network = !self.show_network;
+                }
                 KeyCode::Char(c) => {
                     if let GameStatus::Started = store.state().server.game.status {
                         match c {
@@ -133,6 +142,11 @@ impl Widget for ArenaWidget<'_> {
         MapWidget::new(self.state, self.arena)
             .render(row[2], buffer);
 
+        if self.arena.show_network {
+            NetworkOverlayWidget::new(self.state)
+                .render(row[2], buffer);
+        }
+
         NotificationLabelWidget::new(self.state)
             .render(column[3], buffer);
     }
@@ -229,7 +243,7 @@ impl Widget for PlayerPanelWidget<'_> {
             .border_type(BorderType::Rounded)
             .render(symbol_area, buffer);
 
-        let player_color = Color::White;
+        let player_color = util::team_color(self.player.team);
         let player_style = match is_user {
              true => Style::default().fg(player_color).add_modifier(Modifier::BOLD),
              false => Style::default().fg(player_color)
@@ -280,6 +294,12 @@ impl Widget for PlayerPanelWidget<'_> {
 
         buffer.set_string(bottom.x, bottom.y, ">", arrow);
         buffer.set_string(bottom.right() - 1, bottom.y, "<", arrow);
+
+        if let Some(ping) = self.player.ping {
+            let ping = format!(" {}ms ", ping.as_millis());
+            let x = bottom.x + bottom.width.saturating_sub(ping.len() as u16) / 2;
+            buffer.set_string(x, bottom.y, ping, Style::default().fg(Color::Gray));
+        }
     }
 }
 
@@ -342,6 +362,41 @@ impl MapWidget<'_> {
 
 impl Widget for MapWidget<'_> {
     fn render(self, area: Rect, buffer: &mut Buffer) {
+        // Hill of the king of the hill mode
+        let arena = self.state.server.game.arena();
+        for y in 0..arena.size {
+            for x in 0..arena.size {
+                if arena.terrain(Vec2::xy(x as i32, y as i32)) == Terrain::Hill {
+                    let style = Style::default().fg(Color::Yellow);
+                    buffer.set_string(area.x + x as u16 * 2, area.y + y as u16, "░", style);
+                }
+            }
+        }
+
+        // Objects: the bases below the flags. The carried flags are shown in their carriers
+        let mut flag_carriers = HashSet::new();
+        let objects = arena.objects
+            .iter()
+            .sorted_by_key(|object| matches!(object.kind, ObjectKind::Flag(_)));
+
+        for object in objects {
+            let x = object.position.x as u16 * 2;
+            let y = object.position.y as u16;
+            match object.kind {
+                ObjectKind::Base(team) => {
+                    let style = Style::default().fg(util::team_color(team));
+                    buffer.set_string(area.x + x, area.y + y, "□", style);
+                },
+                ObjectKind::Flag(_) if object.carrier != EntityId::NONE => {
+                    flag_carriers.insert(object.carrier);
+                },
+                ObjectKind::Flag(team) => {
+                    let style = Style::default().fg(util::team_color(team)).add_modifier(Modifier::BOLD);
+                    buffer.set_string(area.x + x, area.y + y, "⚑", style);
+                },
+            }
+        }
+
         // Player sight
         let user_player = &self.state.server.game.arena().user_player;
         let player = &self.state.server.game.players[user_player.player_id];
@@ -360,8 +415,13 @@ impl Widget for MapWidget<'_> {
             .border_type(BorderType::Rounded)
             .render(area, buffer);
 
+        // The user entity is drawn at its last known position,
+        // the rest are drawn in the past in order to be interpolated.
+        let now = Instant::now();
+        let interpolation = &self.state.server.game.interpolation;
+
         // Spells
-        for (_, spell) in &self.state.server.game.arena().spells {
+        for spell in interpolation.spells(now) {
             let x = spell.position.x as u16 * 2;
             let y = spell.position.y as u16;
             let style = Style::default().fg(Color::Indexed(208)).remove_modifier(Modifier::BOLD);
@@ -369,18 +429,34 @@ impl Widget for MapWidget<'_> {
         }
 
         // Entities
-        for (id, entity) in &self.state.server.game.arena().entities {
+        let user_entity = self.state.server.game.arena().entities.get(&player.entity_id);
+        let entities = interpolation
+            .entities(now)
+            .into_iter()
+            .filter(|entity| entity.id != player.entity_id)
+            .chain(user_entity.cloned());
+
+        for entity in entities {
             let x = entity.position.x as u16 * 2;
             let y = entity.position.y as u16;
             let character = self.state.server.game.characters.get(&entity.character_id).unwrap();
-            let color = match self.arena.damaged_entities.get(id) {
+            let team = self.state.server.game.players
+                .iter()
+                .find(|player| player.character_id == entity.character_id)
+                .map(|player| player.team)
+                .unwrap_or(TeamId::NONE);
+            let color = match self.arena.damaged_entities.get(&entity.id) {
                 Some(_) => Color::LightRed,
-                None => Color::White,
+                None => util::team_color(team),
             };
             let style = match character.id() {
                 CharacterId::Player(_) => Style::default().fg(color).add_modifier(Modifier::BOLD),
                 _ => Style::default().fg(color),
             };
+            let style = match flag_carriers.contains(&entity.id) {
+                true => style.add_modifier(Modifier::REVERSED),
+                false => style,
+            };
             buffer.set_string(area.x + x, area.y + y, &character.symbol().to_string(), style);
         }
 
@@ -389,6 +465,56 @@ impl Widget for MapWidget<'_> {
     }
 }
 
+#[derive(derive_new::new)]
+struct NetworkOverlayWidget<'a> {state: &'a State}
+
+impl NetworkOverlayWidget<'_> {
+    const DIMENSION: (u16, u16) = (16, 4);
+}
+
+impl Widget for NetworkOverlayWidget<'_> {
+    fn render(self, area: Rect, buffer: &mut Buffer) {
+        let stats = &self.state.server.network_stats;
+        let millis = |duration: Option<Duration>| match duration {
+            Some(duration) => format!("{}ms", duration.as_millis()),
+            None => "-".into(),
+        };
+
+        let ping = stats.udp_ping.or(stats.tcp_ping);
+        let loss = match stats.udp_loss {
+            Some(loss) => format!("{:.0}%", loss * 100.0),
+            None => "-".into(),
+        };
+        let frame_rate = self.state.server.game.frame_rate(Instant::now());
+
+        let label_style = Style::default().fg(Color::DarkGray);
+        let value_style = Style::default().fg(Color::Gray).add_modifier(Modifier::BOLD);
+        let lines = vec![
+            ("Ping:   ", millis(ping)),
+            ("Jitter: ", millis(stats.jitter)),
+            ("Frames: ", format!("{}/s", frame_rate)),
+            ("Loss:   ", loss),
+        ]
+        .into_iter()
+        .map(|(label, value)| Spans::from(vec![
+            Span::styled(label, label_style),
+            Span::styled(value, value_style),
+        ]))
+        .collect::<Vec<_>>();
+
+        let overlay = Rect::new(
+            area.right().saturating_sub(Self::DIMENSION.0 + 1),
+            area.y + 1,
+            Self::DIMENSION.0,
+            Self::DIMENSION.1,
+        ).intersection(area);
+
+        Paragraph::new(lines)
+            .alignment(Alignment::Left)
+            .render(overlay, buffer);
+    }
+}
+
 #[derive(derive_new::new)]
 struct FinishGameMessageWidget<'a> {state: &'a State}
 
@@ -435,10 +561,70 @@ struct NotificationLabelWidget<'a> {state: &'a State}
 
 impl NotificationLabelWidget<'_> {
     const HEIGHT: u16 = 2;
+
+    fn kill_message(&self, kill: &Kill) -> Spans<'static> {
+        let game = &self.state.server.game;
+        let player_span = |index: usize| {
+            let player = &game.players[index];
+            let symbol = game.characters[&player.character_id].symbol();
+            let style = Style::default().fg(util::team_color(player.team)).add_modifier(Modifier::BOLD);
+            Span::styled(symbol.to_string(), style)
+        };
+
+        let style = Style::default().fg(Color::Gray);
+        match kill.killer {
+            Some(killer) if killer != kill.victim => {
+                let spell = SPELL_SPECIFICATIONS.get(&kill.spell).map(|spec| spec.name).unwrap_or("?");
+                Spans::from(vec![
+                    player_span(killer),
+                    Span::styled(" killed ", style),
+                    player_span(kill.victim),
+                    Span::styled(format!(" with {}", spell.to_lowercase()), style),
+                ])
+            },
+            _ => Spans::from(vec![player_span(kill.victim), Span::styled(" died", style)]),
+        }
+    }
 }
 
 impl Widget for NotificationLabelWidget<'_> {
     fn render(self, area: Rect, buffer: &mut Buffer) {
+        if let Some(reconnection) = &self.state.server.reconnection {
+            let secs = reconnection.next_attempt_timestamp
+                .saturating_duration_since(Instant::now())
+                .as_secs() + 1;
+
+            let style = Style::default().fg(Color::LightYellow);
+            let cause = match &self.state.server.shutdown_reason {
+                Some(reason) => reason.as_str(),
+                None => "Connection lost",
+            };
+            let messages = vec![
+                Spans::from(vec![Span::styled(cause, style)]),
+                Spans::from(vec![
+                    Span::styled("Reconnecting in ", style),
+                    Span::styled(secs.to_string(), style.add_modifier(Modifier::BOLD)),
+                    Span::styled(format!("... (attempt {})", reconnection.attempt), style),
+                ]),
+            ];
+
+            return Paragraph::new(messages)
+                .alignment(Alignment::Center)
+                .render(area, buffer);
+        }
+
+        if let Some(message) = self.state.server.current_broadcast() {
+            let style = Style::default().fg(Color::LightMagenta);
+            let messages = vec![
+                Spans::from(vec![Span::styled("Message from the server", style)]),
+                Spans::from(vec![Span::styled(message, style.add_modifier(Modifier::BOLD))]),
+            ];
+
+            return Paragraph::new(messages)
+                .alignment(Alignment::Center)
+                .render(area, buffer);
+        }
+
         let messages = match self.state.server.game.next_arena_timestamp {
             Some(timestamp) => {
                 let secs = timestamp.saturating_duration_since(Instant::now()).as_secs() + 1;
@@ -470,7 +656,10 @@ impl Widget for NotificationLabelWidget<'_> {
                     ]),
                 ]
             }
-            None => vec![]
+            None => self.state.server.game
+                .current_kills()
+                .map(|kill| self.kill_message(kill))
+                .collect(),
         };
 
         Paragraph::new(messages)
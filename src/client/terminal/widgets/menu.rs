@@ -3,13 +3,14 @@ use super::waiting_room::{WaitingRoom, WaitingRoomWidget};
 
 use crate::client::configuration::{Config};
 use crate::client::state::{State, VersionInfo, GameStatus};
-use crate::client::server_proxy::{ConnectionStatus};
+use crate::client::server_proxy::{ConnectionStatus, ServerSource};
 use crate::client::store::{Store, Action};
 use crate::client::terminal::input::{InputEvent};
 use crate::client::terminal::renderer::{Cursor};
 
 use crate::version::{self, Compatibility};
-use crate::message::{LoginStatus};
+use crate::message::{LoginStatus, RoomSettings, RoomStatus, QueuePreferences, QueueStatus};
+use crate::ids::{TeamId};
 
 use tui::buffer::{Buffer};
 use tui::widgets::{Block, Borders, BorderType, Paragraph, Widget, StatefulWidget};
@@ -24,8 +25,13 @@ use std::time::{Instant};
 
 pub struct Menu {
     server_addr_input: InputText,
+    password_input: InputText,
+    room_input: InputText,
     character_symbol_input: InputCapitalLetter,
     waiting_room: WaitingRoom,
+    listed_server_index: Option<usize>,
+    listed_room_index: Option<usize>,
+    choosing_room: bool, // Toggled by the user, the room input has the focus instead of the character
 }
 
 impl Menu {
@@ -34,11 +40,16 @@ impl Menu {
             server_addr_input: InputText::new(
                 config.server_addr.map(|addr| addr.to_string())
             ),
+            password_input: InputText::new(config.password.clone()),
+            room_input: InputText::new(None),
             character_symbol_input: InputCapitalLetter::new(config.character),
             waiting_room: WaitingRoom::new(
                 WaitingRoomPanelWidget::WIDTH - 2,
                 ServerInfoPanelWidget::HEIGHT - 2
             ),
+            listed_server_index: None,
+            listed_room_index: None,
+            choosing_room: false,
         }
     }
 
@@ -53,14 +64,57 @@ impl Menu {
                                 store.dispatch(Action::Connect(addr));
                             }
                         }
+                        else if self.password_input.has_focus() {
+                            let password = self.password_input.content().to_string();
+                            store.dispatch(Action::SetPassword(password));
+                        }
+                        else if self.room_input.has_focus() {
+                            let character = self.character_symbol_input.content();
+                            if let Some(action) = Self::room_action(self.room_input.content(), character) {
+                                store.dispatch(action);
+                                self.choosing_room = false;
+                            }
+                        }
                         else if self.character_symbol_input.has_focus() {
                             if let Some(character) = self.character_symbol_input.content() {
                                 store.dispatch(Action::Login(character));
                             }
                         }
                     }
+                    KeyCode::Tab => {
+                        if let Some(team) = Self::next_team(store.state()) {
+                            store.dispatch(Action::ChooseTeam(team));
+                        }
+                        else {
+                            self.choosing_room = !self.choosing_room;
+                        }
+                    }
+                    KeyCode::Up | KeyCode::Down => {
+                        let listed_servers = &store.state().listed_servers;
+                        let rooms = &store.state().server.rooms;
+                        if self.server_addr_input.has_focus() && !listed_servers.is_empty() {
+                            let index = Self::next_index(
+                                self.listed_server_index,
+                                listed_servers.len(),
+                                key_event.code
+                            );
+                            self.listed_server_index = Some(index);
+                            self.server_addr_input.set_content(listed_servers[index].addr.to_string());
+                        }
+                        else if self.room_input.has_focus() && !rooms.is_empty() {
+                            let index = Self::next_index(self.listed_room_index, rooms.len(), key_event.code);
+                            self.listed_room_index = Some(index);
+                            self.room_input.set_content(rooms[index].name.clone());
+                        }
+                    },
                     KeyCode::Esc => {
-                        if let Some(LoginStatus::Logged(..)) = store.state().user.login_status {
+                        if store.state().server.reconnection.is_some() {
+                            store.dispatch(Action::Disconnect);
+                        }
+                        else if store.state().server.queue.is_some() {
+                            store.dispatch(Action::LeaveQueue);
+                        }
+                        else if let Some(LoginStatus::Logged(..)) = store.state().user.login_status {
                             if !store.state().server.is_full() {
                                 store.dispatch(Action::Logout);
                             }
@@ -75,28 +129,105 @@ impl Menu {
                     _ => (),
                 }
                 self.server_addr_input.key_pressed(key_event);
+                self.password_input.key_pressed(key_event);
+                self.room_input.key_pressed(key_event);
                 self.character_symbol_input.key_pressed(key_event);
             },
             InputEvent::ResizeDisplay(_, _) => {},
         }
     }
 
+    /// Index of the list selected after pressing up or down.
+    fn next_index(index: Option<usize>, len: usize, code: KeyCode) -> usize {
+        match (index, code) {
+            (None, _) => 0,
+            (Some(index), KeyCode::Up) => index.saturating_sub(1),
+            (Some(index), _) => (index + 1).min(len - 1),
+        }
+    }
+
+    /// Joins the room written as `NAME`, or creates it if written as `NAME PLAYERS MAP_SIZE POINTS`.
+    /// Team chosen after pressing tab: no team, then each team in order.
+    /// None if the user can not choose a team.
+    fn next_team(state: &State) -> Option<TeamId> {
+        let teams = state.server.game_info.as_ref().map(|info| info.teams).unwrap_or(0);
+        let character = state.user.character_symbol?;
+        if teams == 0 || !state.user.is_logged() || state.server.game.status == GameStatus::Started {
+            return None
+        }
+
+        match state.server.team_choice(character) {
+            TeamId(id) if id >= teams => Some(TeamId::NONE),
+            TeamId(id) => Some(TeamId(id + 1)),
+        }
+    }
+
+    /// Written as `queue PLAYERS [MAP_SIZE]`, waits for a match with the chosen character.
+    fn room_action(content: &str, character: Option<char>) -> Option<Action> {
+        let words = content.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["queue", players_number, map_size @ ..] if map_size.len() <= 1 => {
+                let preferences = QueuePreferences {
+                    players_number: players_number.parse().ok()?,
+                    map_size: match map_size.first() {
+                        Some(map_size) => Some(map_size.parse().ok()?),
+                        None => None,
+                    },
+                };
+                Some(Action::JoinQueue(character?, preferences))
+            },
+            [name] => Some(Action::JoinRoom(name.to_string())),
+            [name, players_number, map_size, winner_points] => {
+                let settings = RoomSettings {
+                    players_number: players_number.parse().ok()?,
+                    map_size: map_size.parse().ok()?,
+                    winner_points: winner_points.parse().ok()?,
+                };
+                Some(Action::CreateRoom(name.to_string(), settings))
+            },
+            _ => None,
+        }
+    }
+
     pub fn update(&mut self, state: &State) {
-        let (server_addr_focus, character_focus) =
+        let (server_addr_focus, password_focus, room_focus, character_focus) =
         if !state.server.connection_status.is_connected()
         || !state.server.has_compatible_version() {
-            (true, false)
+            (true, false, false, false)
         }
         else if !state.user.is_logged() {
-            (false, true)
+            if state.server.password_required
+            && (state.user.password.is_none() || state.user.has_wrong_password()) {
+                (false, true, false, false)
+            }
+            else if self.choosing_room && !state.server.rooms.is_empty() {
+                (false, false, true, false)
+            }
+            else {
+                (false, false, false, true)
+            }
         }
         else {
-            (false, false)
+            (false, false, false, false)
         };
 
         self.character_symbol_input.focus(character_focus);
+        self.room_input.focus(room_focus);
+        self.password_input.focus(password_focus);
         self.server_addr_input.focus(server_addr_focus);
         self.waiting_room.update(state);
+
+        self.listed_server_index = match self.listed_server_index {
+            Some(_) if state.listed_servers.is_empty() => None,
+            Some(index) => Some(index.min(state.listed_servers.len() - 1)),
+            None => None,
+        };
+
+        self.listed_room_index = match self.listed_room_index {
+            Some(_) if state.server.rooms.is_empty() => None,
+            Some(index) => Some(index.min(state.server.rooms.len() - 1)),
+            None => None,
+        };
     }
 }
 
@@ -154,7 +285,7 @@ impl StatefulWidget for MenuWidget<'_> {
             ].as_ref())
             .split(column[5]);
 
-        ServerInfoPanelWidget::new(self.state)
+        ServerInfoPanelWidget::new(self.state, self.menu)
             .render(row[0], buffer);
 
         WaitingRoomPanelWidget::new(self.state, self.menu)
@@ -220,7 +351,7 @@ struct ClientInfoPanelWidget<'a> {state: &'a State, menu: &'a Menu}
 
 impl ClientInfoPanelWidget<'_> {
     const INITIAL_CURSOR: u16 = 17;
-    const HEIGHT: u16 = 2;
+    const HEIGHT: u16 = 4;
 }
 
 impl StatefulWidget for ClientInfoPanelWidget<'_> {
@@ -235,8 +366,14 @@ impl StatefulWidget for ClientInfoPanelWidget<'_> {
         ServerAddressLabelWidget::new(self.state, self.menu)
             .render(column[0], buffer, cursor);
 
-        CharacterLabelWidget::new(self.state, self.menu)
+        PasswordLabelWidget::new(self.state, self.menu)
             .render(column[1], buffer, cursor);
+
+        RoomLabelWidget::new(self.state, self.menu)
+            .render(column[2], buffer, cursor);
+
+        CharacterLabelWidget::new(self.state, self.menu)
+            .render(column[3], buffer, cursor);
     }
 }
 
@@ -268,11 +405,19 @@ impl StatefulWidget for ServerAddressLabelWidget<'_> {
                 Ok(_) => match self.state.server.connection_status {
                     ConnectionStatus::Connected => ("Connected", Color::LightGreen),
                     ConnectionStatus::NotConnected => {
-                        ("Not connected", Color::DarkGray)
+                        if self.state.server.shutdown_reason.is_some() {
+                            ("Server closed", Color::LightRed)
+                        }
+                        else {
+                            ("Not connected", Color::DarkGray)
+                        }
                     }
                     ConnectionStatus::NotFound => ("Server not found", Color::LightRed),
                     ConnectionStatus::Lost => {
-                        if !self.state.server.has_compatible_version() {
+                        if self.state.server.reconnection.is_some() {
+                            ("Reconnecting...", Color::LightYellow)
+                        }
+                        else if !self.state.server.has_compatible_version() {
                             ("Version error", Color::LightRed)
                         }
                         else {
@@ -294,6 +439,123 @@ impl StatefulWidget for ServerAddressLabelWidget<'_> {
     }
 }
 
+#[derive(derive_new::new)]
+struct PasswordLabelWidget<'a> {state: &'a State, menu: &'a Menu}
+
+impl StatefulWidget for PasswordLabelWidget<'_> {
+    type State = Cursor;
+    fn render(self, area: Rect, buffer: &mut Buffer, cursor: &mut Cursor) {
+        let hidden_password = "*".repeat(self.menu.password_input.content().chars().count());
+        let password_msg = Spans::from(vec![
+            Span::raw("Password:        "),
+            Span::styled(hidden_password, Style::default().add_modifier(Modifier::BOLD)),
+        ]);
+
+        Paragraph::new(password_msg)
+            .alignment(Alignment::Left)
+            .render(area, buffer);
+
+        let (status_message, status_color) =
+        if self.state.server.game_info.is_none() {
+            ("", Color::DarkGray)
+        }
+        else if !self.state.server.password_required {
+            ("Not required", Color::DarkGray)
+        }
+        else if self.state.user.has_wrong_password() {
+            ("Wrong password", Color::LightRed)
+        }
+        else if self.state.user.is_logged() {
+            ("Accepted", Color::LightGreen)
+        }
+        else if self.state.user.password.is_none() {
+            ("Required", Color::LightYellow)
+        }
+        else {
+            ("Not checked", Color::DarkGray)
+        };
+
+        let hint = Span::styled(status_message, Style::default().fg(status_color));
+        Paragraph::new(hint)
+            .alignment(Alignment::Right)
+            .render(area, buffer);
+
+        if let Some(ref pos) = self.menu.password_input.cursor_position() {
+            cursor.set(area.x + ClientInfoPanelWidget::INITIAL_CURSOR + *pos as u16, area.y);
+        }
+    }
+}
+
+#[derive(derive_new::new)]
+struct RoomLabelWidget<'a> {state: &'a State, menu: &'a Menu}
+
+impl StatefulWidget for RoomLabelWidget<'_> {
+    type State = Cursor;
+    fn render(self, area: Rect, buffer: &mut Buffer, cursor: &mut Cursor) {
+        let room = match self.menu.room_input.has_focus() {
+            true => Span::styled(
+                self.menu.room_input.content(),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            false => Span::styled(
+                self.state.server.room_name().unwrap_or_default(),
+                Style::default().fg(Color::Gray),
+            ),
+        };
+
+        Paragraph::new(Spans::from(vec![Span::raw("Room:            "), room]))
+            .alignment(Alignment::Left)
+            .render(area, buffer);
+
+        let (status_message, status_color) =
+        if self.state.server.game_info.is_none() {
+            ("".into(), Color::DarkGray)
+        }
+        else if let Some(queue) = &self.state.server.queue {
+            match &queue.status {
+                None => ("Queuing...".into(), Color::DarkGray),
+                Some(QueueStatus::Queued { waiting, rating }) => {
+                    let rating = rating.map(|rating| rating.to_string()).unwrap_or_else(|| "-".into());
+                    (format!("Queued {}, rating {}", waiting, rating), Color::LightCyan)
+                },
+                Some(QueueStatus::InvalidPlayerName) => ("Invalid character".into(), Color::LightRed),
+                Some(QueueStatus::InvalidPreferences) => ("Invalid preferences".into(), Color::LightRed),
+                Some(QueueStatus::Logged) => ("Logout to queue".into(), Color::LightYellow),
+                Some(QueueStatus::Unavailable) => ("Queue not available".into(), Color::LightYellow),
+                Some(QueueStatus::Matched(_)) | Some(QueueStatus::Left) => ("".into(), Color::DarkGray),
+            }
+        }
+        else if self.state.server.rooms.is_empty() {
+            ("Not available".into(), Color::DarkGray)
+        }
+        else {
+            match self.state.server.room_status {
+                Some(RoomStatus::NotFound) => ("Room not found".into(), Color::LightRed),
+                Some(RoomStatus::AlreadyExists) => ("Room already exists".into(), Color::LightRed),
+                Some(RoomStatus::InvalidName) => ("Invalid room name".into(), Color::LightRed),
+                Some(RoomStatus::InvalidSettings) => ("Invalid room settings".into(), Color::LightRed),
+                Some(RoomStatus::RoomLimit) => ("Room limit reached".into(), Color::LightYellow),
+                Some(RoomStatus::Logged) => ("Logout to change the room".into(), Color::LightYellow),
+                Some(RoomStatus::Joined) | None if self.menu.room_input.has_focus() =>
+                    ("NAME [PLAYERS MAP_SIZE POINTS]".into(), Color::DarkGray),
+                Some(RoomStatus::Joined) | None => {
+                    let rooms = self.state.server.rooms.len();
+                    (format!("{} room{} <Tab>", rooms, if rooms == 1 { "" } else { "s" }), Color::DarkGray)
+                },
+            }
+        };
+
+        let hint = Span::styled(status_message, Style::default().fg(status_color));
+        Paragraph::new(hint)
+            .alignment(Alignment::Right)
+            .render(area, buffer);
+
+        if let Some(ref pos) = self.menu.room_input.cursor_position() {
+            cursor.set(area.x + ClientInfoPanelWidget::INITIAL_CURSOR + *pos as u16, area.y);
+        }
+    }
+}
+
 #[derive(derive_new::new)]
 struct CharacterLabelWidget<'a> {state: &'a State, menu: &'a Menu}
 
@@ -314,21 +576,31 @@ impl StatefulWidget for CharacterLabelWidget<'_> {
             .alignment(Alignment::Left)
             .render(area, buffer);
 
+        let has_teams = self.state.server.game_info.as_ref().is_some_and(|info| info.teams > 0);
         let (status_message, status_color) =
-        if self.state.user.is_logged() {
-            ("Logged", Color::LightGreen)
+        if self.state.user.is_logged() && has_teams {
+            match self.state.server.team_choice(character) {
+                TeamId::NONE => ("Logged, any team".into(), Color::LightGreen),
+                team => (format!("Logged, team {}", team.0), util::team_color(team)),
+            }
+        }
+        else if self.state.user.is_logged() {
+            ("Logged".into(), Color::LightGreen)
+        }
+        else if let Some(LoginStatus::Banned(_)) = self.state.user.login_status {
+            ("Banned".into(), Color::LightRed)
         }
         else if self.state.server.logged_players.contains(&character) {
-            ("Name already chosen", Color::LightRed)
+            ("Name already chosen".into(), Color::LightRed)
         }
         else if self.state.server.is_full() {
-            ("Player limit reached", Color::LightYellow)
+            ("Player limit reached".into(), Color::LightYellow)
         }
         else if let Some(LoginStatus::InvalidPlayerName) = self.state.user.login_status {
-            ("Invalid player name", Color::LightRed)
+            ("Invalid player name".into(), Color::LightRed)
         }
         else {
-            ("Not logged", Color::DarkGray)
+            ("Not logged".into(), Color::DarkGray)
         };
 
         let hint = Span::styled(status_message, Style::default().fg(status_color));
@@ -343,7 +615,7 @@ impl StatefulWidget for CharacterLabelWidget<'_> {
 }
 
 #[derive(derive_new::new)]
-struct ServerInfoPanelWidget<'a> {state: &'a State}
+struct ServerInfoPanelWidget<'a> {state: &'a State, menu: &'a Menu}
 
 impl ServerInfoPanelWidget<'_> {
     const HEIGHT: u16 = 2 + ServerInfoWithContentPanelWidget::HEIGHT;
@@ -351,11 +623,19 @@ impl ServerInfoPanelWidget<'_> {
 
 impl Widget for ServerInfoPanelWidget<'_> {
     fn render(self, area: Rect, buffer: &mut Buffer) {
+        let show_listed_servers = self.state.server.game_info.is_none()
+            && self.state.server.version_info.is_none()
+            && !self.state.listed_servers.is_empty();
+
         Block::default()
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
             .title(Span::styled(
-                "Server info",
+                match (show_listed_servers, self.state.server.room_name()) {
+                    (true, _) => "Servers (↑↓)".into(),
+                    (false, Some(room)) => format!("Server info: {}", room),
+                    (false, None) => "Server info".into(),
+                },
                 Style::default().add_modifier(Modifier::BOLD)
             ))
             .render(area, buffer);
@@ -366,6 +646,10 @@ impl Widget for ServerInfoPanelWidget<'_> {
             ServerInfoWithContentPanelWidget::new(self.state)
                 .render(inner, buffer);
         }
+        else if show_listed_servers {
+            ServerListWidget::new(self.state, self.menu)
+                .render(inner, buffer);
+        }
         else {
             ServerInfoWithoutContentPanelWidget::new(self.state)
                 .render(inner, buffer);
@@ -373,6 +657,84 @@ impl Widget for ServerInfoPanelWidget<'_> {
     }
 }
 
+#[derive(derive_new::new)]
+struct ServerListWidget<'a> {state: &'a State, menu: &'a Menu}
+
+impl Widget for ServerListWidget<'_> {
+    fn render(self, area: Rect, buffer: &mut Buffer) {
+        let selected = self.menu.listed_server_index;
+        let offset = selected.unwrap_or(0).saturating_sub(area.height.saturating_sub(1) as usize);
+
+        let listed_servers = self.state.listed_servers
+            .iter()
+            .enumerate()
+            .skip(offset)
+            .take(area.height as usize);
+
+        for (row, (index, listed_server)) in listed_servers.enumerate() {
+            let row_area = Rect::new(area.x, area.y + row as u16, area.width, 1);
+            let is_selected = selected == Some(index);
+
+            let addr_style = match is_selected {
+                true => Style::default().add_modifier(Modifier::BOLD),
+                false => Style::default().fg(Color::Gray),
+            };
+
+            let source = match listed_server.source {
+                ServerSource::Lan => "LAN",
+                ServerSource::Registry => "WAN",
+            };
+
+            let left = Spans::from(vec![
+                Span::raw(if is_selected { "> " } else { "  " }),
+                Span::styled(listed_server.info.name.clone(), addr_style),
+                Span::styled(format!(" {} ", source), Style::default().fg(Color::DarkGray)),
+                Span::styled(listed_server.addr.to_string(), Style::default().fg(Color::Gray)),
+            ]);
+
+            Paragraph::new(left)
+                .alignment(Alignment::Left)
+                .render(row_area, buffer);
+
+            let ping = match listed_server.ping {
+                Some(ping) => format!("{}ms ", ping.as_millis()),
+                None => "-ms ".into(),
+            };
+
+            let (message, color) = match listed_server.compatibility {
+                Compatibility::None => (format!("v{}", listed_server.version), Color::LightRed),
+                compatibility => {
+                    let info = &listed_server.info;
+                    let message = format!(
+                        "{}/{} players",
+                        info.logged_players.len(),
+                        info.players_number,
+                    );
+                    let color = match compatibility {
+                        Compatibility::Fully => Color::LightGreen,
+                        _ => Color::Yellow,
+                    };
+                    (message, color)
+                }
+            };
+
+            let private = match listed_server.info.password_required {
+                true => "private ",
+                false => "",
+            };
+
+            let right = Spans::from(vec![
+                Span::styled(private, Style::default().fg(Color::DarkGray)),
+                Span::styled(ping, Style::default().fg(Color::Gray)),
+                Span::styled(message, Style::default().fg(color)),
+            ]);
+            Paragraph::new(right)
+                .alignment(Alignment::Right)
+                .render(row_area, buffer);
+        }
+    }
+}
+
 #[derive(derive_new::new)]
 struct ServerInfoWithoutContentPanelWidget<'a> {state: &'a State}
 
@@ -542,6 +904,11 @@ impl Widget for ServerInfoPointsLabelWidget<'_> {
         Paragraph::new(left)
             .alignment(Alignment::Left)
             .render(area, buffer);
+
+        let right = Span::styled(game_info.mode.name(), Style::default().fg(Color::LightCyan));
+        Paragraph::new(right)
+            .alignment(Alignment::Right)
+            .render(area, buffer);
     }
 }
 
@@ -634,8 +1001,50 @@ impl Widget for NotificationLabelWidget<'_> {
             .add_modifier(Modifier::BOLD)
             .fg(Color::Yellow));
 
+        let tab = Span::styled(" <Tab> ", Style::default()
+            .add_modifier(Modifier::BOLD)
+            .fg(Color::Cyan));
+
         let messages =
-        if !self.state.server.is_connected() || !self.state.server.has_compatible_version() {
+        if let Some(reconnection) = &self.state.server.reconnection {
+            let secs = reconnection.next_attempt_timestamp
+                .saturating_duration_since(Instant::now())
+                .as_secs() + 1;
+
+            let style = Style::default().fg(Color::LightYellow);
+            let cause = match &self.state.server.shutdown_reason {
+                Some(reason) => format!("{}. Reconnecting in ", reason),
+                None => "Connection lost. Reconnecting in ".into(),
+            };
+            vec![
+                Spans::from(vec![
+                    Span::styled(cause, style),
+                    Span::styled(secs.to_string(), style.add_modifier(Modifier::BOLD)),
+                    Span::styled("...", style),
+                ]),
+                Spans::from(vec![
+                    Span::raw("Press"), esc, Span::raw("to stop reconnecting")
+                ]),
+            ]
+        }
+        else if let Some(message) = self.state.server.current_broadcast() {
+            let style = Style::default().fg(Color::LightMagenta);
+            vec![
+                Spans::from(vec![Span::styled("Message from the server", style)]),
+                Spans::from(vec![Span::styled(message, style.add_modifier(Modifier::BOLD))]),
+            ]
+        }
+        else if let (Some(reason), false) =
+            (&self.state.server.shutdown_reason, self.state.server.is_connected()) {
+            let style = Style::default().fg(Color::LightRed);
+            vec![
+                Spans::from(vec![Span::styled(format!("Server closed: {}", reason), style)]),
+                Spans::from(vec![
+                    Span::raw("Press"), enter, Span::raw("to connect to server")
+                ]),
+            ]
+        }
+        else if !self.state.server.is_connected() || !self.state.server.has_compatible_version() {
             vec![
                 Spans::from(vec![
                     Span::raw("Press"), enter, Span::raw("to connect to server")
@@ -645,6 +1054,27 @@ impl Widget for NotificationLabelWidget<'_> {
                 ]),
             ]
         }
+        else if let Some(true) = self.state.server.queue.as_ref().map(|queue| queue.is_waiting()) {
+            let style = Style::default().fg(Color::LightCyan);
+            vec![
+                Spans::from(vec![Span::styled("Waiting for a match...", style)]),
+                Spans::from(vec![
+                    Span::raw("Press"), esc, Span::raw("to leave the queue")
+                ]),
+            ]
+        }
+        else if self.menu.room_input.has_focus() {
+            vec![
+                Spans::from(vec![
+                    Span::raw("Press"), enter, Span::raw("to join the room, or to create it with the settings")
+                ]),
+                Spans::from(vec![
+                    Span::raw("Write 'queue PLAYERS [MAP_SIZE]' for a match, or press"),
+                    tab,
+                    Span::raw("to choose the character"),
+                ]),
+            ]
+        }
         else if !self.state.user.is_logged() {
             vec![
                 if self.menu.character_symbol_input.content().is_none() {
@@ -680,6 +1110,16 @@ impl Widget for NotificationLabelWidget<'_> {
                 ]),
             ]
         }
+        else if self.state.server.game_info().teams > 0 {
+            vec![
+                Spans::from(vec![
+                    Span::raw("Press"), tab, Span::raw("to choose the team")
+                ]),
+                Spans::from(vec![
+                    Span::raw("Press"), esc, Span::raw("to logout the character")
+                ]),
+            ]
+        }
         else{
             vec![
                 Spans::from(vec![
@@ -1,4 +1,7 @@
+use crate::ids::{TeamId};
+
 use tui::layout::{Rect};
+use tui::style::{Color};
 
 use crossterm::event::{KeyEvent, KeyCode};
 
@@ -12,6 +15,15 @@ pub fn centered_area(base: Rect, dimension: (u16, u16)) -> Rect {
     Rect::new(x, y, width, height)
 }
 
+/// Color of the players of a team. White for the players without team.
+pub fn team_color(team: TeamId) -> Color {
+    const COLORS: [Color; 4] = [Color::LightCyan, Color::LightMagenta, Color::LightGreen, Color::LightYellow];
+    match team {
+        TeamId::NONE => Color::White,
+        TeamId(id) => COLORS[(id - 1) % COLORS.len()],
+    }
+}
+
 pub fn vertically_centered(base: Rect, height: u16) -> Rect {
     let height_diff = base.height as i16 - height as i16;
     let y = if height_diff > 0 { base.y + height_diff as u16 / 2 } else { 0 };
@@ -33,21 +45,26 @@ impl InputText {
     }
 
     pub fn key_pressed(&mut self, key_event: KeyEvent) {
+        // The cursor counts chars, not bytes, to allow non ascii content.
+        let len = self.content.chars().count();
+        let byte_index = |content: &String, cursor: usize| {
+            content.char_indices().nth(cursor).map(|(index, _)| index).unwrap_or(content.len())
+        };
         if let Some(ref mut cursor) = self.cursor {
             match key_event.code {
                 KeyCode::Char(character) => {
-                    self.content.insert(*cursor, character);
+                    self.content.insert(byte_index(&self.content, *cursor), character);
                     *cursor += 1;
                 }
                 KeyCode::Delete => {
-                    if *cursor < self.content.len() {
-                        self.content.remove(*cursor);
+                    if *cursor < len {
+                        self.content.remove(byte_index(&self.content, *cursor));
                     }
                 }
                 KeyCode::Backspace => {
                     if *cursor > 0 {
                         *cursor -= 1;
-                        self.content.remove(*cursor);
+                        self.content.remove(byte_index(&self.content, *cursor));
                     }
                 }
                 KeyCode::Left => {
@@ -56,7 +73,7 @@ impl InputText {
                     }
                 }
                 KeyCode::Right => {
-                    if *cursor < self.content.len() {
+                    if *cursor < len {
                         *cursor += 1;
                     }
                 }
@@ -64,7 +81,7 @@ impl InputText {
                     *cursor = 0;
                 }
                 KeyCode::End => {
-                    *cursor = self.content.len();
+                    *cursor = len;
                 }
                 _ => (),
             }
@@ -90,6 +107,13 @@ impl InputText {
         self.content.as_str()
     }
 
+    pub fn set_content(&mut self, content: String) {
+        self.content = content;
+        if let Some(ref mut cursor) = self.cursor {
+            *cursor = self.content.chars().count();
+        }
+    }
+
     pub fn cursor_position(&self) -> Option<usize> {
         self.cursor
     }
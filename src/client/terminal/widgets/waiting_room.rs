@@ -1,6 +1,9 @@
+use super::util::{self};
+
 use crate::client::state::{State};
 
 use crate::direction::{Direction};
+use crate::ids::{TeamId};
 
 use tui::widgets::{Widget};
 use tui::buffer::{Buffer};
@@ -16,6 +19,7 @@ struct PlayerState {
     position: (u16, u16),
     direction: Direction,
     last_move: Instant,
+    team: TeamId, // Chosen before the game
 }
 
 pub struct WaitingRoom {
@@ -46,10 +50,15 @@ impl WaitingRoom {
                     position,
                     direction: rng.gen(),
                     last_move: Instant::now() - MINIMAL_MOVE_TIME,
+                    team: TeamId::NONE,
                 });
             }
         }
 
+        for (player, player_state) in &mut self.players {
+            player_state.team = state.server.team_choice(*player);
+        }
+
         let mut player_positions = self.players
             .values()
             .map(|state| state.position)
@@ -117,7 +126,7 @@ impl Widget for WaitingRoomWidget<'_> {
                 buffer
                     .get_mut(area.x + draw_at.0, area.y + draw_at.1)
                     .set_char(*player)
-                    .set_style(Style::default().add_modifier(Modifier::BOLD));
+                    .set_style(Style::default().fg(util::team_color(state.team)).add_modifier(Modifier::BOLD));
             }
         }
     }
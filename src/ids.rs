@@ -1,5 +1,7 @@
 use serde::{Serialize, Deserialize};
 
+use rand::{RngCore, rngs::OsRng};
+
 macro_rules! define_optional_id {
     ($name:ident) => {
         #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
@@ -16,9 +18,34 @@ macro_rules! define_optional_id {
     }
 }
 
-pub type SessionToken = usize;
+/// Secret random value that identifies a session.
+/// Only the server and the client that owns the session know it.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionToken([u8; 32]);
+
+impl SessionToken {
+    pub fn random() -> SessionToken {
+        let mut bytes = [0; 32];
+        OsRng.fill_bytes(&mut bytes);
+        SessionToken(bytes)
+    }
+}
+
+/// Only the first bytes are shown to avoid leaking the token into the logs.
+impl std::fmt::Debug for SessionToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in &self.0[..4] {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, "…")
+    }
+}
 
 define_optional_id!(EntityId);
 define_optional_id!(SpellId);
 define_optional_id!(SpellSpecId);
 define_optional_id!(SkillId);
+define_optional_id!(PingId);
+define_optional_id!(RoomId);
+define_optional_id!(TeamId);
+define_optional_id!(ObjectId);
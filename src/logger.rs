@@ -1,7 +1,11 @@
+use crate::ids::{SessionToken};
+
 use fern::colors::{Color, ColoredLevelConfig};
 use log::{LevelFilter};
+use log::kv::{self, ToValue, Value};
 use colored::{Colorize};
 use clap::{crate_name};
+use message_io::network::{Endpoint};
 
 use std::str::{FromStr};
 
@@ -39,12 +43,139 @@ impl FromStr for Level {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct FormatUnknown;
+
+pub const LOG_FORMATS: [&str; 2] = ["text", "json"];
+
+pub enum Format {
+    Text,
+    Json, // One object by line with the timestamp, level, target, message and fields
+}
+
+impl FromStr for Format {
+    type Err = FormatUnknown;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(FormatUnknown),
+        }
+    }
+}
+
 pub enum Output<'a> {
     Stdout,
     File(&'a str),
 }
 
-pub fn init(level: Level, output: Output) {
+/// Value of a structured field of a log record.
+pub enum Field {
+    Number(u64),
+    Text(String),
+}
+
+macro_rules! impl_number_field {
+    ($($number:ty),*) => {
+        $(
+            impl From<$number> for Field {
+                fn from(value: $number) -> Field {
+                    Field::Number(value as u64)
+                }
+            }
+        )*
+    }
+}
+
+impl_number_field!(u8, u16, u32, u64, usize);
+
+impl From<char> for Field {
+    fn from(value: char) -> Field {
+        Field::Text(value.to_string())
+    }
+}
+
+impl From<&str> for Field {
+    fn from(value: &str) -> Field {
+        Field::Text(value.into())
+    }
+}
+
+impl From<String> for Field {
+    fn from(value: String) -> Field {
+        Field::Text(value)
+    }
+}
+
+impl From<Endpoint> for Field {
+    fn from(value: Endpoint) -> Field {
+        Field::Text(value.to_string())
+    }
+}
+
+impl From<SessionToken> for Field {
+    fn from(value: SessionToken) -> Field {
+        Field::Text(format!("{:?}", value))
+    }
+}
+
+impl ToValue for Field {
+    fn to_value(&self) -> Value<'_> {
+        match self {
+            Field::Number(number) => Value::from(*number),
+            Field::Text(text) => Value::from(text.as_str()),
+        }
+    }
+}
+
+/// Logs a message with structured fields, only written by the json format:
+/// `logger::record!(Info, {player: symbol, endpoint: endpoint}, "Player '{}' logged", symbol)`
+macro_rules! record {
+    ($level:ident, {$($key:ident: $value:expr),+ $(,)?}, $($arg:tt)+) => {{
+        let level = log::Level::$level;
+        if level <= log::max_level() {
+            let fields = [$((stringify!($key), $crate::logger::Field::from($value))),+];
+            log::logger().log(&log::Record::builder()
+                .args(format_args!($($arg)+))
+                .level(level)
+                .target(module_path!())
+                .module_path_static(Some(module_path!()))
+                .file_static(Some(file!()))
+                .line(Some(line!()))
+                .key_values(&&fields[..])
+                .build()
+            );
+        }
+    }};
+}
+
+pub(crate) use record;
+
+struct JsonFields(serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> kv::Visitor<'kvs> for JsonFields {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = match value.to_u64() {
+            Some(number) => serde_json::Value::from(number),
+            None => serde_json::Value::from(value.to_string()),
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+fn json_record(message: &std::fmt::Arguments, record: &log::Record) -> String {
+    let mut fields = JsonFields(serde_json::Map::new());
+    fields.0.insert("timestamp".into(), chrono::Local::now().to_rfc3339().into());
+    fields.0.insert("level".into(), record.level().to_string().into());
+    fields.0.insert("target".into(), record.target().into());
+    fields.0.insert("message".into(), message.to_string().into());
+    record.key_values().visit(&mut fields).ok();
+    serde_json::Value::Object(fields.0).to_string()
+}
+
+pub fn init(level: Level, output: Output, format: Format) {
     let level_colors = ColoredLevelConfig::new()
         .error(Color::Red)
         .warn(Color::Yellow)
@@ -64,22 +195,27 @@ pub fn init(level: Level, output: Output) {
         Level::Dev => log_config.level(LevelFilter::Trace),
     };
 
-    let log_config = log_config.format(move |out, message, record| {
-        let target = if level == Level::Dev {
-            format!("[{}] ", record.target())
-        }
-        else {
-            String::new()
-        };
+    let log_config = match format {
+        Format::Text => log_config.format(move |out, message, record| {
+            let target = if level == Level::Dev {
+                format!("[{}] ", record.target())
+            }
+            else {
+                String::new()
+            };
 
-        out.finish(format_args!(
-            "{}{}{} {}",
-            format!("[{}] ", chrono::Local::now().format("%H:%M:%S")).white(),
-            target.white(),
-            level_colors.color(record.level()),
-            format!("{}", message).bright_white(),
-        ))
-    });
+            out.finish(format_args!(
+                "{}{}{} {}",
+                format!("[{}] ", chrono::Local::now().format("%H:%M:%S")).white(),
+                target.white(),
+                level_colors.color(record.level()),
+                format!("{}", message).bright_white(),
+            ))
+        }),
+        Format::Json => log_config.format(|out, message, record| {
+            out.finish(format_args!("{}", json_record(message, record)))
+        }),
+    };
 
     let log_config = match output {
         Output::Stdout => log_config.chain(std::io::stdout()),
@@ -6,6 +6,7 @@ extern crate derive_builder;
 
 mod client;
 mod server;
+mod registry;
 
 mod logger;
 mod version;
@@ -34,11 +35,13 @@ fn main() {
         .setting(AppSettings::ArgRequiredElseHelp)
         .subcommand(client::configure_cli())
         .subcommand(server::configure_cli())
+        .subcommand(registry::configure_cli())
         .get_matches_from(args);
 
     match matches.subcommand() {
         ("client", Some(matches)) => client::run(matches),
         ("server", Some(matches)) => server::run(matches),
+        ("registry", Some(matches)) => registry::run(matches),
         _ => unreachable!(),
     }
 }
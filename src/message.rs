@@ -1,15 +1,30 @@
-use crate::version::{Compatibility};
+use crate::version::{Compatibility, ProtocolVersion};
 use crate::character::{CharacterId, Character};
 use crate::vec2::{Vec2};
 use crate::direction::{Direction};
-use crate::ids::{SessionToken, EntityId, SpellId, SpellSpecId, SkillId};
+use crate::ids::{SessionToken, EntityId, SpellId, SpellSpecId, SkillId, PingId, TeamId, ObjectId};
 
 use serde::{Serialize, Deserialize};
 
 use std::time::{Duration};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::str::{FromStr};
 
 // See the protocol diagram in docs/design/communication.md
 
+/// Longest server name, bounded to fit the server info in a udp datagram.
+pub const MAX_SERVER_NAME_LEN: usize = 32;
+
+/// Longest message that the server admin can broadcast to the clients.
+/// Also the longest reason of a server shutdown.
+pub const MAX_BROADCAST_LEN: usize = 120;
+
+/// Longest name of a room created by the clients.
+pub const MAX_ROOM_NAME_LEN: usize = 16;
+
+/// Multicast address where the servers listen for the discovery probes of the LAN clients.
+pub const DISCOVERY_ADDR: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(239, 255, 35, 49), 3550);
+
 // ===================================================
 //     High level messages
 // ===================================================
@@ -17,30 +32,81 @@ use std::time::{Duration};
 /// Messages that Client sends to Server
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ClientMessage {
-    // Version
-    Version(String),
+    // Version, must be always the first message
+    Version(ProtocolVersion),
 
     // Server info
     SubscribeServerInfo,
 
     // Login messages
-    Login(char),
+    Login(char, Option<String>), // player, password
     Logout,
 
     // Udp handshake
     ConnectUdp(SessionToken),
     TrustUdp,
 
+    // Latency measurement, by tcp or udp
+    Ping(PingId),
+    Pong(PingId),
+
     // Arena real time messages
     MovePlayer(Direction),
     CastSkill(Direction, SkillId),
+
+    // LAN discovery, by udp multicast
+    DiscoveryProbe(u16), // Port where the client listens for the answer
+
+    // Registry messages, by udp. Registration is sent periodically by the servers as heartbeat
+    RegisterServer(DiscoveryInfo),
+    QueryRegistry,
+
+    // Room messages, by tcp, only used with the rooms capability. Not allowed while logged.
+    ListRooms,
+    CreateRoom(String, RoomSettings), // The client joins the created room
+    JoinRoom(String),
+
+    // Matchmaking messages, only used with the matchmaking capability. Not allowed while logged.
+    JoinQueue(char, QueuePreferences), // Character to play the match
+    LeaveQueue,
+
+    // Team messages, only used in the modes with teams. Only allowed while logged and waiting
+    // for the game. `TeamId::NONE` lets the server choose the team.
+    ChooseTeam(TeamId),
+}
+
+impl ClientMessage {
+    /// Name of the message variant, used to label the metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClientMessage::Version(..) => "Version",
+            ClientMessage::SubscribeServerInfo => "SubscribeServerInfo",
+            ClientMessage::Login(..) => "Login",
+            ClientMessage::Logout => "Logout",
+            ClientMessage::ConnectUdp(..) => "ConnectUdp",
+            ClientMessage::TrustUdp => "TrustUdp",
+            ClientMessage::Ping(..) => "Ping",
+            ClientMessage::Pong(..) => "Pong",
+            ClientMessage::MovePlayer(..) => "MovePlayer",
+            ClientMessage::CastSkill(..) => "CastSkill",
+            ClientMessage::DiscoveryProbe(..) => "DiscoveryProbe",
+            ClientMessage::RegisterServer(..) => "RegisterServer",
+            ClientMessage::QueryRegistry => "QueryRegistry",
+            ClientMessage::ListRooms => "ListRooms",
+            ClientMessage::CreateRoom(..) => "CreateRoom",
+            ClientMessage::JoinRoom(..) => "JoinRoom",
+            ClientMessage::JoinQueue(..) => "JoinQueue",
+            ClientMessage::LeaveQueue => "LeaveQueue",
+            ClientMessage::ChooseTeam(..) => "ChooseTeam",
+        }
+    }
 }
 
 /// Messages that Server sends to Client
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ServerMessage {
-    // Version
-    Version(String, Compatibility),
+    // Version, must be always the first message
+    Version(ProtocolVersion, Compatibility),
 
     // Server info
     StaticServerInfo(ServerInfo),
@@ -52,6 +118,10 @@ pub enum ServerMessage {
     // Udp handshake
     UdpConnected,
 
+    // Latency measurement, by tcp or udp
+    Ping(PingId),
+    Pong(PingId),
+
     // Game messages
     StartGame(GameInfo),
     FinishGame,
@@ -61,6 +131,60 @@ pub enum ServerMessage {
     // Arena messages
     WaitArena(Duration),
     StartArena(ArenaInfo),
+
+    // LAN discovery, by udp
+    DiscoveryInfo(DiscoveryInfo),
+
+    // Registry messages, by udp
+    RegisteredServers(Vec<(SocketAddr, DiscoveryInfo)>), // Tcp address, info
+
+    // Admin messages
+    Broadcast(String),
+
+    // The server closes the connections after it.
+    // If `restart_in` is given, the server is expected to be running again after that time.
+    Shutdown { reason: String, restart_in: Option<Duration> },
+
+    // Room messages. The room list is sent again to the subscribed clients when it changes.
+    RoomList(Vec<RoomInfo>),
+    RoomStatus(String, RoomStatus), // room, status
+
+    // Matchmaking messages. Once matched, the client is moved to the room of the match,
+    // where it logs as usual.
+    QueueStatus(QueueStatus),
+
+    // Team messages. The teams chosen by the logged players of the room, sent again when
+    // they change. The players without a chosen team are not listed.
+    TeamChoices(Vec<(char, TeamId)>),
+}
+
+impl ServerMessage {
+    /// Name of the message variant, used to label the metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServerMessage::Version(..) => "Version",
+            ServerMessage::StaticServerInfo(..) => "StaticServerInfo",
+            ServerMessage::DynamicServerInfo(..) => "DynamicServerInfo",
+            ServerMessage::LoginStatus(..) => "LoginStatus",
+            ServerMessage::UdpConnected => "UdpConnected",
+            ServerMessage::Ping(..) => "Ping",
+            ServerMessage::Pong(..) => "Pong",
+            ServerMessage::StartGame(..) => "StartGame",
+            ServerMessage::FinishGame => "FinishGame",
+            ServerMessage::GameEvent(..) => "GameEvent",
+            ServerMessage::GameStep(..) => "GameStep",
+            ServerMessage::WaitArena(..) => "WaitArena",
+            ServerMessage::StartArena(..) => "StartArena",
+            ServerMessage::DiscoveryInfo(..) => "DiscoveryInfo",
+            ServerMessage::RegisteredServers(..) => "RegisteredServers",
+            ServerMessage::Broadcast(..) => "Broadcast",
+            ServerMessage::Shutdown { .. } => "Shutdown",
+            ServerMessage::RoomList(..) => "RoomList",
+            ServerMessage::RoomStatus(..) => "RoomStatus",
+            ServerMessage::QueueStatus(..) => "QueueStatus",
+            ServerMessage::TeamChoices(..) => "TeamChoices",
+        }
+    }
 }
 
 // ===================================================
@@ -78,27 +202,171 @@ pub enum LoginStatus {
     InvalidPlayerName,
     AlreadyLogged,
     PlayerLimit,
+    WrongPassword,
+    Banned(Option<Duration>), // Remaining time, None if it is permanent
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl LoginStatus {
+    /// Name of the status variant, used to label the metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LoginStatus::Logged(_, LoggedKind::FirstTime) => "Logged",
+            LoginStatus::Logged(_, LoggedKind::Reconnection) => "Reconnected",
+            LoginStatus::InvalidPlayerName => "InvalidPlayerName",
+            LoginStatus::AlreadyLogged => "AlreadyLogged",
+            LoginStatus::PlayerLimit => "PlayerLimit",
+            LoginStatus::WrongPassword => "WrongPassword",
+            LoginStatus::Banned(..) => "Banned",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ServerInfo {
+    pub name: String,
     pub udp_port: u16,
     pub players_number: u8,
     pub map_size: u16,
     pub winner_points: u16,
+    pub password_required: bool,
     pub logged_players: Vec<char>,
+    pub mode: GameModeKind,
+    pub teams: u8, // 0 if the mode has no teams
+}
+
+/// Rules of the games: how the players score and when the arenas end.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum GameModeKind {
+    Survival, // The survivors score by each death
+    Deathmatch, // The players score by each kill
+    TimedRounds, // Deathmatch with respawns in arenas of fixed duration
+    KingOfTheHill, // The player alone in the hill scores, with respawns in arenas of fixed duration
+    TeamDeathmatch, // The team of the killer scores by each kill of a rival
+    CaptureTheFlag, // The team that carries a rival flag to its base scores, with respawns
+}
+
+impl GameModeKind {
+    pub const ALL: [GameModeKind; 6] = [
+        GameModeKind::Survival,
+        GameModeKind::Deathmatch,
+        GameModeKind::TimedRounds,
+        GameModeKind::KingOfTheHill,
+        GameModeKind::TeamDeathmatch,
+        GameModeKind::CaptureTheFlag,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameModeKind::Survival => "survival",
+            GameModeKind::Deathmatch => "deathmatch",
+            GameModeKind::TimedRounds => "timed-rounds",
+            GameModeKind::KingOfTheHill => "king-of-the-hill",
+            GameModeKind::TeamDeathmatch => "team-deathmatch",
+            GameModeKind::CaptureTheFlag => "capture-the-flag",
+        }
+    }
+
+    pub fn has_teams(&self) -> bool {
+        matches!(self, GameModeKind::TeamDeathmatch | GameModeKind::CaptureTheFlag)
+    }
+}
+
+impl FromStr for GameModeKind {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        GameModeKind::ALL
+            .iter()
+            .find(|mode| mode.name() == value)
+            .copied()
+            .ok_or_else(|| format!("Unknown game mode '{}'", value))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct RoomSettings {
+    pub players_number: u8,
+    pub map_size: u16,
+    pub winner_points: u16,
+}
+
+impl RoomSettings {
+    pub const PLAYERS_NUMBER_RANGE: std::ops::RangeInclusive<u8> = 2..=26;
+    pub const MAP_SIZE_RANGE: std::ops::RangeInclusive<u16> = 10..=60;
+    pub const WINNER_POINTS_RANGE: std::ops::RangeInclusive<u16> = 1..=100;
+
+    /// Checks the bounds of the settings of a room created by a client.
+    pub fn is_valid(&self) -> bool {
+        Self::PLAYERS_NUMBER_RANGE.contains(&self.players_number)
+            && Self::MAP_SIZE_RANGE.contains(&self.map_size)
+            && Self::WINNER_POINTS_RANGE.contains(&self.winner_points)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoomInfo {
+    pub name: String,
+    pub settings: RoomSettings,
+    pub logged_players: Vec<char>,
+    pub playing: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RoomStatus {
+    Joined,
+    NotFound,
+    AlreadyExists,
+    InvalidName,
+    InvalidSettings,
+    RoomLimit,
+    Logged, // The client must logout before changing the room
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct QueuePreferences {
+    pub players_number: u8,
+    pub map_size: Option<u16>, // Any size if not given
+}
+
+impl QueuePreferences {
+    pub fn is_valid(&self) -> bool {
+        RoomSettings::PLAYERS_NUMBER_RANGE.contains(&self.players_number)
+            && self.map_size.is_none_or(|size| RoomSettings::MAP_SIZE_RANGE.contains(&size))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum QueueStatus {
+    Queued { waiting: usize, rating: Option<i32> }, // Clients waiting for the same players number
+    Matched(String), // Room of the match
+    Left,
+    InvalidPlayerName,
+    InvalidPreferences,
+    Logged, // The client must logout before joining the queue
+    Unavailable,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DiscoveryInfo {
+    pub tcp_port: u16,
+    pub version: ProtocolVersion,
+    pub info: ServerInfo,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GameInfo {
+    pub map_size: u16, // It can differ from the server info if the server config was reloaded
+    pub winner_points: u16,
     pub characters: Vec<Character>,
     pub players: Vec<(CharacterId, usize)>, //id, points
+    pub teams: Vec<TeamId>, // Of each player. `TeamId::NONE` if the mode has no teams
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 pub enum Terrain {
     Floor,
     Wall,
+    Hill, // Floor that scores in the king of the hill mode
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -110,7 +378,11 @@ pub struct ArenaInfo {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum GameEvent {
-    PlayerPointsUpdated(Vec<usize>)
+    PlayerPointsUpdated(Vec<usize>),
+    PlayerPingsUpdated(Vec<Option<Duration>>),
+    WinnerPointsChanged(usize),
+    PlayerRespawned(usize, EntityId), // Player index, new entity
+    EntityKilled { victim: EntityId, killer: EntityId, spell: SpellSpecId }, // `NONE` ids without killer
     // Other possible game event here
 }
 
@@ -130,8 +402,24 @@ pub struct SpellData {
     pub position: Vec2,
 }
 
+/// Arena elements placed by the game mode, that do not take part in the collisions.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ObjectKind {
+    Flag(TeamId),
+    Base(TeamId),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ObjectData {
+    pub id: ObjectId,
+    pub kind: ObjectKind,
+    pub position: Vec2,
+    pub carrier: EntityId, // `EntityId::NONE` if it is not carried
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Frame {
     pub entities: Vec<EntityData>,
     pub spells: Vec<SpellData>,
+    pub objects: Vec<ObjectData>,
 }
@@ -0,0 +1,70 @@
+mod registry_manager;
+
+use registry_manager::{RegistryManager, Config};
+
+use crate::logger::{self};
+
+use clap::{App, Arg, ArgMatches};
+
+use std::time::{Duration};
+
+lazy_static! {
+    static ref DEFAULT_PORT: String = 3551.to_string();
+}
+
+pub fn configure_cli<'a, 'b>() -> App<'a, 'b> {
+    App::new("registry")
+        .about("Running asciiarena registry mode: a directory where the servers are registered \
+            to be listed by the clients")
+        .arg(Arg::with_name("log")
+            .long("log")
+            .short("l")
+            .value_name("LEVEL")
+            .default_value("info")
+            .possible_values(&logger::LOG_LEVELS)
+            .help("Set the log level of verbosity")
+        )
+        .arg(Arg::with_name("log-format")
+            .long("log-format")
+            .value_name("FORMAT")
+            .default_value("text")
+            .possible_values(&logger::LOG_FORMATS)
+            .help("Write the log as colored text or as a JSON object by line")
+        )
+        .arg(Arg::with_name("port")
+            .long("port")
+            .value_name("PORT")
+            .default_value(&DEFAULT_PORT)
+            .validator(|port| match port.parse::<u16>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("The value must be in range 0..65535".into())
+            })
+            .help("Set the udp port for servers and clients")
+        )
+        .arg(Arg::with_name("expiration")
+            .long("expiration")
+            .value_name("SECONDS")
+            .default_value("15")
+            .validator(|value| match value.parse::<u64>() {
+                Ok(value) if value > 0 => Ok(()),
+                _ => Err("The value must be a number > 0".into()),
+            })
+            .help("Unlist a server if it does not send a heartbeat in this time. \
+                The servers send a heartbeat every 5 seconds")
+        )
+}
+
+pub fn run(matches: &ArgMatches) {
+    let level = matches.value_of("log").unwrap().parse().unwrap();
+    let format = matches.value_of("log-format").unwrap().parse().unwrap();
+    logger::init(level, logger::Output::Stdout, format);
+
+    let config = Config {
+        port: matches.value_of("port").unwrap().parse().unwrap(),
+        expiration: Duration::from_secs(matches.value_of("expiration").unwrap().parse().unwrap()),
+    };
+
+    if let Some(mut registry_manager) = RegistryManager::new(&config) {
+        registry_manager.run();
+    }
+}
@@ -0,0 +1,185 @@
+use crate::message::{ClientMessage, ServerMessage, DiscoveryInfo, MAX_SERVER_NAME_LEN};
+use crate::version::{self, ProtocolVersion, Compatibility};
+use crate::util::{self, TokenBucket};
+
+use message_io::events::{EventQueue};
+use message_io::network::{Network, NetEvent, Endpoint};
+
+use itertools::{Itertools};
+
+use std::time::{Duration, Instant};
+use std::collections::{HashMap};
+use std::net::{SocketAddr, IpAddr};
+
+const EXPIRATION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const SERVERS_PER_MESSAGE: usize = 8; // Keeps each answer under the udp datagram size
+
+/// The answers are larger than the queries: they are limited by ip and in size
+/// to not flood other hosts with spoofed queries.
+/// The clients query every two seconds.
+const QUERY_RATE: f32 = 1.0; // By second
+const QUERY_BURST: f32 = 4.0;
+const MAX_SERVERS_BY_QUERY: usize = 8 * SERVERS_PER_MESSAGE;
+
+/// Registered servers, in total and from the same ip, to not be filled by a single host.
+const MAX_SERVERS: usize = 1024;
+const MAX_SERVERS_BY_IP: usize = 8;
+
+#[derive(Debug)]
+enum Event {
+    Network(NetEvent<ClientMessage>),
+    CheckExpiration, // Generated periodically
+    Close,           // Main loop control
+}
+
+pub struct Config {
+    pub port: u16,
+    pub expiration: Duration,
+}
+
+pub struct RegistryManager<'a> {
+    config: &'a Config,
+    network: Network,
+    servers: HashMap<SocketAddr, (DiscoveryInfo, Instant)>, // Tcp address, info, last heartbeat
+    query_limits: HashMap<IpAddr, TokenBucket>,
+    event_queue: EventQueue<Event>,
+}
+
+impl<'a> RegistryManager<'a> {
+    pub fn new(config: &'a Config) -> Option<RegistryManager<'a>> {
+        let mut event_queue = EventQueue::new();
+
+        let sender = event_queue.sender().clone();
+        let mut network = Network::new(move |net_event| sender.send(Event::Network(net_event)));
+
+        let signal_sender = event_queue.sender().clone();
+        ctrlc::set_handler(move || {
+            signal_sender.send_with_priority(Event::Close)
+        }).unwrap();
+
+        if let Err(error) = network.listen_udp(("0.0.0.0", config.port)) {
+            log::error!("Can not run registry on UDP port {}: {}", config.port, error);
+            return None;
+        }
+
+        log::info!("Registry running on port {} (udp)", config.port);
+
+        event_queue.sender().send_with_timer(Event::CheckExpiration, EXPIRATION_CHECK_INTERVAL);
+
+        Some(RegistryManager {
+            config,
+            network,
+            servers: HashMap::new(),
+            query_limits: HashMap::new(),
+            event_queue,
+        })
+    }
+
+    pub fn run(&mut self) {
+        loop {
+            let event = self.event_queue.receive();
+            log::trace!("[Process event] - {:?}", event);
+            match event {
+                Event::Network(net_event) => match net_event {
+                    NetEvent::Message(endpoint, message) => match message {
+                        ClientMessage::RegisterServer(discovery_info) => {
+                            self.process_register_server(endpoint, discovery_info);
+                        },
+                        ClientMessage::QueryRegistry => {
+                            self.process_query_registry(endpoint);
+                        },
+                        _ => {
+                            log::warn!("Unexpected message from {}. Ignored", endpoint.addr());
+                        },
+                    },
+                    NetEvent::AddedEndpoint(_) => unreachable!(),
+                    NetEvent::RemovedEndpoint(_) => unreachable!(),
+                    NetEvent::DeserializationError(endpoint) => {
+                        log::warn!("Unknown message from {}. Ignored", endpoint.addr());
+                    },
+                },
+                Event::CheckExpiration => {
+                    self.process_check_expiration();
+                },
+                Event::Close => {
+                    log::info!("Closing registry");
+                    break
+                },
+            }
+        }
+    }
+
+    fn process_register_server(&mut self, endpoint: Endpoint, discovery_info: DiscoveryInfo) {
+        let ip = util::canonical_ip(endpoint.addr().ip());
+        let addr = SocketAddr::new(ip, discovery_info.tcp_port);
+
+        let name_len = discovery_info.info.name.chars().count();
+        if !(1..=MAX_SERVER_NAME_LEN).contains(&name_len) {
+            log::debug!("Server from {} rejected: invalid name", addr);
+            return
+        }
+
+        let compatibility = version::check(&ProtocolVersion::current(), &discovery_info.version);
+        if compatibility == Compatibility::None {
+            log::debug!(
+                "Server '{}' from {} rejected: incompatible protocol {}",
+                discovery_info.info.name,
+                addr,
+                discovery_info.version.protocol
+            );
+            return
+        }
+
+        if !self.servers.contains_key(&addr) {
+            let servers_by_ip = self.servers.keys().filter(|server| server.ip() == ip).count();
+            if self.servers.len() >= MAX_SERVERS || servers_by_ip >= MAX_SERVERS_BY_IP {
+                log::debug!("Server '{}' from {} rejected: server limit", discovery_info.info.name, addr);
+                return
+            }
+            log::info!("Server '{}' registered from {}", discovery_info.info.name, addr);
+        }
+        self.servers.insert(addr, (discovery_info, Instant::now()));
+    }
+
+    fn process_query_registry(&mut self, endpoint: Endpoint) {
+        let ip = util::canonical_ip(endpoint.addr().ip());
+        let accepted = self.query_limits
+            .entry(ip)
+            .or_insert_with(|| TokenBucket::new(QUERY_BURST, QUERY_RATE))
+            .consume(Instant::now());
+
+        if !accepted {
+            log::trace!("Query from {} ignored, rate limit exceeded", endpoint.addr());
+            return
+        }
+
+        log::trace!("Query from {}", endpoint.addr());
+
+        // The most recently seen servers first.
+        let servers = self.servers
+            .iter()
+            .sorted_by_key(|(_, (_, last_heartbeat))| std::cmp::Reverse(*last_heartbeat))
+            .take(MAX_SERVERS_BY_QUERY)
+            .map(|(addr, (discovery_info, _))| (*addr, discovery_info.clone()));
+
+        for chunk in &servers.chunks(SERVERS_PER_MESSAGE) {
+            self.network.send(endpoint, ServerMessage::RegisteredServers(chunk.collect()));
+        }
+    }
+
+    fn process_check_expiration(&mut self) {
+        let now = Instant::now();
+        let expiration = self.config.expiration;
+        self.servers.retain(|addr, (discovery_info, last_heartbeat)| {
+            let alive = now.duration_since(*last_heartbeat) < expiration;
+            if !alive {
+                log::info!("Server '{}' from {} unregistered", discovery_info.info.name, addr);
+            }
+            alive
+        });
+
+        self.query_limits.retain(|_, limit| !limit.is_full(now));
+
+        self.event_queue.sender().send_with_timer(Event::CheckExpiration, EXPIRATION_CHECK_INTERVAL);
+    }
+}
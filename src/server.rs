@@ -1,14 +1,25 @@
 mod server_manager;
+mod configuration;
+mod admin;
+mod ban_list;
+mod status;
+mod metrics;
+mod room;
+mod matchmaking;
 mod session;
+mod connection;
 mod game;
 
-use server_manager::{ServerManager, Config};
+use server_manager::{ServerManager};
+use configuration::{ConfigLoader, Options};
 
 use crate::logger::{self};
+use crate::message::{MAX_SERVER_NAME_LEN};
 
 use clap::{App, Arg, ArgMatches};
 
-use std::time::{Duration};
+use std::str::{FromStr};
+use std::net::{SocketAddr, IpAddr};
 
 lazy_static! {
     static ref DEFAULT_TCP_PORT: String = 3549.to_string();
@@ -26,6 +37,37 @@ pub fn configure_cli<'a, 'b>() -> App<'a, 'b> {
             .possible_values(&logger::LOG_LEVELS)
             .help("Set the log level of verbosity")
         )
+        .arg(Arg::with_name("log-format")
+            .long("log-format")
+            .value_name("FORMAT")
+            .default_value("text")
+            .possible_values(&logger::LOG_FORMATS)
+            .help("Write the log as colored text or as a JSON object by line. \
+                The JSON records include fields as the endpoint, the player or the arena")
+        )
+        .arg(Arg::with_name("config")
+            .long("config")
+            .short("c")
+            .value_name("FILE")
+            .help("Read the server options from a TOML file. \
+                The options are named as the command line options, that override the file values. \
+                The file is reloaded when it changes, applying the game settings in the next game")
+        )
+        .arg(Arg::with_name("bind")
+            .long("bind")
+            .value_name("IP")
+            .multiple(true)
+            .number_of_values(1)
+            .default_value("0.0.0.0")
+            .validator(|ip| match ip.parse::<IpAddr>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("The value must be a valid ipv4 or ipv6 address".into()),
+            })
+            .help("Set the addresses where the server listens. It can be repeated. \
+                Use '::' to listen in ipv4 and ipv6 (dual-stack) if the system allows it. \
+                The clients connected by ipv6 only receive the game by tcp, \
+                udp is only supported by ipv4")
+        )
         .arg(Arg::with_name("tcp-port")
             .long("tcp-port")
             .value_name("PORT")
@@ -57,11 +99,160 @@ pub fn configure_cli<'a, 'b>() -> App<'a, 'b> {
             })
             .help("Set the map size length")
         )
+        .arg(Arg::with_name("map-generator")
+            .long("map-generator")
+            .value_name("GENERATOR")
+            .default_value("empty")
+            .possible_values(&["empty", "pillars"])
+            .help("Set how the arena maps are generated")
+        )
+        .arg(Arg::with_name("winner-points")
+            .long("winner-points")
+            .value_name("POINTS")
+            .default_value("5")
+            .validator(|value| match value.parse::<usize>() {
+                Ok(points) if points > 0 => Ok(()),
+                _ => Err("The value must be a number > 0".into()),
+            })
+            .help("Points needed to win the game")
+        )
+        .arg(Arg::with_name("arena-waiting")
+            .long("arena-waiting")
+            .value_name("SECONDS")
+            .default_value("3")
+            .validator(|value| match value.parse::<u64>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("The value must be a positive number".into()),
+            })
+            .help("Waiting time before starting each arena")
+        )
+        .arg(Arg::with_name("tick-rate")
+            .long("tick-rate")
+            .value_name("STEPS")
+            .default_value("30")
+            .validator(|value| match value.parse::<u32>() {
+                Ok(rate) if rate > 0 => Ok(()),
+                _ => Err("The value must be a number > 0".into()),
+            })
+            .help("Game steps computed and sent to the clients per second")
+        )
+        .arg(Arg::with_name("mode")
+            .long("mode")
+            .value_name("MODE")
+            .default_value("survival")
+            .possible_values(&[
+                "survival", "deathmatch", "timed-rounds", "king-of-the-hill",
+                "team-deathmatch", "capture-the-flag",
+            ])
+            .help("Set the game mode")
+        )
+        .arg(Arg::with_name("teams")
+            .long("teams")
+            .value_name("NUMBER")
+            .default_value("2")
+            .validator(|value| match value.parse::<u8>() {
+                Ok(teams) if teams >= 2 => Ok(()),
+                _ => Err("The value must be a number >= 2".into()),
+            })
+            .help("Number of teams in the modes with teams")
+        )
+        .arg(Arg::with_name("friendly-fire")
+            .long("friendly-fire")
+            .help("The spells damage the players of the same team")
+        )
+        .arg(Arg::with_name("idle-timeout")
+            .long("idle-timeout")
+            .value_name("SECONDS")
+            .default_value("10")
+            .validator(|value| match value.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => Ok(()),
+                _ => Err("The value must be a number > 0".into()),
+            })
+            .help("Close the client connections that do not send any message in this time. \
+                Clients send heartbeats each second while connected")
+        )
+        .arg(Arg::with_name("message-rate")
+            .long("message-rate")
+            .value_name("MESSAGES")
+            .default_value("50")
+            .validator(|value| match value.parse::<u32>() {
+                Ok(rate) if rate > 0 => Ok(()),
+                _ => Err("The value must be a number > 0".into()),
+            })
+            .help("Maximum messages per second accepted from each client endpoint, \
+                allowing bursts of the double. Tcp clients that exceed it are disconnected")
+        )
+        .arg(Arg::with_name("no-discovery")
+            .long("no-discovery")
+            .help("Do not answer the LAN discovery probes of the clients")
+        )
+        .arg(Arg::with_name("name")
+            .long("name")
+            .value_name("NAME")
+            .default_value("asciiarena")
+            .validator(|name| match name.chars().count() {
+                1..=MAX_SERVER_NAME_LEN => Ok(()),
+                _ => Err(format!("The name must have between 1 and {} characters", MAX_SERVER_NAME_LEN)),
+            })
+            .help("Set the server name shown in the server lists of the clients")
+        )
+        .arg(Arg::with_name("registry")
+            .long("registry")
+            .value_name("ADDR")
+            .validator(|addr| match addr.parse::<SocketAddr>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("Registry must be a valid network address".into()),
+            })
+            .help("Register the server in a registry (ip and port), \
+                see 'asciiarena registry'. Format example: 192.168.0.56:3551")
+        )
+        .arg(Arg::with_name("password")
+            .long("password")
+            .value_name("PASSWORD")
+            .help("Only allow to login the clients that know this password")
+        )
+        .arg(Arg::with_name("ban-list")
+            .long("ban-list")
+            .value_name("FILE")
+            .help("Keep the bans in this TOML file, created if it does not exist. \
+                Without it, the bans are lost when the server stops")
+        )
+        .arg(Arg::with_name("status-port")
+            .long("status-port")
+            .value_name("PORT")
+            .validator(|port| match port.parse::<u16>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("The value must be in range 0..65535".into())
+            })
+            .help("Serve the server status as JSON and the Prometheus metrics by HTTP \
+                in this port, only from localhost. \
+                Example: curl http://localhost:<PORT>/status or /metrics")
+        )
+        .arg(Arg::with_name("finish-arena-on-close")
+            .long("finish-arena-on-close")
+            .help("When the server is closed by Ctrl-C or by the admin console, \
+                wait for the end of the current arena. A second close request closes it immediately")
+        )
+        .arg(Arg::with_name("max-rooms")
+            .long("max-rooms")
+            .value_name("NUMBER")
+            .default_value("4")
+            .validator(|value| match value.parse::<usize>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("The value must be a positive number".into()),
+            })
+            .help("Maximum rooms that the clients can create besides the main room, \
+                each one with its own game. 0 disables the creation of rooms")
+        )
+        .arg(Arg::with_name("admin")
+            .long("admin")
+            .help("Read admin commands from stdin to manage the sessions and the game. \
+                Write 'help' in the console to list them")
+        )
         .arg(Arg::with_name("players")
             .long("players")
             .short("p")
             .value_name("NUMBER")
-            .required(true)
             .validator(|value| {
                 match value.parse::<u32>() {
                     Ok(number) => match number > 0 {
@@ -72,24 +263,68 @@ pub fn configure_cli<'a, 'b>() -> App<'a, 'b> {
                 }
             })
             .help("Number of players. \
-                The game will not start until the number of players has been reached.")
+                The game will not start until the number of players has been reached. \
+                Required if it is not in the config file")
         )
 }
 
 pub fn run(matches: &ArgMatches) {
     let level = matches.value_of("log").unwrap().parse().unwrap();
-    logger::init(level, logger::Output::Stdout);
+    let format = matches.value_of("log-format").unwrap().parse().unwrap();
+    logger::init(level, logger::Output::Stdout, format);
 
-    let config = Config {
-        tcp_port: matches.value_of("tcp-port").unwrap().parse().unwrap(),
-        udp_port: matches.value_of("udp-port").unwrap().parse().unwrap(),
-        players_number: matches.value_of("players").unwrap().parse().unwrap(),
-        map_size: matches.value_of("map-size").unwrap().parse().unwrap(),
-        winner_points: 5,
-        arena_waiting: Duration::from_secs(3),
-    };
+    let mut config_loader = ConfigLoader::new(
+        matches.value_of("config").map(|file| file.into()),
+        options(matches, true),
+        options(matches, false),
+    );
+
+    match config_loader.load() {
+        Ok(config) => {
+            if let Some(mut server_manager) = ServerManager::new(config, config_loader, matches.is_present("admin")) {
+                server_manager.run();
+            }
+        },
+        Err(error) => log::error!("{}", error),
+    }
+}
+
+/// Options given by command line.
+/// If `explicit` is true, only the options written by the user are returned,
+/// otherwise, only the default values.
+fn options(matches: &ArgMatches, explicit: bool) -> Options {
+    let given = |name| (matches.occurrences_of(name) > 0) == explicit;
+
+    fn value<T: FromStr>(matches: &ArgMatches, name: &str, given: bool) -> Option<T>
+    where T::Err: std::fmt::Debug {
+        matches.value_of(name).filter(|_| given).map(|value| value.parse().unwrap())
+    }
 
-    if let Some(mut server_manager) = ServerManager::new(&config) {
-        server_manager.run();
+    Options {
+        bind: matches.values_of("bind")
+            .filter(|_| given("bind"))
+            .map(|ips| ips.map(|ip| ip.parse().unwrap()).collect()),
+        tcp_port: value(matches, "tcp-port", given("tcp-port")),
+        udp_port: value(matches, "udp-port", given("udp-port")),
+        players: value(matches, "players", given("players")),
+        map_size: value(matches, "map-size", given("map-size")),
+        map_generator: value(matches, "map-generator", given("map-generator")),
+        winner_points: value(matches, "winner-points", given("winner-points")),
+        arena_waiting: value(matches, "arena-waiting", given("arena-waiting")),
+        tick_rate: value(matches, "tick-rate", given("tick-rate")),
+        mode: value(matches, "mode", given("mode")),
+        teams: value(matches, "teams", given("teams")),
+        friendly_fire: Some(matches.is_present("friendly-fire")).filter(|_| given("friendly-fire")),
+        idle_timeout: value(matches, "idle-timeout", given("idle-timeout")),
+        message_rate: value(matches, "message-rate", given("message-rate")),
+        no_discovery: Some(matches.is_present("no-discovery")).filter(|_| given("no-discovery")),
+        name: value(matches, "name", given("name")),
+        registry: value(matches, "registry", given("registry")),
+        password: value(matches, "password", given("password")),
+        ban_list: value(matches, "ban-list", given("ban-list")),
+        status_port: value(matches, "status-port", given("status-port")),
+        finish_arena_on_close: Some(matches.is_present("finish-arena-on-close"))
+            .filter(|_| given("finish-arena-on-close")),
+        max_rooms: value(matches, "max-rooms", given("max-rooms")),
     }
 }
@@ -0,0 +1,212 @@
+use crate::message::{MAX_BROADCAST_LEN};
+use crate::util::{self};
+
+use serde::{Serialize, Deserialize};
+
+use std::io::{BufRead};
+use std::net::{IpAddr};
+use std::str::{FromStr};
+use std::time::{Duration};
+use std::thread::{self};
+use std::convert::{TryFrom};
+
+const HELP: &str = "\
+Admin commands:
+  sessions           List the player sessions
+  endpoints          List the client connections
+  kick <CHAR|IP>     Close the session of a character or the connections of an ip
+  ban <CHAR|IP> [MINUTES]
+                     Kick and reject the next logins, permanently if no minutes are given
+  unban <CHAR|IP>    Remove a ban
+  bans               List the bans
+  rooms              List the rooms with their settings and players
+  start [ROOM]       Start the game with the players logged (at least 2), in the main room by default
+  end [ROOM]         Finish the current arena and wait for the next one, in the main room by default
+  points <POINTS> [ROOM]
+                     Change the points needed to win, also in the current game, in the main room by default
+  broadcast <TEXT>   Show a message to all the connected clients
+  stop [REASON]      Close the server, showing the reason to the clients
+  restart <SECONDS> [REASON]
+                     Close the server, announcing to the clients that it will be back
+                     in these seconds. The clients reconnect by themselves
+  help               Show this help";
+
+/// Character or ip affected by a kick or a ban.
+/// Stored as the character or the ip written in the admin console.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub enum Target {
+    Player(char),
+    Ip(IpAddr),
+}
+
+impl FromStr for Target {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut chars = value.chars();
+        match (chars.next(), chars.next()) {
+            (Some(symbol), None) if util::is_valid_character(symbol) => Ok(Target::Player(symbol)),
+            _ => match value.parse::<IpAddr>() {
+                Ok(ip) => Ok(Target::Ip(util::canonical_ip(ip))),
+                Err(_) => Err(format!("'{}' is neither a character nor an ip", value)),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Target {
+    type Error = String;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Target> for String {
+    fn from(target: Target) -> String {
+        match target {
+            Target::Player(symbol) => symbol.to_string(),
+            Target::Ip(ip) => ip.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Player(symbol) => write!(f, "player '{}'", symbol),
+            Target::Ip(ip) => write!(f, "ip {}", ip),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+    Sessions,
+    Endpoints,
+    Kick(Target),
+    Ban(Target, Option<Duration>),
+    Unban(Target),
+    Bans,
+    Rooms,
+    Start(Option<String>), // Room, the main room if not given
+    EndArena(Option<String>), // Room, the main room if not given
+    WinnerPoints(usize, Option<String>), // Points, room. The main room if not given
+    Broadcast(String),
+    Stop(Option<String>), // Reason
+    Restart(Duration, Option<String>), // Time until it is running again, reason
+}
+
+impl FromStr for AdminCommand {
+    type Err = String;
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let (name, argument) = match line.find(char::is_whitespace) {
+            Some(index) => (&line[..index], line[index..].trim()),
+            None => (line, ""),
+        };
+
+        let no_argument = |command| match argument.is_empty() {
+            true => Ok(command),
+            false => Err(format!("The command '{}' has no arguments", name)),
+        };
+
+        let reason = |reason: &str| match reason.chars().count() {
+            0 => Ok(None),
+            1..=MAX_BROADCAST_LEN => Ok(Some(reason.into())),
+            _ => Err(format!("The reason must have at most {} characters", MAX_BROADCAST_LEN)),
+        };
+
+        let room = || match argument.is_empty() {
+            true => None,
+            false => Some(argument.into()),
+        };
+
+        let target = || match argument.is_empty() {
+            true => Err(format!("The command '{}' needs a character or an ip", name)),
+            false => argument.parse::<Target>(),
+        };
+
+        match name {
+            "sessions" => no_argument(AdminCommand::Sessions),
+            "endpoints" => no_argument(AdminCommand::Endpoints),
+            "kick" => Ok(AdminCommand::Kick(target()?)),
+            "ban" => {
+                let mut arguments = argument.split_whitespace();
+                let target = match arguments.next() {
+                    Some(target) => target.parse()?,
+                    None => return Err(format!("The command '{}' needs a character or an ip", name)),
+                };
+                let duration = match arguments.next().map(|minutes| minutes.parse::<u64>()) {
+                    Some(Ok(minutes)) if minutes > 0 => Some(Duration::from_secs(minutes * 60)),
+                    Some(_) => return Err("The minutes must be a number > 0".into()),
+                    None => None,
+                };
+                match arguments.next() {
+                    Some(_) => Err(format!("Too many arguments for the command '{}'", name)),
+                    None => Ok(AdminCommand::Ban(target, duration)),
+                }
+            },
+            "unban" => Ok(AdminCommand::Unban(target()?)),
+            "bans" => no_argument(AdminCommand::Bans),
+            "rooms" => no_argument(AdminCommand::Rooms),
+            "start" => Ok(AdminCommand::Start(room())),
+            "end" => Ok(AdminCommand::EndArena(room())),
+            "points" => {
+                let (points, room) = match argument.find(char::is_whitespace) {
+                    Some(index) => (&argument[..index], Some(argument[index..].trim().into())),
+                    None => (argument, None),
+                };
+                match points.parse::<usize>() {
+                    Ok(points) if points > 0 => Ok(AdminCommand::WinnerPoints(points, room)),
+                    _ => Err("The points must be a number > 0".into()),
+                }
+            },
+            "broadcast" => match argument.chars().count() {
+                1..=MAX_BROADCAST_LEN => Ok(AdminCommand::Broadcast(argument.into())),
+                _ => Err(format!("The message must have between 1 and {} characters", MAX_BROADCAST_LEN)),
+            },
+            "stop" => Ok(AdminCommand::Stop(reason(argument)?)),
+            "restart" => {
+                let (seconds, rest) = match argument.find(char::is_whitespace) {
+                    Some(index) => (&argument[..index], argument[index..].trim()),
+                    None => (argument, ""),
+                };
+                match seconds.parse::<u64>() {
+                    Ok(seconds) if seconds > 0 =>
+                        Ok(AdminCommand::Restart(Duration::from_secs(seconds), reason(rest)?)),
+                    _ => Err("The seconds must be a number > 0".into()),
+                }
+            },
+            _ => Err(format!("Unknown command '{}'. Write 'help' to list the commands", name)),
+        }
+    }
+}
+
+/// Reads the admin commands from stdin, one by line, until stdin is closed.
+/// The help and the wrong commands are answered directly, without calling `callback`.
+pub fn run_console(callback: impl Fn(AdminCommand) + Send + 'static) {
+    thread::Builder::new()
+        .name("asciiarena: admin console".into())
+        .spawn(move || {
+            let stdin = std::io::stdin();
+            for line in stdin.lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(error) => {
+                        log::error!("Can not read the admin console: {}", error);
+                        break
+                    }
+                };
+                match line.trim() {
+                    "" => (),
+                    "help" => println!("{}", HELP),
+                    line => match line.parse() {
+                        Ok(command) => callback(command),
+                        Err(error) => println!("{}", error),
+                    },
+                }
+            }
+            log::trace!("Admin console closed");
+        })
+        .unwrap();
+}
@@ -0,0 +1,231 @@
+use super::admin::{Target};
+
+use serde::{Serialize, Deserialize};
+
+use std::collections::{HashMap};
+use std::path::{PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BanEntry {
+    target: Target,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expiration: Option<u64>, // Seconds since the unix epoch
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct BanFile {
+    #[serde(default)]
+    bans: Vec<BanEntry>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ban {
+    expiration: Option<SystemTime>,
+}
+
+impl Ban {
+    /// Remaining time of the ban. `None` if it is permanent.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.expiration.map(|expiration| {
+            expiration.duration_since(SystemTime::now()).unwrap_or_default()
+        })
+    }
+
+    fn is_expired(&self) -> bool {
+        self.remaining() == Some(Duration::default())
+    }
+}
+
+impl std::fmt::Display for Ban {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.remaining() {
+            Some(remaining) => write!(f, "{} minutes left", remaining.as_secs() / 60 + 1),
+            None => write!(f, "permanent"),
+        }
+    }
+}
+
+/// Banned players and ips. If there is a file, the changes are saved into it.
+pub struct BanList {
+    file: Option<PathBuf>,
+    bans: HashMap<Target, Ban>,
+}
+
+impl BanList {
+    /// Loads the bans from the file. A non-existent file is considered empty.
+    pub fn load(file: Option<PathBuf>) -> Result<BanList, String> {
+        let ban_file = match &file {
+            Some(file) if file.exists() => {
+                let content = std::fs::read_to_string(file)
+                    .map_err(|error| format!("Can not read {}: {}", file.display(), error))?;
+                toml::from_str(&content)
+                    .map_err(|error| format!("Invalid ban list {}: {}", file.display(), error))?
+            },
+            _ => BanFile::default(),
+        };
+
+        let bans = ban_file.bans
+            .into_iter()
+            .map(|entry| {
+                let expiration = entry.expiration.map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+                (entry.target, Ban { expiration })
+            })
+            .filter(|(_, ban)| !ban.is_expired())
+            .collect();
+
+        Ok(BanList { file, bans })
+    }
+
+    /// The ban of the target, if it is banned.
+    pub fn find(&self, target: Target) -> Option<Ban> {
+        self.bans.get(&target).filter(|ban| !ban.is_expired()).copied()
+    }
+
+    pub fn bans(&self) -> impl Iterator<Item = (&Target, &Ban)> {
+        self.bans.iter().filter(|(_, ban)| !ban.is_expired())
+    }
+
+    /// Bans the target during `duration` or permanently if `None`.
+    /// A previous ban of the target is replaced.
+    pub fn ban(&mut self, target: Target, duration: Option<Duration>) {
+        let expiration = duration.map(|duration| SystemTime::now() + duration);
+        self.bans.insert(target, Ban { expiration });
+        self.save();
+    }
+
+    /// Returns false if the target was not banned.
+    pub fn unban(&mut self, target: Target) -> bool {
+        let was_banned = self.find(target).is_some();
+        self.bans.remove(&target);
+        self.save();
+        was_banned
+    }
+
+    fn save(&mut self) {
+        self.bans.retain(|_, ban| !ban.is_expired());
+        if let Some(file) = &self.file {
+            let ban_file = BanFile {
+                bans: self.bans
+                    .iter()
+                    .map(|(&target, ban)| BanEntry {
+                        target,
+                        expiration: ban.expiration.map(|expiration| {
+                            expiration.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+                        }),
+                    })
+                    .collect(),
+            };
+
+            let content = toml::to_string(&ban_file).unwrap();
+            if let Err(error) = std::fs::write(file, content) {
+                log::error!("Can not save the ban list in {}: {}", file.display(), error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::{IpAddr, Ipv4Addr};
+
+    /// File in the temporary directory, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            let path = std::env::temp_dir()
+                .join(format!("asciiarena-ban-list-{}-{}.toml", std::process::id(), name));
+            let _ = std::fs::remove_file(&path);
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+    #[test]
+    fn without_file() {
+        let mut ban_list = BanList::load(None).unwrap();
+        ban_list.ban(Target::Player('A'), None);
+        assert!(ban_list.find(Target::Player('A')).is_some());
+        assert!(ban_list.unban(Target::Player('A')));
+        assert!(!ban_list.unban(Target::Player('A')));
+    }
+
+    #[test]
+    fn non_existent_file_is_empty() {
+        let file = TempFile::new("non-existent");
+        let ban_list = BanList::load(Some(file.0.clone())).unwrap();
+        assert_eq!(ban_list.bans().count(), 0);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let file = TempFile::new("save-and-load");
+        let mut ban_list = BanList::load(Some(file.0.clone())).unwrap();
+        ban_list.ban(Target::Player('A'), None);
+        ban_list.ban(Target::Ip(IP), Some(Duration::from_secs(3600)));
+        ban_list.ban(Target::Player('B'), None);
+        ban_list.unban(Target::Player('B'));
+
+        let ban_list = BanList::load(Some(file.0.clone())).unwrap();
+        assert_eq!(ban_list.bans().count(), 2);
+        assert_eq!(ban_list.find(Target::Player('A')).unwrap().remaining(), None);
+        let remaining = ban_list.find(Target::Ip(IP)).unwrap().remaining().unwrap();
+        assert!(remaining > Duration::from_secs(3500) && remaining <= Duration::from_secs(3600));
+        assert!(ban_list.find(Target::Player('B')).is_none());
+    }
+
+    #[test]
+    fn expired_bans_dropped() {
+        let file = TempFile::new("expired");
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let content = format!(
+            "[[bans]]\ntarget = \"A\"\nexpiration = {}\n\n[[bans]]\ntarget = \"B\"\nexpiration = {}\n",
+            now - 60,
+            now + 60,
+        );
+        std::fs::write(&file.0, content).unwrap();
+
+        let ban_list = BanList::load(Some(file.0.clone())).unwrap();
+        assert!(ban_list.find(Target::Player('A')).is_none());
+        assert!(ban_list.find(Target::Player('B')).is_some());
+        assert_eq!(ban_list.bans().count(), 1);
+    }
+
+    #[test]
+    fn targets_as_strings() {
+        let file = TempFile::new("targets");
+        let content = "[[bans]]\ntarget = \"A\"\n\n[[bans]]\ntarget = \"::ffff:10.0.0.1\"\n";
+        std::fs::write(&file.0, content).unwrap();
+
+        // The ipv4 addresses mapped into ipv6 are the same target as the ipv4 ones.
+        let ban_list = BanList::load(Some(file.0.clone())).unwrap();
+        assert!(ban_list.find(Target::Player('A')).is_some());
+        assert!(ban_list.find(Target::Ip(IP)).is_some());
+
+        let entry = BanEntry { target: Target::Ip(IP), expiration: None };
+        assert_eq!(toml::to_string(&entry).unwrap(), "target = \"10.0.0.1\"\n");
+        let entry = BanEntry { target: Target::Player('A'), expiration: Some(5) };
+        assert_eq!(toml::to_string(&entry).unwrap(), "target = \"A\"\nexpiration = 5\n");
+    }
+
+    #[test]
+    fn invalid_targets_rejected() {
+        for target in &["a", "AB", "10.0.0.256"] {
+            let file = TempFile::new(&format!("invalid-{}", target));
+            std::fs::write(&file.0, format!("[[bans]]\ntarget = \"{}\"\n", target)).unwrap();
+            assert!(BanList::load(Some(file.0.clone())).is_err());
+        }
+    }
+}
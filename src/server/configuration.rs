@@ -0,0 +1,244 @@
+use super::game::arena::map::{MapGenerator};
+
+use crate::message::{RoomSettings, GameModeKind, MAX_SERVER_NAME_LEN};
+
+use serde::{Deserialize};
+
+use std::net::{SocketAddr, IpAddr};
+use std::time::{Duration, SystemTime};
+use std::path::{PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub bind: Vec<IpAddr>,
+    pub tcp_port: u16,
+    pub udp_port: u16,
+    pub players_number: u8,
+    pub map_size: usize,
+    pub map_generator: MapGenerator,
+    pub winner_points: usize,
+    pub arena_waiting: Duration,
+    pub tick_rate: u32,
+    pub mode: GameModeKind,
+    pub teams: u8,
+    pub friendly_fire: bool,
+    pub idle_timeout: Duration,
+    pub message_rate: u32,
+    pub discovery: bool,
+    pub name: String,
+    pub registry: Option<SocketAddr>,
+    pub password: Option<String>,
+    pub ban_list: Option<PathBuf>,
+    pub status_port: Option<u16>,
+    pub finish_arena_on_close: bool,
+    pub max_rooms: usize,
+}
+
+impl Config {
+    /// Duration of a game step.
+    pub fn step_duration(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.tick_rate as f32)
+    }
+
+    /// Settings of the main room, the room of the clients that do not join another one.
+    pub fn room_settings(&self) -> RoomSettings {
+        RoomSettings {
+            players_number: self.players_number,
+            map_size: self.map_size as u16,
+            winner_points: self.winner_points as u16,
+        }
+    }
+
+    /// Copies the settings that are safe to change between games.
+    pub fn apply_game_settings(&mut self, other: &Config) {
+        self.map_size = other.map_size;
+        self.map_generator = other.map_generator;
+        self.winner_points = other.winner_points;
+        self.arena_waiting = other.arena_waiting;
+        self.tick_rate = other.tick_rate;
+        self.mode = other.mode;
+        self.teams = other.teams;
+        self.friendly_fire = other.friendly_fire;
+        self.max_rooms = other.max_rooms;
+    }
+
+    /// Names of the changed settings that only are applied restarting the server.
+    pub fn restart_settings_changed(&self, other: &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.bind != other.bind { changed.push("bind") }
+        if self.tcp_port != other.tcp_port { changed.push("tcp-port") }
+        if self.udp_port != other.udp_port { changed.push("udp-port") }
+        if self.players_number != other.players_number { changed.push("players") }
+        if self.idle_timeout != other.idle_timeout { changed.push("idle-timeout") }
+        if self.message_rate != other.message_rate { changed.push("message-rate") }
+        if self.discovery != other.discovery { changed.push("no-discovery") }
+        if self.name != other.name { changed.push("name") }
+        if self.registry != other.registry { changed.push("registry") }
+        if self.password != other.password { changed.push("password") }
+        if self.ban_list != other.ban_list { changed.push("ban-list") }
+        if self.status_port != other.status_port { changed.push("status-port") }
+        if self.finish_arena_on_close != other.finish_arena_on_close {
+            changed.push("finish-arena-on-close")
+        }
+        changed
+    }
+}
+
+/// Server options that can be specified by the command line or by a config file.
+/// A `None` value means that the option is not specified.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Options {
+    pub bind: Option<Vec<IpAddr>>,
+    pub tcp_port: Option<u16>,
+    pub udp_port: Option<u16>,
+    pub players: Option<u8>,
+    pub map_size: Option<usize>,
+    pub map_generator: Option<MapGenerator>,
+    pub winner_points: Option<usize>,
+    pub arena_waiting: Option<u64>, // In seconds
+    pub tick_rate: Option<u32>,
+    pub mode: Option<GameModeKind>,
+    pub teams: Option<u8>,
+    pub friendly_fire: Option<bool>,
+    pub idle_timeout: Option<u64>, // In seconds
+    pub message_rate: Option<u32>,
+    pub no_discovery: Option<bool>,
+    pub name: Option<String>,
+    pub registry: Option<SocketAddr>,
+    pub password: Option<String>,
+    pub ban_list: Option<PathBuf>,
+    pub status_port: Option<u16>,
+    pub finish_arena_on_close: Option<bool>,
+    pub max_rooms: Option<usize>,
+}
+
+impl Options {
+    /// Options of `self`, completed with the options of `other` not specified in `self`.
+    pub fn or(self, other: Options) -> Options {
+        Options {
+            bind: self.bind.or(other.bind),
+            tcp_port: self.tcp_port.or(other.tcp_port),
+            udp_port: self.udp_port.or(other.udp_port),
+            players: self.players.or(other.players),
+            map_size: self.map_size.or(other.map_size),
+            map_generator: self.map_generator.or(other.map_generator),
+            winner_points: self.winner_points.or(other.winner_points),
+            arena_waiting: self.arena_waiting.or(other.arena_waiting),
+            tick_rate: self.tick_rate.or(other.tick_rate),
+            mode: self.mode.or(other.mode),
+            teams: self.teams.or(other.teams),
+            friendly_fire: self.friendly_fire.or(other.friendly_fire),
+            idle_timeout: self.idle_timeout.or(other.idle_timeout),
+            message_rate: self.message_rate.or(other.message_rate),
+            no_discovery: self.no_discovery.or(other.no_discovery),
+            name: self.name.or(other.name),
+            registry: self.registry.or(other.registry),
+            password: self.password.or(other.password),
+            ban_list: self.ban_list.or(other.ban_list),
+            status_port: self.status_port.or(other.status_port),
+            finish_arena_on_close: self.finish_arena_on_close.or(other.finish_arena_on_close),
+            max_rooms: self.max_rooms.or(other.max_rooms),
+        }
+    }
+
+    fn into_config(self) -> Result<Config, String> {
+        fn required<T>(value: Option<T>, name: &str) -> Result<T, String> {
+            value.ok_or_else(|| format!("The option '{}' is required", name))
+        }
+
+        fn positive<T: Default + PartialOrd>(value: T, name: &str) -> Result<T, String> {
+            match value > T::default() {
+                true => Ok(value),
+                false => Err(format!("The option '{}' must be > 0", name)),
+            }
+        }
+
+        let teams = required(self.teams, "teams")?;
+        if teams < 2 {
+            return Err("The option 'teams' must be >= 2".into())
+        }
+
+        let name = required(self.name, "name")?;
+        if !(1..=MAX_SERVER_NAME_LEN).contains(&name.chars().count()) {
+            return Err(format!("The name must have between 1 and {} characters", MAX_SERVER_NAME_LEN))
+        }
+
+        Ok(Config {
+            bind: required(self.bind, "bind")?,
+            tcp_port: required(self.tcp_port, "tcp-port")?,
+            udp_port: required(self.udp_port, "udp-port")?,
+            players_number: positive(required(self.players, "players")?, "players")?,
+            map_size: required(self.map_size, "map-size")?,
+            map_generator: required(self.map_generator, "map-generator")?,
+            winner_points: positive(required(self.winner_points, "winner-points")?, "winner-points")?,
+            arena_waiting: Duration::from_secs(required(self.arena_waiting, "arena-waiting")?),
+            tick_rate: positive(required(self.tick_rate, "tick-rate")?, "tick-rate")?,
+            mode: required(self.mode, "mode")?,
+            teams,
+            friendly_fire: required(self.friendly_fire, "friendly-fire")?,
+            idle_timeout: Duration::from_secs(
+                positive(required(self.idle_timeout, "idle-timeout")?, "idle-timeout")?
+            ),
+            message_rate: positive(required(self.message_rate, "message-rate")?, "message-rate")?,
+            discovery: !required(self.no_discovery, "no-discovery")?,
+            name,
+            registry: self.registry,
+            password: self.password,
+            ban_list: self.ban_list,
+            status_port: self.status_port,
+            finish_arena_on_close: required(self.finish_arena_on_close, "finish-arena-on-close")?,
+            max_rooms: required(self.max_rooms, "max-rooms")?,
+        })
+    }
+}
+
+/// Builds the server configuration from the command line and an optional config file.
+/// The values given explicitly by command line override the values of the file.
+pub struct ConfigLoader {
+    file: Option<PathBuf>,
+    file_modification: Option<SystemTime>,
+    explicit_options: Options,
+    default_options: Options,
+}
+
+impl ConfigLoader {
+    pub fn new(file: Option<PathBuf>, explicit_options: Options, default_options: Options) -> ConfigLoader {
+        ConfigLoader { file, file_modification: None, explicit_options, default_options }
+    }
+
+    pub fn has_file(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Checks if the config file was modified since the last load.
+    pub fn is_file_modified(&self) -> bool {
+        match &self.file {
+            Some(file) => Self::modification(file).ok() != self.file_modification,
+            None => false,
+        }
+    }
+
+    pub fn load(&mut self) -> Result<Config, String> {
+        let file_options = match &self.file {
+            Some(file) => {
+                // Recorded before reading: a write during the load will be detected in the next check.
+                self.file_modification = Self::modification(file).ok();
+                let content = std::fs::read_to_string(file)
+                    .map_err(|error| format!("Can not read {}: {}", file.display(), error))?;
+                toml::from_str(&content)
+                    .map_err(|error| format!("Invalid config file {}: {}", file.display(), error))?
+            },
+            None => Options::default(),
+        };
+
+        self.explicit_options.clone()
+            .or(file_options)
+            .or(self.default_options.clone())
+            .into_config()
+    }
+
+    fn modification(file: &PathBuf) -> std::io::Result<SystemTime> {
+        std::fs::metadata(file)?.modified()
+    }
+}
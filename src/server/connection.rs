@@ -0,0 +1,77 @@
+use super::room::{MAIN_ROOM};
+
+use crate::version::{Capabilities};
+use crate::ids::{RoomId};
+use crate::util::{TokenBucket};
+
+use std::time::{Duration, Instant};
+
+/// Information related to a client endpoint, logged or not.
+pub struct Connection {
+    latency: Option<Duration>,
+    last_activity: Instant,
+    message_bucket: TokenBucket,
+    is_throttled: bool,
+    capabilities: Option<Capabilities>,
+    room: RoomId,
+}
+
+impl Connection {
+    /// Creates a connection that accepts `message_rate` messages per second,
+    /// with bursts of twice that amount.
+    pub fn new(message_rate: u32) -> Connection {
+        Connection {
+            latency: None,
+            last_activity: Instant::now(),
+            message_bucket: TokenBucket::new(message_rate as f32 * 2.0, message_rate as f32),
+            is_throttled: false,
+            capabilities: None,
+            room: MAIN_ROOM,
+        }
+    }
+
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.latency = Some(latency);
+    }
+
+    /// Capabilities negotiated with the client. None until the version is checked.
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        self.capabilities
+    }
+
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = Some(capabilities);
+    }
+
+    /// Room where the client logs and whose server info receives.
+    pub fn room(&self) -> RoomId {
+        self.room
+    }
+
+    pub fn set_room(&mut self, room: RoomId) {
+        self.room = room;
+    }
+
+    /// Time since the last message received from this connection.
+    pub fn idle_time(&self, now: Instant) -> Duration {
+        now.duration_since(self.last_activity)
+    }
+
+    /// Registers a received message.
+    /// Returns false if the message exceeds the rate limit and should be discarded.
+    pub fn receive_message(&mut self, now: Instant) -> bool {
+        self.last_activity = now;
+        let accepted = self.message_bucket.consume(now);
+        self.is_throttled = !accepted;
+        accepted
+    }
+
+    /// The last message received exceeded the rate limit.
+    pub fn is_throttled(&self) -> bool {
+        self.is_throttled
+    }
+}
@@ -1,23 +1,34 @@
 pub mod player;
 pub mod arena;
+pub mod mode;
 
 use player::{Player};
-use arena::{Arena};
+use arena::{Arena, ArenaEvent};
+use arena::map::{MapGenerator};
+use mode::{GameMode, StepSummary};
 
 use crate::character::{Character, CharacterId, CharacterBuilder};
-use crate::message::{Terrain};
+use crate::message::{Terrain, GameModeKind};
+use crate::ids::{EntityId, TeamId};
 
 use rand::seq::{IteratorRandom};
 
 use std::collections::{HashMap, BTreeMap, BTreeSet};
 use std::rc::{Rc};
+use std::time::{Instant};
 
 pub struct Game {
     map_size: usize,
+    map_generator: MapGenerator,
     winner_points: usize,
+    mode: Box<dyn GameMode>,
+    friendly_fire: bool,
+    respawns: BTreeMap<char, Instant>, // Dead players that will appear again in the arena
 
     arena_number: usize,
     arena: Option<Arena>,
+    arena_ended: bool, // Finished before the mode decides it, as by the admin
+    entity_players: HashMap<EntityId, char>, // All the player entities of the arena, also the dead ones
 
     characters: HashMap<CharacterId, Rc<Character>>,
 
@@ -27,11 +38,16 @@ pub struct Game {
 impl Game {
     pub fn new(
         map_size: usize,
+        map_generator: MapGenerator,
         winner_points: usize,
-        player_characters: impl Iterator<Item = char>
+        mode: Box<dyn GameMode>,
+        friendly_fire: bool,
+        player_teams: impl Iterator<Item = (char, TeamId)>
     ) -> Game {
-        let characters = player_characters
-            .map(|symbol| {
+        let teams = player_teams.collect::<HashMap<_, _>>();
+        let characters = teams
+            .keys()
+            .map(|&symbol| {
                 let character = CharacterBuilder::default()
                     .id(CharacterId::Player(symbol))
                     .symbol(symbol)
@@ -48,15 +64,22 @@ impl Game {
         let players = characters
             .values()
             .map(|character|{
-                (character.symbol(), Player::new(character.clone()))
+                let team = teams[&character.symbol()];
+                (character.symbol(), Player::new(character.clone(), team))
             })
             .collect();
 
         Game {
             map_size,
+            map_generator,
             winner_points,
+            mode,
+            friendly_fire,
+            respawns: BTreeMap::new(),
             arena_number: 0,
             arena: None,
+            arena_ended: false,
+            entity_players: HashMap::new(),
             players,
             characters,
         }
@@ -78,10 +101,31 @@ impl Game {
         &self.players
     }
 
+    pub fn map_size(&self) -> usize {
+        self.map_size
+    }
+
+    pub fn mode(&self) -> GameModeKind {
+        self.mode.kind()
+    }
+
+    pub fn winner_points(&self) -> usize {
+        self.winner_points
+    }
+
+    pub fn set_winner_points(&mut self, winner_points: usize) {
+        self.winner_points = winner_points;
+    }
+
     pub fn arena_number(&self) -> usize {
         self.arena_number
     }
 
+    /// Player that owns or owned the entity in the current arena.
+    pub fn player_of_entity(&self, entity_id: EntityId) -> Option<char> {
+        self.entity_players.get(&entity_id).copied()
+    }
+
     pub fn pole(&self) -> Vec<&Player> {
         let mut sorted_players = self.players.values().collect::<Vec<_>>();
 
@@ -90,7 +134,11 @@ impl Game {
     }
 
     pub fn create_new_arena(&mut self) -> &Arena {
-        let mut arena = Arena::new(self.map_size);
+        let mut arena = Arena::new(self.map_size, self.map_generator, self.friendly_fire);
+        self.mode.arena_created(&mut arena, &self.players);
+        self.respawns.clear();
+        self.entity_players.clear();
+
         let initial_positions = arena
             .map()
             .ground()
@@ -105,26 +153,85 @@ impl Game {
             let character = player.character().clone();
             let entity = arena.create_entity(character, position);
             entity.set_behaviour(player.create_entity_behaviour(entity.id()));
+            entity.set_team(player.team());
+            self.entity_players.insert(entity.id(), player.character().symbol());
         }
 
         self.arena = Some(arena);
+        self.arena_ended = false;
         self.arena_number += 1;
         self.arena.as_ref().unwrap()
     }
 
-    pub fn step(&mut self) {
-        let living_players_before = self.living_players();
+    /// Updates the arena, scoring what happened in it. Returns the events of the arena update.
+    pub fn step(&mut self) -> Vec<ArenaEvent> {
+        // A finished arena keeps running until the next one starts, but nobody scores in it.
+        let arena_finished = self.has_arena_finished();
+        let arena = match &mut self.arena {
+            Some(arena) => arena,
+            None => return Vec::new(),
+        };
 
-        if let Some(arena) = &mut self.arena {
-            arena.update();
+        let events = arena.update();
+        if arena_finished {
+            return events
         }
 
-        let living_players_after = self.living_players();
-        let deleted_players = living_players_before.difference(&living_players_after).count();
-        if deleted_players > 0 {
-            for symbol in living_players_after {
+        // The killer is the owner of the spell, although its entity died before.
+        let entity_players = &self.entity_players;
+        let deaths = events
+            .iter()
+            .filter_map(|event| match event {
+                ArenaEvent::EntityDied { entity, killer, .. } => {
+                    let player = *entity_players.get(entity)?;
+                    Some((player, entity_players.get(killer).copied()))
+                },
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut step = StepSummary { arena, players: &self.players, deaths: &deaths };
+        for (symbol, points) in self.mode.score(&mut step) {
+            self.players.get_mut(&symbol).unwrap().add_points(points);
+        }
+
+        if let Some(respawn_time) = self.mode.respawn_time() {
+            let now = Instant::now();
+            for (symbol, _) in deaths {
+                self.respawns.insert(symbol, now + respawn_time);
+            }
+            self.respawn_players(now);
+        }
+        events
+    }
+
+    /// Places again in the arena the dead players whose respawn time has come.
+    fn respawn_players(&mut self, now: Instant) {
+        let arena = self.arena.as_mut().unwrap();
+        let ready = self.respawns
+            .iter()
+            .filter(|(_, &time)| time <= now)
+            .map(|(symbol, _)| *symbol)
+            .collect::<Vec<_>>();
+
+        for symbol in ready {
+            let position = arena
+                .map()
+                .ground()
+                .iter()
+                .enumerate()
+                .filter(|(_, &terrain)| terrain == Terrain::Floor)
+                .map(|(index, _)| arena.map().position_of(index))
+                .filter(|&position| arena.entities().values().all(|entity| entity.position() != position))
+                .choose(&mut rand::thread_rng());
+
+            if let Some(position) = position {
+                self.respawns.remove(&symbol);
                 let player = self.players.get_mut(&symbol).unwrap();
-                player.add_points(deleted_players);
+                let entity = arena.create_entity(player.character().clone(), position);
+                entity.set_behaviour(player.create_entity_behaviour(entity.id()));
+                entity.set_team(player.team());
+                self.entity_players.insert(entity.id(), symbol);
             }
         }
     }
@@ -137,10 +244,16 @@ impl Game {
             .collect()
     }
 
+    /// Finishes the current arena, nobody scores in it until the next one.
+    pub fn end_arena(&mut self) {
+        self.arena_ended = true;
+    }
+
+    pub fn has_arena_finished(&self) -> bool {
+        self.arena_ended || self.mode.has_arena_finished(&self.players)
+    }
+
     pub fn has_finished(&self) -> bool {
-        self.players
-            .values()
-            .find(|&player| player.points() >= self.winner_points)
-            .is_some()
+        self.mode.has_game_finished(&self.players, self.winner_points)
     }
 }
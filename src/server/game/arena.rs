@@ -1,37 +1,58 @@
 pub mod entity;
 pub mod map;
 pub mod spell;
+pub mod object;
 
-use map::{Map};
+use map::{Map, MapGenerator};
 use entity::{Entity, EntityAction};
 use spell::{Spell, SpellAction};
+use object::{Object};
 
 use crate::character::{Character};
-use crate::ids::{SpellId, EntityId, SpellSpecId};
+use crate::ids::{SpellId, EntityId, SpellSpecId, ObjectId};
 use crate::vec2::Vec2;
-use crate::message::Terrain;
+use crate::message::{Terrain, ObjectKind};
 
 use std::collections::{HashMap, VecDeque};
+use std::cmp::{Ordering};
 
 use std::time::{Instant};
 use std::rc::{Rc};
 
+/// What happened in an arena update, in the order it happened.
+/// The caster of a spell is its origin entity.
+#[derive(Debug, Clone)]
+pub enum ArenaEvent {
+    SpellCast { spell: SpellId, spec: SpellSpecId, caster: EntityId },
+    SpellDestroyed(SpellId),
+    EntityDamaged { entity: EntityId, caster: EntityId, spell: SpellSpecId, damage: usize },
+    EntityHealed { entity: EntityId, caster: EntityId, spell: SpellSpecId, health: usize },
+    EntityDied { entity: EntityId, killer: EntityId, spell: SpellSpecId }, // `NONE` ids without killer
+    EntityMoved { entity: EntityId, from: Vec2, to: Vec2 },
+}
+
 pub struct Arena {
     map: Map,
     entities: HashMap<EntityId, Entity>,
     spells: HashMap<SpellId, Spell>,
+    objects: HashMap<ObjectId, Object>,
+    friendly_fire: bool, // The spells damage the entities of the same team
     last_entity_id: EntityId,
     last_spell_id: SpellId,
+    last_object_id: ObjectId,
 }
 
 impl Arena {
-    pub fn new(map_size: usize) -> Arena {
+    pub fn new(map_size: usize, map_generator: MapGenerator, friendly_fire: bool) -> Arena {
         Arena {
-            map: Map::new(map_size),
+            map: Map::new(map_size, map_generator),
             entities: HashMap::new(),
             spells: HashMap::new(),
+            objects: HashMap::new(),
+            friendly_fire,
             last_entity_id: EntityId::NONE,
             last_spell_id: SpellId::NONE,
+            last_object_id: ObjectId::NONE,
         }
     }
 
@@ -39,6 +60,10 @@ impl Arena {
         &self.map
     }
 
+    pub fn map_mut(&mut self) -> &mut Map {
+        &mut self.map
+    }
+
     pub fn entities(&self) -> &HashMap<EntityId, Entity> {
         &self.entities
     }
@@ -47,6 +72,14 @@ impl Arena {
         &self.spells
     }
 
+    pub fn objects(&self) -> &HashMap<ObjectId, Object> {
+        &self.objects
+    }
+
+    pub fn object_mut(&mut self, id: ObjectId) -> Option<&mut Object> {
+        self.objects.get_mut(&id)
+    }
+
     pub fn create_entity(
         &mut self,
         character: Rc<Character>,
@@ -59,19 +92,29 @@ impl Arena {
         self.entities.get_mut(&id).unwrap()
     }
 
-    pub fn create_spell(&mut self, spec_id: SpellSpecId, entity_id: EntityId) {
+    pub fn create_object(&mut self, kind: ObjectKind, position: Vec2) -> &mut Object {
+        let id = ObjectId::next(self.last_object_id);
+        self.last_object_id = id;
+        self.objects.insert(id, Object::new(id, kind, position));
+        self.objects.get_mut(&id).unwrap()
+    }
+
+    pub fn create_spell(&mut self, spec_id: SpellSpecId, entity_id: EntityId) -> SpellId {
         let id = SpellId::next(self.last_spell_id);
         let entity = &self.entities[&entity_id];
         let spell = Spell::new(id, spec_id, &entity);
         self.last_spell_id = id;
         self.spells.insert(id, spell);
+        id
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self) -> Vec<ArenaEvent> {
         assert!(self.spells.iter().all(|(_, spell)| !spell.is_destroyed()));
         assert!(self.entities.iter().all(|(_, entity)| entity.is_alive()));
 
         let current_time = Instant::now();
+        let mut killers = HashMap::new();
+        let mut events = Vec::new();
 
         for (_, spell) in &mut self.spells {
             let mut spell_actions = VecDeque::from(
@@ -93,9 +136,32 @@ impl Arena {
                                         .behaviour()
                                         .entity_collision(&entity);
 
-                                    if affect {
+                                    // An ally absorbs the spell without damage.
+                                    if affect && (self.friendly_fire || !spell.is_ally(entity)) {
+                                        let previous_health = entity.health();
                                         entity.add_health(-spell.damage());
                                         spell.add_affected_entity(entity.id());
+
+                                        let (caster, spell_spec) = (spell.entity_origin_id(), spell.spec_id());
+                                        match entity.health().cmp(&previous_health) {
+                                            Ordering::Less => events.push(ArenaEvent::EntityDamaged {
+                                                entity: entity.id(),
+                                                caster,
+                                                spell: spell_spec,
+                                                damage: previous_health - entity.health(),
+                                            }),
+                                            Ordering::Greater => events.push(ArenaEvent::EntityHealed {
+                                                entity: entity.id(),
+                                                caster,
+                                                spell: spell_spec,
+                                                health: entity.health() - previous_health,
+                                            }),
+                                            Ordering::Equal => (),
+                                        }
+
+                                        if !entity.is_alive() {
+                                            killers.insert(entity.id(), (caster, spell_spec));
+                                        }
                                     }
 
                                     spell_actions.extend(actions);
@@ -111,6 +177,9 @@ impl Arena {
                     SpellAction::Cast(_spells) => todo!(),
                     SpellAction::Create(_entities) => todo!(),
                     SpellAction::Destroy => {
+                        if !spell.is_destroyed() {
+                            events.push(ArenaEvent::SpellDestroyed(spell.id()));
+                        }
                         spell.destroy();
                         let actions = spell.behaviour().destroyed(&spell);
                         spell_actions.extend(actions);
@@ -143,14 +212,20 @@ impl Arena {
 
                             if !occupied_position {
                                 let entity = self.entities.get_mut(&entity_id).unwrap();
-                                entity.walk(current_time);
+                                let from = entity.position();
+                                if entity.walk(current_time) {
+                                    let to = entity.position();
+                                    events.push(ArenaEvent::EntityMoved { entity: entity_id, from, to });
+                                }
                             }
                         }
                     }
                     EntityAction::Cast(direction, _skill) => {
                         let entity = self.entities.get_mut(&entity_id).unwrap();
                         entity.set_direction(direction);
-                        self.create_spell(SpellSpecId(1), entity_id);
+                        let spec = SpellSpecId(1);
+                        let spell = self.create_spell(spec, entity_id);
+                        events.push(ArenaEvent::SpellCast { spell, spec, caster: entity_id });
                     }
                     EntityAction::Destroy => {
                         let entity = self.entities.get_mut(&entity_id).unwrap();
@@ -161,7 +236,29 @@ impl Arena {
             }
         }
 
+        let deaths = self.entities
+            .values()
+            .filter(|entity| !entity.is_alive())
+            .map(|entity| {
+                let (killer, spell) = killers
+                    .get(&entity.id())
+                    .copied()
+                    .unwrap_or((EntityId::NONE, SpellSpecId::NONE));
+                ArenaEvent::EntityDied { entity: entity.id(), killer, spell }
+            })
+            .collect::<Vec<_>>();
+        events.extend(deaths);
+
+        // The carried objects follow their carriers, and are dropped where the carriers die.
+        for object in self.objects.values_mut().filter(|object| object.is_carried()) {
+            match self.entities.get(&object.carrier()) {
+                Some(entity) if entity.is_alive() => object.set_position(entity.position()),
+                _ => object.set_carrier(EntityId::NONE),
+            }
+        }
+
         self.spells.retain(|_, spell| !spell.is_destroyed());
         self.entities.retain(|_, entity| entity.is_alive());
+        events
     }
 }
@@ -3,7 +3,7 @@ use super::map::{Map};
 use crate::character::{Character};
 use crate::vec2::{Vec2};
 use crate::direction::{Direction};
-use crate::ids::{EntityId, SkillId};
+use crate::ids::{EntityId, SkillId, TeamId};
 
 use std::time::{Instant, Duration};
 use std::collections::{HashMap};
@@ -32,6 +32,7 @@ pub struct Entity {
     id: EntityId,
     character: Rc<Character>,
     behaviour: RefCell<Box<dyn EntityBehaviour>>,
+    team: TeamId,
     direction: Direction,
     position: Vec2,
     health: usize,
@@ -46,6 +47,7 @@ impl Entity {
             id,
             position,
             behaviour: RefCell::new(get_behaviour(character.id().behaviour_name())),
+            team: TeamId::NONE,
             direction: Direction::Down,
             health: character.max_health(),
             energy: character.max_energy(),
@@ -71,6 +73,14 @@ impl Entity {
         self.behaviour = RefCell::new(behaviour);
     }
 
+    pub fn team(&self) -> TeamId {
+        self.team
+    }
+
+    pub fn set_team(&mut self, team: TeamId) {
+        self.team = team;
+    }
+
     pub fn health(&self) -> usize {
         self.health
     }
@@ -1,20 +1,44 @@
 use crate::vec2::Vec2;
 use crate::message::Terrain;
 
+use serde::{Deserialize};
+
+use std::str::{FromStr};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MapGenerator {
+    Empty,   // Only the border walls
+    Pillars, // Border walls and a grid of wall blocks
+}
+
+impl FromStr for MapGenerator {
+    type Err = String;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "empty" => Ok(MapGenerator::Empty),
+            "pillars" => Ok(MapGenerator::Pillars),
+            _ => Err(format!("Unknown map generator '{}'", value)),
+        }
+    }
+}
+
 pub struct Map {
     size: usize,
     ground: Vec<Terrain>,
 }
 
 impl Map {
-    pub fn new(size: usize) -> Map {
+    const PILLARS_SEPARATION: usize = 4;
+
+    pub fn new(size: usize, generator: MapGenerator) -> Map {
         Map {
             size,
-            ground: Self::build_ground(size, 0),
+            ground: Self::build_ground(size, generator),
         }
     }
 
-    fn build_ground(size: usize, _seed: usize) -> Vec<Terrain> {
+    fn build_ground(size: usize, generator: MapGenerator) -> Vec<Terrain> {
         (0..size * size).map(|index|{
             let x = index % size;
             let y = index / size;
@@ -22,6 +46,12 @@ impl Map {
             if x == 0 || y == 0 || x == size - 1 || y == size - 1 {
                 Terrain::Wall
             }
+            else if generator == MapGenerator::Pillars
+            && x.is_multiple_of(Self::PILLARS_SEPARATION)
+            && y.is_multiple_of(Self::PILLARS_SEPARATION)
+            && x < size - 2 && y < size - 2 {
+                Terrain::Wall
+            }
             else {
                 Terrain::Floor
             }
@@ -39,6 +69,16 @@ impl Map {
         self.ground[position.y as usize* self.size + position.x as usize]
     }
 
+    pub fn set_terrain(&mut self, position: Vec2, terrain: Terrain) {
+        assert!(position.x >= 0 && position.x < self.size as i32);
+        assert!(position.y >= 0 && position.y < self.size as i32);
+        self.ground[position.y as usize* self.size + position.x as usize] = terrain;
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
     pub fn position_of(&self, index: usize) -> Vec2 {
         assert!(index < self.size * self.size);
         Vec2::xy((index % self.size) as i32, (index / self.size) as i32)
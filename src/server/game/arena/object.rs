@@ -0,0 +1,51 @@
+use crate::message::{ObjectKind};
+use crate::vec2::{Vec2};
+use crate::ids::{ObjectId, EntityId};
+
+/// Element of the arena that the entities can walk over and carry.
+pub struct Object {
+    id: ObjectId,
+    kind: ObjectKind,
+    position: Vec2,
+    carrier: EntityId,
+}
+
+impl Object {
+    pub fn new(id: ObjectId, kind: ObjectKind, position: Vec2) -> Object {
+        Object {
+            id,
+            kind,
+            position,
+            carrier: EntityId::NONE,
+        }
+    }
+
+    pub fn id(&self) -> ObjectId {
+        self.id
+    }
+
+    pub fn kind(&self) -> ObjectKind {
+        self.kind
+    }
+
+    pub fn position(&self) -> Vec2 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Vec2) {
+        self.position = position;
+    }
+
+    /// Entity that moves the object with it, `EntityId::NONE` if nobody carries it.
+    pub fn carrier(&self) -> EntityId {
+        self.carrier
+    }
+
+    pub fn set_carrier(&mut self, carrier: EntityId) {
+        self.carrier = carrier;
+    }
+
+    pub fn is_carried(&self) -> bool {
+        self.carrier != EntityId::NONE
+    }
+}
@@ -3,7 +3,7 @@ use super::map::{Map};
 
 use crate::vec2::{Vec2};
 use crate::direction::{Direction};
-use crate::ids::{EntityId, SpellId, SpellSpecId};
+use crate::ids::{EntityId, SpellId, SpellSpecId, TeamId};
 use crate::specification::spells::{SPELL_SPECIFICATIONS};
 
 use std::time::{Instant, Duration};
@@ -35,6 +35,7 @@ pub struct Spell {
     id: SpellId,
     spec_id: SpellSpecId,
     entity_origin_id: EntityId,
+    team: TeamId, // Of the origin entity
     behaviour: RefCell<Box<dyn SpellBehaviour>>,
     damage: i32,
     //effects
@@ -53,6 +54,7 @@ impl Spell {
             id,
             spec_id: spec_id,
             entity_origin_id: entity.id(),
+            team: entity.team(),
             behaviour: RefCell::new(get_behaviour(spec.behaviour_name)),
             damage: spec.damage, /* Mul to entity effects */
             position: entity.position() + entity.direction().to_vec2(),
@@ -76,6 +78,11 @@ impl Spell {
         self.entity_origin_id
     }
 
+    /// The entity is of the same team than the entity that cast the spell.
+    pub fn is_ally(&self, entity: &Entity) -> bool {
+        self.team != TeamId::NONE && self.team == entity.team()
+    }
+
     pub fn damage(&self) -> i32 {
         self.damage
     }
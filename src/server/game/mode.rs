@@ -0,0 +1,469 @@
+use super::player::{Player};
+use super::arena::{Arena};
+use super::arena::map::{Map};
+
+use crate::message::{GameModeKind, Terrain, ObjectKind};
+use crate::vec2::{Vec2};
+use crate::ids::{TeamId, ObjectId, EntityId};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{Instant, Duration};
+
+/// Duration of the arenas in the modes with respawns.
+const ROUND_DURATION: Duration = Duration::from_secs(60);
+
+/// Time that a dead player waits to appear again in the modes with respawns.
+const RESPAWN_TIME: Duration = Duration::from_secs(3);
+
+/// Time that a player must hold the hill alone to score a point.
+const HILL_POINT_TIME: Duration = Duration::from_secs(2);
+
+/// Distance from the center of the hill to its border.
+const HILL_RADIUS: i32 = 1;
+
+/// Time that a dropped flag waits on the ground before returning to its base.
+const FLAG_RETURN_TIME: Duration = Duration::from_secs(10);
+
+/// Distance from the map border to the bases.
+const BASE_MARGIN: i32 = 2;
+
+/// What happened in a game step, given to the mode to score it.
+pub struct StepSummary<'a> {
+    pub arena: &'a mut Arena, // Its objects can be changed by the mode
+    pub players: &'a BTreeMap<char, Player>,
+    pub deaths: &'a [(char, Option<char>)], // Dead player, killer player
+}
+
+/// Rules of a game: how the players score and when the arenas and the game finish.
+pub trait GameMode {
+    fn kind(&self) -> GameModeKind;
+
+    /// Prepares a new arena before placing the players in it.
+    fn arena_created(&mut self, _arena: &mut Arena, _players: &BTreeMap<char, Player>) {}
+
+    /// Time to appear again in the same arena after dying. None if the dead players wait for
+    /// the next arena.
+    fn respawn_time(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Points earned by each player in the step. The mode can also move the arena objects.
+    fn score(&mut self, step: &mut StepSummary) -> Vec<(char, usize)>;
+
+    fn has_arena_finished(&self, players: &BTreeMap<char, Player>) -> bool;
+
+    fn has_game_finished(&self, players: &BTreeMap<char, Player>, winner_points: usize) -> bool {
+        players.values().any(|player| player.points() >= winner_points)
+    }
+}
+
+pub fn create(kind: GameModeKind) -> Box<dyn GameMode> {
+    match kind {
+        GameModeKind::Survival => Box::new(Survival),
+        GameModeKind::Deathmatch => Box::new(Deathmatch),
+        GameModeKind::TimedRounds => Box::new(TimedRounds { round_start: Instant::now() }),
+        GameModeKind::KingOfTheHill => Box::new(KingOfTheHill {
+            round_start: Instant::now(),
+            holder: None,
+        }),
+        GameModeKind::TeamDeathmatch => Box::new(TeamDeathmatch),
+        GameModeKind::CaptureTheFlag => Box::new(CaptureTheFlag {
+            round_start: Instant::now(),
+            flags: HashMap::new(),
+        }),
+    }
+}
+
+/// Distributes the players into the teams, numbered from 1.
+/// The chosen teams are respected while they are not bigger than the others.
+/// The rest of players are added to the smallest teams.
+pub fn assign_teams(players: &[char], choices: &HashMap<char, TeamId>, teams: u8) -> Vec<(char, TeamId)> {
+    let team_ids = (1..=teams as usize).map(TeamId).collect::<Vec<_>>();
+    let max_members = players.len().div_ceil(team_ids.len());
+    let mut members = team_ids.iter().map(|&team| (team, 0)).collect::<HashMap<_, _>>();
+
+    let mut assigned = HashMap::new();
+    for player in players {
+        if let Some(team) = choices.get(player) {
+            if let Some(count) = members.get_mut(team).filter(|count| **count < max_members) {
+                *count += 1;
+                assigned.insert(*player, *team);
+            }
+        }
+    }
+
+    players
+        .iter()
+        .map(|player| match assigned.get(player) {
+            Some(team) => (*player, *team),
+            None => {
+                let team = *team_ids.iter().min_by_key(|team| (members[team], team.0)).unwrap();
+                *members.get_mut(&team).unwrap() += 1;
+                (*player, team)
+            },
+        })
+        .collect()
+}
+
+fn living_players(players: &BTreeMap<char, Player>) -> usize {
+    players.values().filter(|player| player.is_alive()).count()
+}
+
+/// Symbols of the players of a team.
+fn team_players(players: &BTreeMap<char, Player>, team: TeamId) -> impl Iterator<Item = char> + '_ {
+    players
+        .values()
+        .filter(move |player| player.team() == team)
+        .map(|player| player.character().symbol())
+}
+
+/// Floor position closest to a point of the map.
+fn closest_floor(map: &Map, position: Vec2) -> Option<Vec2> {
+    (0..map.size() * map.size())
+        .map(|index| map.position_of(index))
+        .filter(|&floor| map.terrain(floor) == Terrain::Floor)
+        .min_by_key(|&floor| (floor - position).square_length())
+}
+
+/// One point to the killer of each player.
+fn kill_points(step: &StepSummary) -> Vec<(char, usize)> {
+    step.deaths
+        .iter()
+        .filter_map(|&(dead, killer)| killer.filter(|&killer| killer != dead))
+        .map(|killer| (killer, 1))
+        .collect()
+}
+
+/// Last man standing: the survivors earn a point by each death.
+pub struct Survival;
+
+impl GameMode for Survival {
+    fn kind(&self) -> GameModeKind {
+        GameModeKind::Survival
+    }
+
+    fn score(&mut self, step: &mut StepSummary) -> Vec<(char, usize)> {
+        if step.deaths.is_empty() {
+            return Vec::new()
+        }
+
+        step.players
+            .values()
+            .filter(|player| player.is_alive())
+            .map(|player| (player.character().symbol(), step.deaths.len()))
+            .collect()
+    }
+
+    fn has_arena_finished(&self, players: &BTreeMap<char, Player>) -> bool {
+        living_players(players) <= 1
+    }
+}
+
+/// The killer of a player earns a point. The arena finishes with one player alive.
+pub struct Deathmatch;
+
+impl GameMode for Deathmatch {
+    fn kind(&self) -> GameModeKind {
+        GameModeKind::Deathmatch
+    }
+
+    fn score(&mut self, step: &mut StepSummary) -> Vec<(char, usize)> {
+        kill_points(step)
+    }
+
+    fn has_arena_finished(&self, players: &BTreeMap<char, Player>) -> bool {
+        living_players(players) <= 1
+    }
+}
+
+/// Deathmatch where the dead players respawn until the round time runs out.
+pub struct TimedRounds {
+    round_start: Instant,
+}
+
+impl GameMode for TimedRounds {
+    fn kind(&self) -> GameModeKind {
+        GameModeKind::TimedRounds
+    }
+
+    fn arena_created(&mut self, _arena: &mut Arena, _players: &BTreeMap<char, Player>) {
+        self.round_start = Instant::now();
+    }
+
+    fn respawn_time(&self) -> Option<Duration> {
+        Some(RESPAWN_TIME)
+    }
+
+    fn score(&mut self, step: &mut StepSummary) -> Vec<(char, usize)> {
+        kill_points(step)
+    }
+
+    fn has_arena_finished(&self, _players: &BTreeMap<char, Player>) -> bool {
+        self.round_start.elapsed() >= ROUND_DURATION
+    }
+}
+
+/// The player that holds the hill of the map center without other players earns points.
+/// The dead players respawn until the round time runs out.
+pub struct KingOfTheHill {
+    round_start: Instant,
+    holder: Option<(char, Instant)>, // Player alone in the hill, since when it scores
+}
+
+impl GameMode for KingOfTheHill {
+    fn kind(&self) -> GameModeKind {
+        GameModeKind::KingOfTheHill
+    }
+
+    fn arena_created(&mut self, arena: &mut Arena, _players: &BTreeMap<char, Player>) {
+        self.round_start = Instant::now();
+        self.holder = None;
+
+        // The hill is placed around the floor position closest to the center.
+        let map = arena.map();
+        let center = Vec2::xy(map.size() as i32 / 2, map.size() as i32 / 2);
+        if let Some(hill_center) = closest_floor(map, center) {
+            for y in -HILL_RADIUS..=HILL_RADIUS {
+                for x in -HILL_RADIUS..=HILL_RADIUS {
+                    let position = hill_center + Vec2::xy(x, y);
+                    if arena.map().terrain(position) == Terrain::Floor {
+                        arena.map_mut().set_terrain(position, Terrain::Hill);
+                    }
+                }
+            }
+        }
+    }
+
+    fn respawn_time(&self) -> Option<Duration> {
+        Some(RESPAWN_TIME)
+    }
+
+    fn score(&mut self, step: &mut StepSummary) -> Vec<(char, usize)> {
+        let kings = step.players
+            .values()
+            .filter_map(|player| step.arena.entities().get(&player.entity_id()))
+            .filter(|entity| step.arena.map().terrain(entity.position()) == Terrain::Hill)
+            .map(|entity| entity.character().symbol())
+            .collect::<Vec<_>>();
+
+        let now = Instant::now();
+        match (kings.as_slice(), self.holder) {
+            ([king], Some((holder, since))) if *king == holder => {
+                if now.duration_since(since) >= HILL_POINT_TIME {
+                    self.holder = Some((holder, now));
+                    return vec![(holder, 1)]
+                }
+            },
+            ([king], _) => self.holder = Some((*king, now)),
+            _ => self.holder = None, // Empty or contested
+        }
+        Vec::new()
+    }
+
+    fn has_arena_finished(&self, _players: &BTreeMap<char, Player>) -> bool {
+        self.round_start.elapsed() >= ROUND_DURATION
+    }
+}
+
+/// The team of the killer earns a point by each rival killed, shared by all its players.
+/// The arena finishes when only players of one team are alive.
+pub struct TeamDeathmatch;
+
+impl GameMode for TeamDeathmatch {
+    fn kind(&self) -> GameModeKind {
+        GameModeKind::TeamDeathmatch
+    }
+
+    fn score(&mut self, step: &mut StepSummary) -> Vec<(char, usize)> {
+        let team_of = |symbol: char| step.players[&symbol].team();
+        step.deaths
+            .iter()
+            .filter_map(|&(dead, killer)| killer.filter(|&killer| team_of(killer) != team_of(dead)))
+            .flat_map(|killer| team_players(step.players, team_of(killer)).map(|symbol| (symbol, 1)))
+            .collect()
+    }
+
+    fn has_arena_finished(&self, players: &BTreeMap<char, Player>) -> bool {
+        let living_teams = players
+            .values()
+            .filter(|player| player.is_alive())
+            .map(|player| player.team())
+            .collect::<HashSet<_>>();
+
+        living_teams.len() <= 1
+    }
+}
+
+struct Flag {
+    object: ObjectId,
+    base: Vec2,
+    dropped_since: Option<Instant>, // Out of its base without carrier
+}
+
+/// Each team has a flag in its base. The team that carries a rival flag to its own base
+/// earns a point, shared by all its players. The carrier drops the flag when it dies,
+/// and a dropped flag returns to its base after a while or when a player of its team touches it.
+/// The dead players respawn until the round time runs out.
+pub struct CaptureTheFlag {
+    round_start: Instant,
+    flags: HashMap<TeamId, Flag>,
+}
+
+impl GameMode for CaptureTheFlag {
+    fn kind(&self) -> GameModeKind {
+        GameModeKind::CaptureTheFlag
+    }
+
+    fn arena_created(&mut self, arena: &mut Arena, players: &BTreeMap<char, Player>) {
+        self.round_start = Instant::now();
+        self.flags.clear();
+
+        let mut teams = players.values().map(|player| player.team()).collect::<Vec<_>>();
+        teams.sort_by_key(|team| team.0);
+        teams.dedup();
+
+        // The bases are spread in a circle around the center, the first one at the left.
+        let size = arena.map().size() as i32;
+        let center = Vec2::xy(size / 2, size / 2);
+        let radius = (size / 2 - BASE_MARGIN) as f32;
+        for (index, &team) in teams.iter().enumerate() {
+            let angle = std::f32::consts::PI * (1.0 + 2.0 * index as f32 / teams.len() as f32);
+            let target = center + Vec2::xy(
+                (radius * angle.cos()).round() as i32,
+                (radius * angle.sin()).round() as i32,
+            );
+
+            if let Some(base) = closest_floor(arena.map(), target) {
+                arena.create_object(ObjectKind::Base(team), base);
+                let object = arena.create_object(ObjectKind::Flag(team), base).id();
+                self.flags.insert(team, Flag { object, base, dropped_since: None });
+            }
+        }
+    }
+
+    fn respawn_time(&self) -> Option<Duration> {
+        Some(RESPAWN_TIME)
+    }
+
+    fn score(&mut self, step: &mut StepSummary) -> Vec<(char, usize)> {
+        let bases = self.flags
+            .iter()
+            .map(|(&team, flag)| (team, flag.base))
+            .collect::<HashMap<_, _>>();
+
+        let now = Instant::now();
+        let mut points = Vec::new();
+        for (&team, flag) in &mut self.flags {
+            let object = &step.arena.objects()[&flag.object];
+            let (position, carrier) = (object.position(), object.carrier());
+
+            if let Some(carrier) = step.arena.entities().get(&carrier) {
+                if bases.get(&carrier.team()) == Some(&carrier.position()) {
+                    points.extend(team_players(step.players, carrier.team()).map(|symbol| (symbol, 1)));
+                    Self::return_flag(step.arena, flag);
+                }
+                continue
+            }
+
+            let toucher = step.arena
+                .entities()
+                .values()
+                .find(|entity| entity.position() == position)
+                .map(|entity| (entity.id(), entity.team()));
+
+            match toucher {
+                Some((_, toucher_team)) if toucher_team == team && position != flag.base => {
+                    Self::return_flag(step.arena, flag);
+                },
+                Some((_, toucher_team)) if toucher_team == team => (), // Guarding it in the base
+                Some((toucher_id, _)) => {
+                    step.arena.object_mut(flag.object).unwrap().set_carrier(toucher_id);
+                    flag.dropped_since = None;
+                },
+                None if position != flag.base => {
+                    let dropped_since = *flag.dropped_since.get_or_insert(now);
+                    if now.duration_since(dropped_since) >= FLAG_RETURN_TIME {
+                        Self::return_flag(step.arena, flag);
+                    }
+                },
+                None => (),
+            }
+        }
+        points
+    }
+
+    fn has_arena_finished(&self, _players: &BTreeMap<char, Player>) -> bool {
+        self.round_start.elapsed() >= ROUND_DURATION
+    }
+}
+
+impl CaptureTheFlag {
+    fn return_flag(arena: &mut Arena, flag: &mut Flag) {
+        let object = arena.object_mut(flag.object).unwrap();
+        object.set_carrier(EntityId::NONE);
+        object.set_position(flag.base);
+        flag.dropped_since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn team_of(assigned: &[(char, TeamId)], player: char) -> TeamId {
+        assigned.iter().find(|(symbol, _)| *symbol == player).unwrap().1
+    }
+
+    fn members(assigned: &[(char, TeamId)], team: TeamId) -> usize {
+        assigned.iter().filter(|(_, assigned_team)| *assigned_team == team).count()
+    }
+
+    #[test]
+    fn assign_teams_balanced_without_choices() {
+        let assigned = assign_teams(&['A', 'B', 'C', 'D', 'E'], &HashMap::new(), 2);
+        assert_eq!(assigned.len(), 5);
+        assert_eq!(members(&assigned, TeamId(1)), 3);
+        assert_eq!(members(&assigned, TeamId(2)), 2);
+
+        // Ties are broken by the lowest team.
+        assert_eq!(team_of(&assigned, 'A'), TeamId(1));
+        assert_eq!(team_of(&assigned, 'B'), TeamId(2));
+    }
+
+    #[test]
+    fn assign_teams_respects_choices() {
+        let choices = vec![('A', TeamId(2)), ('B', TeamId(2))].into_iter().collect();
+        let assigned = assign_teams(&['A', 'B', 'C', 'D'], &choices, 2);
+        assert_eq!(team_of(&assigned, 'A'), TeamId(2));
+        assert_eq!(team_of(&assigned, 'B'), TeamId(2));
+        assert_eq!(team_of(&assigned, 'C'), TeamId(1));
+        assert_eq!(team_of(&assigned, 'D'), TeamId(1));
+    }
+
+    #[test]
+    fn assign_teams_full_chosen_team() {
+        let choices = vec![('A', TeamId(1)), ('B', TeamId(1)), ('C', TeamId(1))].into_iter().collect();
+        let assigned = assign_teams(&['A', 'B', 'C', 'D'], &choices, 2);
+        assert_eq!(team_of(&assigned, 'A'), TeamId(1));
+        assert_eq!(team_of(&assigned, 'B'), TeamId(1));
+        assert_eq!(team_of(&assigned, 'C'), TeamId(2));
+        assert_eq!(team_of(&assigned, 'D'), TeamId(2));
+    }
+
+    #[test]
+    fn assign_teams_ignores_unknown_teams() {
+        let choices = vec![('A', TeamId(3)), ('B', TeamId::NONE)].into_iter().collect();
+        let assigned = assign_teams(&['A', 'B', 'C'], &choices, 2);
+        assert!(assigned.iter().all(|(_, team)| *team == TeamId(1) || *team == TeamId(2)));
+        assert_eq!(members(&assigned, TeamId(1)), 2);
+        assert_eq!(members(&assigned, TeamId(2)), 1);
+    }
+
+    #[test]
+    fn assign_teams_more_teams_than_players() {
+        let choices = vec![('B', TeamId(3))].into_iter().collect();
+        let assigned = assign_teams(&['A', 'B'], &choices, 4);
+        assert_eq!(team_of(&assigned, 'A'), TeamId(1));
+        assert_eq!(team_of(&assigned, 'B'), TeamId(3));
+    }
+}
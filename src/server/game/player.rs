@@ -3,7 +3,7 @@ use super::arena::map::{Map};
 
 use crate::character::{Character};
 use crate::direction::{Direction};
-use crate::ids::{SkillId, EntityId};
+use crate::ids::{SkillId, EntityId, TeamId};
 
 use std::rc::{Rc};
 use std::time::{Instant};
@@ -13,6 +13,7 @@ use std::collections::{HashMap};
 pub struct Player {
     character: Rc<Character>,
     entity_handler: Rc<RefCell<EntityHandler>>,
+    team: TeamId,
     points: usize,
 }
 
@@ -21,10 +22,11 @@ impl Player {
     pub const MAX_ENERGY: usize = 100;
     pub const SPEED_BASE: f32 = 8.0;
 
-    pub fn new(character: Rc<Character>) -> Player {
+    pub fn new(character: Rc<Character>, team: TeamId) -> Player {
         Player {
             character,
             entity_handler: Rc::new(RefCell::new(EntityHandler::default())),
+            team,
             points: 0,
         }
     }
@@ -37,6 +39,10 @@ impl Player {
         self.entity_handler.borrow().entity_id
     }
 
+    pub fn team(&self) -> TeamId {
+        self.team
+    }
+
     pub fn points(&self) -> usize {
         self.points
     }
@@ -46,11 +52,11 @@ impl Player {
     }
 
     pub fn walk(&mut self, direction: Direction) {
-        self.entity_handler.borrow_mut().actions.push(EntityAction::Walk(direction))
+        self.entity_handler.borrow_mut().push_action(EntityAction::Walk(direction))
     }
 
     pub fn cast(&mut self, direction: Direction, id: SkillId) {
-        self.entity_handler.borrow_mut().actions.push(EntityAction::Cast(direction, id))
+        self.entity_handler.borrow_mut().push_action(EntityAction::Cast(direction, id))
     }
 
     pub fn add_points(&mut self, points: usize) {
@@ -69,6 +75,20 @@ pub struct EntityHandler {
     actions: Vec<EntityAction>,
 }
 
+impl EntityHandler {
+    /// Actions that can be queued between two updates. The exceeding ones are discarded.
+    const MAX_ACTIONS: usize = 8;
+
+    fn push_action(&mut self, action: EntityAction) {
+        if self.actions.len() < Self::MAX_ACTIONS {
+            self.actions.push(action);
+        }
+        else {
+            log::trace!("Action queue of entity {:?} full. Action discarded", self.entity_id);
+        }
+    }
+}
+
 pub struct PlayerBehaviour {
     entity_handler: Rc<RefCell<EntityHandler>>
 }
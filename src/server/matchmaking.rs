@@ -0,0 +1,289 @@
+use crate::message::{QueuePreferences};
+
+use message_io::network::{Endpoint};
+
+use std::collections::{HashMap};
+use std::time::{Duration, Instant};
+
+/// Rating of a character before its first game.
+const INITIAL_RATING: i32 = 1000;
+
+/// Maximum rating change of a character against each opponent of a game.
+const RATING_FACTOR: f32 = 32.0;
+
+/// Rating difference allowed between the clients of a match.
+/// It grows while they wait, to not wait forever for a similar opponent.
+const BASE_RATING_GAP: i32 = 100;
+const RATING_GAP_GROWTH: i32 = 50;
+const RATING_GAP_GROWTH_PERIOD: Duration = Duration::from_secs(5);
+
+struct QueueEntry {
+    endpoint: Endpoint,
+    character: char,
+    preferences: QueuePreferences,
+    since: Instant,
+}
+
+/// Clients grouped by the queue, all of them with the same preferences.
+pub struct Match {
+    pub players: Vec<(Endpoint, char)>,
+    pub preferences: QueuePreferences,
+}
+
+/// Queue of the clients waiting for a game, and the skill ratings of the characters.
+/// The ratings only live while the server is running.
+pub struct Matchmaking {
+    queue: Vec<QueueEntry>, // The oldest first
+    ratings: HashMap<char, i32>,
+}
+
+impl Matchmaking {
+    pub fn new() -> Matchmaking {
+        Matchmaking {
+            queue: Vec::new(),
+            ratings: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Rating of the character, None if it has not finished any game.
+    pub fn rating(&self, character: char) -> Option<i32> {
+        self.ratings.get(&character).copied()
+    }
+
+    /// Adds the client to the queue, replacing its previous request.
+    /// Returns the number of clients waiting for the same players number.
+    pub fn join(&mut self, endpoint: Endpoint, character: char, preferences: QueuePreferences) -> usize {
+        self.leave(endpoint);
+        self.queue.push(QueueEntry { endpoint, character, preferences, since: Instant::now() });
+        self.queue
+            .iter()
+            .filter(|entry| entry.preferences.players_number == preferences.players_number)
+            .count()
+    }
+
+    /// Returns false if the client was not in the queue.
+    pub fn leave(&mut self, endpoint: Endpoint) -> bool {
+        let previous_len = self.queue.len();
+        self.queue.retain(|entry| entry.endpoint != endpoint);
+        self.queue.len() < previous_len
+    }
+
+    /// Takes from the queue a group of clients with compatible preferences and similar ratings.
+    /// The oldest clients are matched first.
+    /// The clients without rating can be matched with anyone.
+    pub fn next_match(&mut self, now: Instant) -> Option<Match> {
+        let group = self.queue.iter().enumerate().find_map(|(anchor_index, anchor)| {
+            let players_number = anchor.preferences.players_number as usize;
+            let waiting = now.duration_since(anchor.since);
+            let max_gap = BASE_RATING_GAP
+                + RATING_GAP_GROWTH * (waiting.as_secs() / RATING_GAP_GROWTH_PERIOD.as_secs()) as i32;
+
+            let anchor_rating = self.rating(anchor.character);
+            let mut candidates = self.queue
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != anchor_index)
+                .filter(|(_, entry)| {
+                    entry.preferences.players_number == anchor.preferences.players_number
+                        && entry.preferences.map_size.is_none_or(|size| {
+                            Some(size) == anchor.preferences.map_size
+                        })
+                })
+                .map(|(index, entry)| {
+                    let gap = anchor_rating
+                        .zip(self.rating(entry.character))
+                        .map(|(anchor_rating, rating)| (anchor_rating - rating).abs());
+                    (index, entry, gap)
+                })
+                .filter(|(_, _, gap)| gap.is_none_or(|gap| gap <= max_gap))
+                .collect::<Vec<_>>();
+
+            // The closest ratings first, then the unknown ones.
+            candidates.sort_by_key(|(index, _, gap)| (gap.is_none(), gap.unwrap_or(0), *index));
+
+            let mut group = vec![anchor_index];
+            let mut characters = vec![anchor.character];
+            for (index, entry, _) in candidates {
+                if group.len() == players_number {
+                    break
+                }
+                // The characters of a game must be different.
+                if !characters.contains(&entry.character) {
+                    group.push(index);
+                    characters.push(entry.character);
+                }
+            }
+
+            Some(group).filter(|group| group.len() == players_number)
+        })?;
+
+        let preferences = self.queue[group[0]].preferences;
+        let mut players = group
+            .iter()
+            .map(|&index| (self.queue[index].endpoint, self.queue[index].character))
+            .collect::<Vec<_>>();
+
+        players.sort_by_key(|(_, character)| *character);
+        self.queue.retain(|entry| !players.iter().any(|(endpoint, _)| *endpoint == entry.endpoint));
+
+        Some(Match { players, preferences })
+    }
+
+    /// Updates the ratings with the result of a game, given the points of each character.
+    /// Each character is compared with each opponent: more points is a win, equal points a draw.
+    pub fn record_game(&mut self, points: &[(char, usize)]) {
+        if points.len() < 2 {
+            return
+        }
+
+        let factor = RATING_FACTOR / (points.len() - 1) as f32;
+        let changes = points
+            .iter()
+            .map(|&(character, character_points)| {
+                let rating = self.rating(character).unwrap_or(INITIAL_RATING);
+                let change = points
+                    .iter()
+                    .filter(|(opponent, _)| *opponent != character)
+                    .map(|&(opponent, opponent_points)| {
+                        let opponent_rating = self.rating(opponent).unwrap_or(INITIAL_RATING);
+                        let expected = 1.0 / (1.0 + 10f32.powf((opponent_rating - rating) as f32 / 400.0));
+                        let score = match character_points.cmp(&opponent_points) {
+                            std::cmp::Ordering::Greater => 1.0,
+                            std::cmp::Ordering::Equal => 0.5,
+                            std::cmp::Ordering::Less => 0.0,
+                        };
+                        factor * (score - expected)
+                    })
+                    .sum::<f32>();
+                (character, rating + change.round() as i32)
+            })
+            .collect::<Vec<_>>();
+
+        self.ratings.extend(changes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use message_io::network::{Network, NetEvent};
+
+    /// Distinct endpoints, alive while the returned network is alive.
+    fn endpoints(number: usize) -> (Network, Vec<Endpoint>) {
+        let mut network = Network::new(|_: NetEvent<()>| ());
+        let endpoints = (0..number)
+            .map(|_| network.connect_udp("127.0.0.1:3000").unwrap())
+            .collect();
+        (network, endpoints)
+    }
+
+    fn preferences(players_number: u8, map_size: Option<u16>) -> QueuePreferences {
+        QueuePreferences { players_number, map_size }
+    }
+
+    #[test]
+    fn match_same_players_number() {
+        let (_network, endpoints) = endpoints(3);
+        let mut matchmaking = Matchmaking::new();
+        matchmaking.join(endpoints[0], 'A', preferences(2, None));
+        matchmaking.join(endpoints[1], 'B', preferences(3, None));
+        assert!(matchmaking.next_match(Instant::now()).is_none());
+
+        matchmaking.join(endpoints[2], 'C', preferences(2, None));
+        let found = matchmaking.next_match(Instant::now()).unwrap();
+        assert_eq!(found.players, vec![(endpoints[0], 'A'), (endpoints[2], 'C')]);
+        assert_eq!(found.preferences, preferences(2, None));
+        assert_eq!(matchmaking.len(), 1);
+    }
+
+    #[test]
+    fn match_any_map_size_with_a_given_one() {
+        let (_network, endpoints) = endpoints(3);
+        let mut matchmaking = Matchmaking::new();
+        matchmaking.join(endpoints[0], 'A', preferences(2, Some(20)));
+        matchmaking.join(endpoints[1], 'B', preferences(2, Some(30)));
+        assert!(matchmaking.next_match(Instant::now()).is_none());
+
+        // The match uses the size of the client that gives it.
+        matchmaking.join(endpoints[2], 'C', preferences(2, None));
+        let found = matchmaking.next_match(Instant::now()).unwrap();
+        assert_eq!(found.players, vec![(endpoints[0], 'A'), (endpoints[2], 'C')]);
+        assert_eq!(found.preferences, preferences(2, Some(20)));
+    }
+
+    #[test]
+    fn rating_gap_grows_while_waiting() {
+        let (_network, endpoints) = endpoints(2);
+        let mut matchmaking = Matchmaking::new();
+        matchmaking.ratings.insert('A', INITIAL_RATING);
+        matchmaking.ratings.insert('B', INITIAL_RATING + BASE_RATING_GAP + RATING_GAP_GROWTH);
+        matchmaking.join(endpoints[0], 'A', preferences(2, None));
+        matchmaking.join(endpoints[1], 'B', preferences(2, None));
+
+        let now = Instant::now();
+        assert!(matchmaking.next_match(now).is_none());
+        assert!(matchmaking.next_match(now + RATING_GAP_GROWTH_PERIOD).is_some());
+    }
+
+    #[test]
+    fn match_unrated_with_anyone() {
+        let (_network, endpoints) = endpoints(3);
+        let mut matchmaking = Matchmaking::new();
+        matchmaking.ratings.insert('A', INITIAL_RATING);
+        matchmaking.ratings.insert('B', INITIAL_RATING + 10 * BASE_RATING_GAP);
+        matchmaking.join(endpoints[0], 'A', preferences(2, None));
+        matchmaking.join(endpoints[1], 'B', preferences(2, None));
+        matchmaking.join(endpoints[2], 'C', preferences(2, None));
+
+        let found = matchmaking.next_match(Instant::now()).unwrap();
+        assert_eq!(found.players, vec![(endpoints[0], 'A'), (endpoints[2], 'C')]);
+    }
+
+    #[test]
+    fn match_different_characters() {
+        let (_network, endpoints) = endpoints(3);
+        let mut matchmaking = Matchmaking::new();
+        matchmaking.join(endpoints[0], 'A', preferences(2, None));
+        matchmaking.join(endpoints[1], 'A', preferences(2, None));
+        assert!(matchmaking.next_match(Instant::now()).is_none());
+
+        matchmaking.join(endpoints[2], 'B', preferences(2, None));
+        let found = matchmaking.next_match(Instant::now()).unwrap();
+        assert_eq!(found.players, vec![(endpoints[0], 'A'), (endpoints[2], 'B')]);
+        assert_eq!(matchmaking.len(), 1);
+    }
+
+    #[test]
+    fn rejoin_replaces_the_request() {
+        let (_network, endpoints) = endpoints(1);
+        let mut matchmaking = Matchmaking::new();
+        assert_eq!(matchmaking.join(endpoints[0], 'A', preferences(2, None)), 1);
+        assert_eq!(matchmaking.join(endpoints[0], 'B', preferences(2, None)), 1);
+        assert!(matchmaking.leave(endpoints[0]));
+        assert!(!matchmaking.leave(endpoints[0]));
+    }
+
+    #[test]
+    fn record_game_ratings() {
+        let mut matchmaking = Matchmaking::new();
+        matchmaking.record_game(&[('A', 3)]);
+        assert_eq!(matchmaking.rating('A'), None);
+
+        matchmaking.record_game(&[('A', 3), ('B', 1), ('C', 1)]);
+        let b = matchmaking.rating('B').unwrap();
+        let c = matchmaking.rating('C').unwrap();
+        assert_eq!(matchmaking.rating('A'), Some(INITIAL_RATING + RATING_FACTOR as i32 / 2));
+        assert_eq!(b, c);
+        assert!(b < INITIAL_RATING);
+
+        // A draw between equal ratings changes nothing.
+        matchmaking.record_game(&[('B', 2), ('C', 2)]);
+        assert_eq!(matchmaking.rating('B'), Some(b));
+        assert_eq!(matchmaking.rating('C'), Some(c));
+    }
+}
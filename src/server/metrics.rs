@@ -0,0 +1,237 @@
+use super::status::{MessageCounters};
+
+use super::game::arena::{ArenaEvent};
+
+use crate::message::{ClientMessage, ServerMessage, LoginStatus};
+
+use message_io::network::{Network, Endpoint};
+
+use std::collections::{BTreeMap};
+use std::fmt::{Write};
+use std::time::{Duration};
+
+const METRIC_PREFIX: &str = "asciiarena";
+
+/// Upper bounds of the game step duration buckets, in seconds.
+const STEP_DURATION_BUCKETS: [f64; 8] = [0.0005, 0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1];
+
+/// Observations grouped in cumulative buckets, as the Prometheus histograms.
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>, // By bound, without accumulating
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Histogram {
+        Histogram { bounds, counts: vec![0; bounds.len()], sum: 0.0, count: 0 }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(index) = self.bounds.iter().position(|&bound| value <= bound) {
+            self.counts[index] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// Server metrics, exported in the Prometheus text format.
+pub struct Metrics {
+    messages_received: BTreeMap<&'static str, u64>, // By message kind
+    messages_sent: BTreeMap<&'static str, u64>, // By message kind
+    bytes_sent: u64,
+    step_duration: Histogram,
+    tick_overruns: u64,
+    games: u64,
+    arenas: u64,
+    spells_cast: u64,
+    damage: u64, // Health points removed by the spells
+    deaths: u64,
+    logins: BTreeMap<&'static str, u64>, // By status
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics {
+            messages_received: BTreeMap::new(),
+            messages_sent: BTreeMap::new(),
+            bytes_sent: 0,
+            step_duration: Histogram::new(&STEP_DURATION_BUCKETS),
+            tick_overruns: 0,
+            games: 0,
+            arenas: 0,
+            spells_cast: 0,
+            damage: 0,
+            deaths: 0,
+            logins: BTreeMap::new(),
+        }
+    }
+
+    /// Sends the message by `network`, recording it as sent to each endpoint.
+    pub fn send(&mut self, network: &mut Network, endpoint: Endpoint, message: ServerMessage) {
+        let size = bincode::serialized_size(&message).unwrap_or_default();
+        self.record_sent(message.kind(), size, 1);
+        network.send(endpoint, message);
+    }
+
+    /// Sends the message by `network` to all the endpoints, recording it as sent to each one.
+    pub fn send_all<'a>(
+        &mut self,
+        network: &mut Network,
+        endpoints: impl IntoIterator<Item = &'a Endpoint>,
+        message: ServerMessage,
+    ) {
+        let size = bincode::serialized_size(&message).unwrap_or_default();
+        let kind = message.kind();
+        let mut sent = 0;
+        network.send_all(endpoints.into_iter().inspect(|_| sent += 1), message);
+        self.record_sent(kind, size, sent);
+    }
+
+    fn record_sent(&mut self, kind: &'static str, size: u64, endpoints: u64) {
+        *self.messages_sent.entry(kind).or_default() += endpoints;
+        self.bytes_sent += size * endpoints;
+    }
+
+    pub fn record_received(&mut self, message: &ClientMessage) {
+        *self.messages_received.entry(message.kind()).or_default() += 1;
+    }
+
+    pub fn record_login(&mut self, status: &LoginStatus) {
+        *self.logins.entry(status.kind()).or_default() += 1;
+    }
+
+    /// Records the processing time of a game step.
+    /// It is an overrun if the step takes longer than the time between steps.
+    pub fn record_step(&mut self, duration: Duration, step_interval: Duration) {
+        self.step_duration.observe(duration.as_secs_f64());
+        if duration > step_interval {
+            self.tick_overruns += 1;
+        }
+    }
+
+    pub fn record_game(&mut self) {
+        self.games += 1;
+    }
+
+    pub fn record_arena(&mut self) {
+        self.arenas += 1;
+    }
+
+    pub fn record_arena_events(&mut self, events: &[ArenaEvent]) {
+        for event in events {
+            match event {
+                ArenaEvent::SpellCast { .. } => self.spells_cast += 1,
+                ArenaEvent::EntityDamaged { damage, .. } => self.damage += *damage as u64,
+                ArenaEvent::EntityDied { .. } => self.deaths += 1,
+                _ => (),
+            }
+        }
+    }
+
+    /// Metrics in the Prometheus text format, including the message counters of the status.
+    pub fn encode(&self, counters: &MessageCounters) -> String {
+        let mut text = String::new();
+
+        encode_labeled(&mut text, "messages_received_total", "Client messages received", &self.messages_received);
+        encode_labeled(&mut text, "messages_sent_total", "Server messages sent to each endpoint", &self.messages_sent);
+        encode_counter(&mut text, "bytes_sent_total", "Serialized bytes of the messages sent", self.bytes_sent);
+        encode_counter(&mut text, "deserialization_errors_total", "Unknown messages received", counters.unknown);
+        encode_counter(&mut text, "rate_limited_total", "Messages discarded by the rate limit", counters.rate_limited);
+        encode_counter(&mut text, "udp_rejections_total", "Udp handshakes rejected", counters.udp_rejections);
+        encode_labeled(&mut text, "logins_total", "Login attempts by status", &self.logins);
+        encode_counter(&mut text, "games_total", "Games played", self.games);
+        encode_counter(&mut text, "arenas_total", "Arenas played", self.arenas);
+        encode_counter(&mut text, "spells_cast_total", "Spells cast in the arenas", self.spells_cast);
+        encode_counter(&mut text, "damage_total", "Health points removed by the spells", self.damage);
+        encode_counter(&mut text, "deaths_total", "Entities dead in the arenas", self.deaths);
+        encode_counter(&mut text, "tick_overruns_total", "Game steps longer than the tick interval", self.tick_overruns);
+        encode_histogram(&mut text, "game_step_duration_seconds", "Processing time of the game steps", &self.step_duration);
+
+        text
+    }
+}
+
+fn encode_header(text: &mut String, name: &str, help: &str, kind: &str) {
+    writeln!(text, "# HELP {}_{} {}", METRIC_PREFIX, name, help).unwrap();
+    writeln!(text, "# TYPE {}_{} {}", METRIC_PREFIX, name, kind).unwrap();
+}
+
+fn encode_counter(text: &mut String, name: &str, help: &str, value: u64) {
+    encode_header(text, name, help, "counter");
+    writeln!(text, "{}_{} {}", METRIC_PREFIX, name, value).unwrap();
+}
+
+fn encode_labeled(text: &mut String, name: &str, help: &str, values: &BTreeMap<&'static str, u64>) {
+    encode_header(text, name, help, "counter");
+    for (kind, value) in values {
+        writeln!(text, "{}_{}{{kind=\"{}\"}} {}", METRIC_PREFIX, name, kind, value).unwrap();
+    }
+}
+
+fn encode_histogram(text: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    encode_header(text, name, help, "histogram");
+    let mut accumulated = 0;
+    for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+        accumulated += count;
+        writeln!(text, "{}_{}_bucket{{le=\"{}\"}} {}", METRIC_PREFIX, name, bound, accumulated).unwrap();
+    }
+    writeln!(text, "{}_{}_bucket{{le=\"+Inf\"}} {}", METRIC_PREFIX, name, histogram.count).unwrap();
+    writeln!(text, "{}_{}_sum {}", METRIC_PREFIX, name, histogram.sum).unwrap();
+    writeln!(text, "{}_{}_count {}", METRIC_PREFIX, name, histogram.count).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: [f64; 3] = [1.0, 2.0, 5.0];
+
+    #[test]
+    fn observe_in_the_first_bucket_not_exceeded() {
+        let mut histogram = Histogram::new(&BOUNDS);
+        for &value in &[0.5, 1.0, 1.5, 5.0, 7.0] {
+            histogram.observe(value);
+        }
+
+        // The values above the last bound are only counted in the total (+Inf).
+        assert_eq!(histogram.counts, vec![2, 1, 1]);
+        assert_eq!(histogram.count, 5);
+        assert_eq!(histogram.sum, 15.0);
+    }
+
+    #[test]
+    fn encode_cumulative_buckets() {
+        let mut histogram = Histogram::new(&BOUNDS);
+        for &value in &[0.5, 1.5, 1.5, 8.0] {
+            histogram.observe(value);
+        }
+
+        let mut text = String::new();
+        encode_histogram(&mut text, "duration_seconds", "Duration", &histogram);
+        let expected = "\
+            # HELP asciiarena_duration_seconds Duration\n\
+            # TYPE asciiarena_duration_seconds histogram\n\
+            asciiarena_duration_seconds_bucket{le=\"1\"} 1\n\
+            asciiarena_duration_seconds_bucket{le=\"2\"} 3\n\
+            asciiarena_duration_seconds_bucket{le=\"5\"} 3\n\
+            asciiarena_duration_seconds_bucket{le=\"+Inf\"} 4\n\
+            asciiarena_duration_seconds_sum 11.5\n\
+            asciiarena_duration_seconds_count 4\n";
+        assert_eq!(text, expected);
+    }
+
+    #[test]
+    fn encode_empty_histogram() {
+        let histogram = Histogram::new(&STEP_DURATION_BUCKETS);
+        let mut text = String::new();
+        encode_histogram(&mut text, "step", "Step", &histogram);
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2 + STEP_DURATION_BUCKETS.len() + 3);
+        assert_eq!(lines[2], "asciiarena_step_bucket{le=\"0.0005\"} 0");
+        assert!(lines[3..].iter().all(|line| line.ends_with(" 0")));
+    }
+}
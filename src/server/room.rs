@@ -0,0 +1,80 @@
+use super::session::{RoomSession};
+use super::game::{Game};
+
+use crate::message::{RoomSettings, RoomInfo, MAX_ROOM_NAME_LEN};
+use crate::ids::{RoomId, TeamId};
+
+use message_io::network::{Endpoint};
+
+use itertools::{Itertools};
+
+use std::collections::{HashMap};
+use std::time::{Instant};
+
+/// Room of the clients that have not joined another one. It follows the server config.
+pub const MAIN_ROOM: RoomId = RoomId(0);
+pub const MAIN_ROOM_NAME: &str = "main";
+
+/// Players sharing a game. Each room has its own settings, sessions and game.
+pub struct Room {
+    pub name: String,
+    pub settings: RoomSettings,
+    pub sessions: RoomSession<Endpoint, char>,
+    pub game: Option<Game>,
+    pub waiting_arena_from: Option<Instant>,
+    pub teams: HashMap<char, TeamId>, // Chosen by the players before the game
+}
+
+impl Room {
+    pub fn new(name: String, settings: RoomSettings) -> Room {
+        Room {
+            name,
+            settings,
+            sessions: RoomSession::new(settings.players_number as usize),
+            game: None,
+            waiting_arena_from: None,
+            teams: HashMap::new(),
+        }
+    }
+
+    pub fn player_symbols(&self) -> Vec<char> {
+        self.sessions.sessions().map(|session| *session.user()).collect()
+    }
+
+    /// Teams chosen by the logged players.
+    pub fn team_choices(&self) -> Vec<(char, TeamId)> {
+        self.player_symbols()
+            .into_iter()
+            .sorted()
+            .filter_map(|symbol| self.teams.get(&symbol).map(|team| (symbol, *team)))
+            .collect()
+    }
+
+    /// Number of the arena that is being played, not waiting to start.
+    pub fn running_arena(&self) -> Option<usize> {
+        self.game
+            .as_ref()
+            .filter(|game| game.arena().is_some() && self.waiting_arena_from.is_none())
+            .map(|game| game.arena_number())
+    }
+
+    /// A room without sessions nor game can be removed if no client is in it.
+    pub fn is_unused(&self) -> bool {
+        self.game.is_none() && self.sessions.sessions().next().is_none()
+    }
+
+    pub fn info(&self) -> RoomInfo {
+        RoomInfo {
+            name: self.name.clone(),
+            settings: self.settings,
+            logged_players: self.player_symbols(),
+            playing: self.game.is_some(),
+        }
+    }
+
+    /// Names of the rooms created by the clients: letters, digits, '-' and '_'.
+    pub fn is_valid_name(name: &str) -> bool {
+        (1..=MAX_ROOM_NAME_LEN).contains(&name.len())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+}
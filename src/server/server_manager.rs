@@ -1,13 +1,25 @@
-use super::session::{RoomSession, SessionStatus};
+use super::configuration::{Config, ConfigLoader};
+use super::admin::{self, AdminCommand, Target};
+use super::ban_list::{BanList};
+use super::status::{self, Page, ServerStatus, RoomState, GameState, PlayerPoints, SessionState,
+    MessageCounters};
+use super::metrics::{Metrics};
+use super::session::{SessionStatus, TokenRejection};
+use super::connection::{Connection};
+use super::room::{Room, MAIN_ROOM, MAIN_ROOM_NAME};
+use super::matchmaking::{Matchmaking, Match};
 use super::game::{Game};
-use super::game::arena::{Arena};
+use super::game::mode::{self};
+use super::game::arena::{Arena, ArenaEvent};
 
 use crate::message::{ClientMessage, ServerMessage, ServerInfo, GameInfo, ArenaInfo,
-    LoginStatus, LoggedKind, EntityData, Frame, GameEvent, SpellData};
-use crate::version::{self, Compatibility};
+    LoginStatus, LoggedKind, EntityData, Frame, GameEvent, SpellData, ObjectData, DiscoveryInfo,
+    RoomSettings, RoomStatus, QueuePreferences, QueueStatus, DISCOVERY_ADDR};
+use crate::version::{self, Compatibility, ProtocolVersion, Capability};
 use crate::direction::{Direction};
-use crate::ids::{SessionToken, SkillId};
+use crate::ids::{SessionToken, SkillId, PingId, RoomId, TeamId};
 use crate::util::{self};
+use crate::logger::{self};
 
 use message_io::events::{EventQueue};
 use message_io::network::{Network, NetEvent, Endpoint};
@@ -15,42 +27,86 @@ use message_io::network::{Network, NetEvent, Endpoint};
 use itertools::{Itertools};
 
 use std::time::{Duration, Instant};
-use std::collections::{HashSet};
-
-lazy_static! {
-    static ref GAME_STEP_DURATION: Duration = Duration::from_secs_f32(1.0 / 30.0);
-}
+use std::collections::{HashSet, HashMap};
+use std::net::{SocketAddr, IpAddr, Ipv4Addr};
+use std::sync::mpsc::{self};
+
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const MATCHMAKING_INTERVAL: Duration = Duration::from_secs(1);
+const LATENCY_LOG_PERIOD: usize = 10; // In ping intervals
+const REGISTRY_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const DISCOVERY_REPLY_INTERVAL: Duration = Duration::from_secs(1); // Minimum by ip
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+const STATUS_TIMEOUT: Duration = Duration::from_secs(1);
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+const CLOSING_REASON: &str = "The server is closing";
+const RESTARTING_REASON: &str = "The server is restarting";
 
 #[derive(Debug)]
 enum Event {
     Network(NetEvent<ClientMessage>),
-    AsyncCreateGame, // Could take time in processing
-    AsyncStartArena, // Generated Eventually
-    GameStep,        // Generated Eventually
-    Close,           // Main loop control
+    AsyncCreateGame(RoomId), // Could take time in processing
+    AsyncStartArena(RoomId, Instant), // Generated Eventually. Start of the waiting
+    GameStep(RoomId),        // Generated Eventually
+    Ping,                    // Generated periodically
+    CheckIdle,               // Generated periodically
+    Matchmaking,             // Generated periodically
+    RegistryHeartbeat,       // Generated periodically
+    CheckConfig,             // Generated periodically
+    Admin(AdminCommand),
+    PageRequest(Page, mpsc::Sender<String>), // From the status server
+    Shutdown(String, Option<Duration>), // Reason, time until it is running again
+    Close,                   // Main loop control
 }
 
-pub struct Config {
-    pub tcp_port: u16,
-    pub udp_port: u16,
-    pub players_number: u8,
-    pub map_size: usize,
-    pub winner_points: usize,
-    pub arena_waiting: Duration,
+/// Shutdown requested to the server.
+enum Closing {
+    AfterArena(String, Option<Duration>), // Waiting for the end of the running arenas
+    Draining, // Announced, waiting for the clients to close their connections
 }
 
-pub struct ServerManager<'a> {
-    config: &'a Config,
+pub struct ServerManager {
+    config: Config,
+    config_loader: ConfigLoader,
+    next_config: Option<Config>, // Reloaded, waiting for the end of the running games
     network: Network,
     subscriptions: HashSet<Endpoint>,
-    room: RoomSession<Endpoint, char>,
-    game: Option<Game>,
-    waiting_arena_from: Option<Instant>,
+    connections: HashMap<Endpoint, Connection>,
+    udp_connections: HashMap<Endpoint, Connection>, // Created by its first message
+    last_ping: (PingId, Instant),
+    start_time: Instant,
+    counters: MessageCounters,
+    metrics: Metrics,
+    registry: Option<Endpoint>,
+    discovery_listener: Option<usize>, // Resource of the multicast listener
+    discovery_replies: HashMap<IpAddr, Instant>, // Last reply by ip, to rate limit them
+    rooms: HashMap<RoomId, Room>,
+    last_room: RoomId, // Id of the last room created
+    matchmaking: Matchmaking,
+    last_match: usize, // Number of the last match, used to name its room
+    ban_list: BanList,
+    closing: Option<Closing>,
     event_queue: EventQueue<Event>,
 }
 
-impl<'a> ServerManager<'a> {
-    pub fn new(config: &'a Config) -> Option<ServerManager<'a>> {
+impl ServerManager {
+    pub fn new(
+        config: Config,
+        config_loader: ConfigLoader,
+        admin_console: bool,
+    ) -> Option<ServerManager> {
+        let ban_list = match BanList::load(config.ban_list.clone()) {
+            Ok(ban_list) => ban_list,
+            Err(error) => {
+                log::error!("{}", error);
+                return None;
+            }
+        };
+
+        let mut rooms = HashMap::new();
+        rooms.insert(MAIN_ROOM, Room::new(MAIN_ROOM_NAME.into(), config.room_settings()));
+
         let mut event_queue = EventQueue::new();
 
         let sender = event_queue.sender().clone();
@@ -58,35 +114,124 @@ impl<'a> ServerManager<'a> {
 
         let signal_sender = event_queue.sender().clone();
         ctrlc::set_handler(move || {
-            signal_sender.send_with_priority(Event::Close)
+            signal_sender.send_with_priority(Event::Shutdown(CLOSING_REASON.into(), None))
         }).unwrap();
 
-        let network_interface = "0.0.0.0";
-        if let Err(_) = network.listen_tcp((network_interface, config.tcp_port)) {
-            log::error!("Can not run server on TCP port {}", config.tcp_port);
-            return None;
+        for &ip in &config.bind {
+            if let Err(error) = network.listen_tcp((ip, config.tcp_port)) {
+                log::error!("Can not run server on TCP {}: {}", SocketAddr::new(ip, config.tcp_port), error);
+                return None;
+            }
+
+            if let Err(error) = network.listen_udp((ip, config.udp_port)) {
+                log::error!("Can not run server on UDP {}: {}", SocketAddr::new(ip, config.udp_port), error);
+                return None;
+            }
         }
 
-        if let Err(_) = network.listen_udp((network_interface, config.udp_port)) {
-            log::error!("Can not run server on UDP port {}", config.udp_port);
-            return None;
+        // A server only reachable from the own machine has nothing to announce in the LAN.
+        let mut discovery_listener = None;
+        if config.discovery && config.bind.iter().any(|ip| !ip.is_loopback()) {
+            // Joining a multicast group can fail without a multicast route.
+            let multicast_available = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+                .and_then(|socket| {
+                    socket.join_multicast_v4(DISCOVERY_ADDR.ip(), &Ipv4Addr::UNSPECIFIED)
+                });
+
+            match multicast_available.and_then(|_| network.listen_udp_multicast(DISCOVERY_ADDR)) {
+                Ok((resource_id, _)) => {
+                    log::info!("Listening LAN discovery probes on {}", DISCOVERY_ADDR);
+                    discovery_listener = Some(resource_id);
+                }
+                Err(error) => log::warn!("LAN discovery not available: {}", error),
+            }
         }
 
         log::info!(
-            "Server running on ports {} (tcp) and {} (udp) for {} players",
+            "Server running on {} with ports {} (tcp) and {} (udp) for {} players",
+            util::format::items_to_string(&config.bind),
             config.tcp_port,
             config.udp_port,
             config.players_number
         );
 
+        if admin_console {
+            let admin_sender = event_queue.sender().clone();
+            admin::run_console(move |command| admin_sender.send(Event::Admin(command)));
+            log::info!("Admin console enabled. Write 'help' to list the commands");
+        }
+
+        if let Some(status_port) = config.status_port {
+            let status_sender = event_queue.sender().clone();
+            let page = move |page| {
+                let (sender, receiver) = mpsc::channel();
+                status_sender.send(Event::PageRequest(page, sender));
+                receiver.recv_timeout(STATUS_TIMEOUT).ok()
+            };
+
+            match status::run_http_server(status_port, page) {
+                Ok(_) => log::info!(
+                    "Serving the server status on http://localhost:{0}/status \
+                    and the metrics on http://localhost:{0}/metrics",
+                    status_port
+                ),
+                Err(error) => {
+                    log::error!("Can not serve the server status on port {}: {}", status_port, error);
+                    return None;
+                }
+            }
+        }
+
+        event_queue.sender().send_with_timer(Event::Ping, PING_INTERVAL);
+        event_queue.sender().send_with_timer(Event::CheckIdle, IDLE_CHECK_INTERVAL);
+        event_queue.sender().send_with_timer(Event::Matchmaking, MATCHMAKING_INTERVAL);
+        if config_loader.has_file() {
+            event_queue.sender().send_with_timer(Event::CheckConfig, CONFIG_CHECK_INTERVAL);
+        }
+
+        let registry = match config.registry {
+            Some(registry_addr) => match util::udp_connectable_addr(registry_addr) {
+                Some(udp_addr) => match network.connect_udp(udp_addr) {
+                    Ok(endpoint) => {
+                        log::info!("Registering server as '{}' in {}", config.name, registry_addr);
+                        event_queue.sender().send(Event::RegistryHeartbeat);
+                        Some(endpoint)
+                    }
+                    Err(error) => {
+                        log::error!("Can not use the registry {}: {}", registry_addr, error);
+                        None
+                    }
+                },
+                None => {
+                    log::error!("Can not use the registry {}: udp by ipv6 is not supported", registry_addr);
+                    None
+                }
+            },
+            None => None,
+        };
+
         Some(ServerManager {
             event_queue,
             network,
             subscriptions: HashSet::new(),
-            room: RoomSession::new(config.players_number as usize),
-            game: None,
-            waiting_arena_from: None,
+            connections: HashMap::new(),
+            udp_connections: HashMap::new(),
+            last_ping: (PingId::NONE, Instant::now()),
+            start_time: Instant::now(),
+            counters: MessageCounters::default(),
+            metrics: Metrics::new(),
+            registry,
+            discovery_listener,
+            discovery_replies: HashMap::new(),
+            rooms,
+            last_room: MAIN_ROOM,
+            matchmaking: Matchmaking::new(),
+            last_match: 0,
+            ban_list,
+            closing: None,
             config,
+            config_loader,
+            next_config: None,
         })
     }
 
@@ -95,14 +240,47 @@ impl<'a> ServerManager<'a> {
             let event = self.event_queue.receive();
             log::trace!("[Process event] - {:?}", event);
             match event {
-                Event::AsyncCreateGame => {
-                    self.process_create_game();
+                Event::AsyncCreateGame(room_id) => {
+                    self.process_create_game(room_id);
+                },
+                Event::AsyncStartArena(room_id, waiting_from) => {
+                    // The clients are leaving, a new arena would be only for them to see it.
+                    if self.closing.is_none() {
+                        self.process_start_arena(room_id, waiting_from);
+                    }
+                },
+                Event::GameStep(room_id) => {
+                    self.process_game_step(room_id);
                 },
-                Event::AsyncStartArena => {
-                    self.process_start_arena();
+                Event::Ping => {
+                    self.process_ping();
                 },
-                Event::GameStep => {
-                    self.process_game_step();
+                Event::CheckIdle => {
+                    self.process_check_idle();
+                },
+                Event::Matchmaking => {
+                    self.process_matchmaking();
+                    self.event_queue.sender().send_with_timer(Event::Matchmaking, MATCHMAKING_INTERVAL);
+                },
+                Event::RegistryHeartbeat => {
+                    self.process_registry_heartbeat();
+                },
+                Event::CheckConfig => {
+                    self.process_check_config();
+                },
+                Event::Admin(command) => {
+                    self.process_admin_command(command);
+                },
+                Event::PageRequest(page, sender) => {
+                    let content = match page {
+                        Page::Status => self.status_json(),
+                        Page::Metrics => self.metrics.encode(&self.counters),
+                    };
+                    // The status server could have given up waiting.
+                    sender.send(content).ok();
+                },
+                Event::Shutdown(reason, restart_in) => {
+                    self.process_shutdown(reason, restart_in);
                 },
                 Event::Close => {
                     log::info!("Closing server");
@@ -111,26 +289,65 @@ impl<'a> ServerManager<'a> {
                 Event::Network(net_event) => match net_event {
                     NetEvent::AddedEndpoint(endpoint) => {
                         log::trace!("{} has connected", endpoint);
+                        if let Some(Closing::Draining) = self.closing {
+                            log::trace!("{} rejected, the server is closing", endpoint);
+                            self.network.remove_resource(endpoint.resource_id());
+                            continue
+                        }
+                        let ip = util::canonical_ip(endpoint.addr().ip());
+                        if self.ban_list.find(Target::Ip(ip)).is_some() {
+                            logger::record!(
+                                Warn, {endpoint: endpoint},
+                                "Banned ip {} has tried to connect. Connection rejected", ip
+                            );
+                            self.network.remove_resource(endpoint.resource_id());
+                            continue
+                        }
+                        let connection = Connection::new(self.config.message_rate);
+                        self.connections.insert(endpoint, connection);
                     },
                     NetEvent::RemovedEndpoint(endpoint) => {
                         log::trace!("{} has disconnected", endpoint);
                         self.process_disconnection(endpoint);
                     },
                     NetEvent::DeserializationError(endpoint) => {
-                        log::error!("{} sends an unknown message. Connection rejected", endpoint);
-                        self.network.remove_resource(endpoint.resource_id()).unwrap();
+                        self.counters.unknown += 1;
+                        if self.connections.contains_key(&endpoint) {
+                            logger::record!(
+                                Error, {endpoint: endpoint},
+                                "{} sends an unknown message. Connection rejected", endpoint
+                            );
+                            self.network.remove_resource(endpoint.resource_id()).unwrap();
+                            self.process_disconnection(endpoint);
+                        }
+                        else {
+                            // The udp endpoints share the listener resource, it can not be removed.
+                            logger::record!(
+                                Error, {endpoint: endpoint},
+                                "{} sends an unknown message by udp. Ignored", endpoint
+                            );
+                        }
                     }
                     NetEvent::Message(endpoint, message) => {
                         log::trace!("Message from {}", endpoint.addr());
+                        match self.connections.contains_key(&endpoint) {
+                            true => self.counters.received_tcp += 1,
+                            false => self.counters.received_udp += 1,
+                        }
+                        self.metrics.record_received(&message);
+                        if !self.process_message_rate(endpoint) {
+                            self.counters.rate_limited += 1;
+                            continue
+                        }
                         match message {
                             ClientMessage::Version(client_version) => {
-                                self.process_version(endpoint, &client_version);
+                                self.process_version(endpoint, client_version);
                             },
                             ClientMessage::SubscribeServerInfo => {
                                 self.process_subscribe_server_info(endpoint);
                             },
-                            ClientMessage::Login(user) => {
-                                self.process_login(endpoint, user);
+                            ClientMessage::Login(user, password) => {
+                                self.process_login(endpoint, user, password);
                             },
                             ClientMessage::Logout => {
                                 self.process_logout(endpoint);
@@ -141,12 +358,47 @@ impl<'a> ServerManager<'a> {
                             ClientMessage::TrustUdp => {
                                 self.process_trust_udp(endpoint);
                             },
+                            ClientMessage::Ping(id) => {
+                                let message = ServerMessage::Pong(id);
+                                self.metrics.send(&mut self.network, endpoint, message);
+                            },
+                            ClientMessage::Pong(id) => {
+                                self.process_pong(endpoint, id);
+                            },
                             ClientMessage::MovePlayer(direction) => {
                                 self.process_move_player(endpoint, direction);
                             },
                             ClientMessage::CastSkill(direction, id) => {
                                 self.process_cast_skill(endpoint, direction, id);
                             },
+                            ClientMessage::DiscoveryProbe(reply_port) => {
+                                self.process_discovery_probe(endpoint, reply_port);
+                            },
+                            ClientMessage::RegisterServer(_) | ClientMessage::QueryRegistry => {
+                                log::warn!("Registry message from {} ignored", endpoint.addr());
+                            },
+                            ClientMessage::ListRooms => {
+                                self.process_list_rooms(endpoint);
+                            },
+                            ClientMessage::CreateRoom(name, settings) => {
+                                self.process_create_room(endpoint, name, settings);
+                            },
+                            ClientMessage::JoinRoom(name) => {
+                                self.process_join_room(endpoint, name);
+                            },
+                            ClientMessage::JoinQueue(character, preferences) => {
+                                self.process_join_queue(endpoint, character, preferences);
+                            },
+                            ClientMessage::LeaveQueue => {
+                                if self.matchmaking.leave(endpoint) {
+                                    log::trace!("{} has left the queue", endpoint.addr());
+                                }
+                                let message = ServerMessage::QueueStatus(QueueStatus::Left);
+                                self.metrics.send(&mut self.network, endpoint, message);
+                            },
+                            ClientMessage::ChooseTeam(team) => {
+                                self.process_choose_team(endpoint, team);
+                            },
                         }
                     },
                 },
@@ -154,65 +406,501 @@ impl<'a> ServerManager<'a> {
         }
     }
 
-    fn process_version(&mut self, endpoint: Endpoint, client_version: &str) {
-        let compatibility = version::check(&client_version, version::current());
+    fn process_version(&mut self, endpoint: Endpoint, client_version: ProtocolVersion) {
+        let server_version = ProtocolVersion::current();
+        let compatibility = version::check(&server_version, &client_version);
+        let capabilities = server_version.capabilities.intersection(client_version.capabilities);
         match compatibility {
             Compatibility::Fully =>
-                log::trace!("Fully compatible versions: {}", client_version),
+                log::trace!("Fully compatible versions: {}", client_version.tag),
             Compatibility::NotExact =>
                 log::warn!(
-                    "Compatible client version, but not exact. Client: {}. Server: {}",
-                    client_version,
-                    version::current()
+                    "Compatible client version, but not exact. \
+                    Client: {} (protocol {}). Server: {} (protocol {}). Capabilities: {:?}",
+                    client_version.tag,
+                    client_version.protocol,
+                    server_version.tag,
+                    server_version.protocol,
+                    capabilities,
                 ),
             Compatibility::None =>
                 log::warn!(
-                    "Incompatible client version. Client: {}. Server: {}. Connection rejected",
-                    client_version,
-                    version::current()
+                    "Incompatible client version. \
+                    Client: {} (protocol {}). Server: {} (protocol {}). Connection rejected",
+                    client_version.tag,
+                    client_version.protocol,
+                    server_version.tag,
+                    server_version.protocol,
                 ),
         };
 
-        let message = ServerMessage::Version(version::current().into(), compatibility);
-        self.network.send(endpoint, message);
+        if let Some(connection) = self.connections.get_mut(&endpoint) {
+            connection.set_capabilities(capabilities);
+        }
+
+        let message = ServerMessage::Version(server_version, compatibility);
+        self.metrics.send(&mut self.network, endpoint, message);
 
         if let Compatibility::None = compatibility {
             self.network.remove_resource(endpoint.resource_id()).unwrap();
+            self.process_disconnection(endpoint);
         }
     }
 
-    fn process_subscribe_server_info(&mut self, endpoint: Endpoint) {
-        let info = ServerInfo {
+    fn has_capability(&self, endpoint: Endpoint, capability: Capability) -> bool {
+        self.connections
+            .get(&endpoint)
+            .and_then(|connection| connection.capabilities())
+            .is_some_and(|capabilities| capabilities.contains(capability))
+    }
+
+    /// Room of the client. The endpoints without connection are in the main room.
+    fn room_of(&self, endpoint: Endpoint) -> RoomId {
+        self.connections
+            .get(&endpoint)
+            .map(|connection| connection.room())
+            .unwrap_or(MAIN_ROOM)
+    }
+
+    fn find_room(&self, name: &str) -> Option<RoomId> {
+        self.rooms
+            .iter()
+            .find(|(_, room)| room.name == name)
+            .map(|(room_id, _)| *room_id)
+    }
+
+    fn server_info(&self, room_id: RoomId) -> ServerInfo {
+        let room = &self.rooms[&room_id];
+        ServerInfo {
+            name: self.config.name.clone(),
             udp_port: self.config.udp_port,
-            players_number: self.config.players_number,
-            map_size: self.config.map_size as u16,
-            winner_points: self.config.winner_points as u16,
-            logged_players: self.room
-                .sessions()
-                .map(|session| *session.user())
-                .collect(),
-        };
+            players_number: room.settings.players_number,
+            map_size: room.settings.map_size,
+            winner_points: room.settings.winner_points,
+            password_required: self.config.password.is_some(),
+            logged_players: room.player_symbols(),
+            mode: self.config.mode,
+            teams: if self.config.mode.has_teams() { self.config.teams } else { 0 },
+        }
+    }
+
+    /// Sends the info of the room to a subscribed client, with the chosen teams if the mode has them.
+    fn send_server_info(&mut self, endpoint: Endpoint, room_id: RoomId) {
+        let message = ServerMessage::StaticServerInfo(self.server_info(room_id));
+        self.metrics.send(&mut self.network, endpoint, message);
 
+        if self.config.mode.has_teams() {
+            let message = ServerMessage::TeamChoices(self.rooms[&room_id].team_choices());
+            self.metrics.send(&mut self.network, endpoint, message);
+        }
+    }
+
+    fn process_subscribe_server_info(&mut self, endpoint: Endpoint) {
         log::trace!("Client {} has subscribed to server info", endpoint.addr());
         self.subscriptions.insert(endpoint);
-        self.network.send(endpoint, ServerMessage::StaticServerInfo(info));
+        self.send_server_info(endpoint, self.room_of(endpoint));
+    }
+
+    fn discovery_info(&self) -> DiscoveryInfo {
+        DiscoveryInfo {
+            tcp_port: self.config.tcp_port,
+            version: ProtocolVersion::current(),
+            info: self.server_info(MAIN_ROOM),
+        }
+    }
+
+    fn process_registry_heartbeat(&mut self) {
+        if let Some(registry) = self.registry {
+            let discovery_info = self.discovery_info();
+            self.network.send(registry, ClientMessage::RegisterServer(discovery_info));
+            self.event_queue.sender().send_with_timer(
+                Event::RegistryHeartbeat,
+                REGISTRY_HEARTBEAT_INTERVAL
+            );
+        }
+    }
+
+    fn process_discovery_probe(&mut self, endpoint: Endpoint, reply_port: u16) {
+        // The reply is larger than the probe: answering probes from the game listeners
+        // or without limit would let spoofed probes flood other hosts.
+        if Some(endpoint.resource_id()) != self.discovery_listener {
+            log::trace!("Discovery probe from {} out of the discovery address ignored", endpoint.addr());
+            return
+        }
+
+        let now = Instant::now();
+        self.discovery_replies
+            .retain(|_, last_reply| now.duration_since(*last_reply) < DISCOVERY_REPLY_INTERVAL);
+
+        let ip = endpoint.addr().ip();
+        if self.discovery_replies.contains_key(&ip) {
+            log::trace!("Discovery probe from {} ignored, answered recently", ip);
+            return
+        }
+        self.discovery_replies.insert(ip, now);
+
+        let reply_addr = SocketAddr::new(ip, reply_port);
+        log::trace!("Discovery probe from {}", reply_addr);
+        match self.network.connect_udp(reply_addr) {
+            Ok(reply_endpoint) => {
+                let discovery_info = self.discovery_info();
+                let message = ServerMessage::DiscoveryInfo(discovery_info);
+                self.metrics.send(&mut self.network, reply_endpoint, message);
+                self.network.remove_resource(reply_endpoint.resource_id());
+            }
+            Err(error) => log::warn!("Can not answer discovery probe of {}: {}", reply_addr, error),
+        }
+    }
+
+    /// Sends the players of the room to the clients subscribed to its info,
+    /// and the updated room list.
+    fn send_room_players(&mut self, room_id: RoomId) {
+        if let Some(room) = self.rooms.get(&room_id) {
+            let endpoints = self.room_subscriptions(room_id);
+            let message = ServerMessage::DynamicServerInfo(room.player_symbols());
+            self.metrics.send_all(&mut self.network, endpoints.iter(), message);
+            self.send_team_choices(room_id);
+        }
+        self.send_room_list();
+    }
+
+    /// Clients subscribed to the info of the room.
+    fn room_subscriptions(&self, room_id: RoomId) -> Vec<Endpoint> {
+        self.subscriptions
+            .iter()
+            .filter(|endpoint| self.room_of(**endpoint) == room_id)
+            .copied()
+            .collect()
+    }
+
+    /// Sends the teams chosen in the room to the clients subscribed to its info.
+    fn send_team_choices(&mut self, room_id: RoomId) {
+        if self.config.mode.has_teams() {
+            let endpoints = self.room_subscriptions(room_id);
+            let message = ServerMessage::TeamChoices(self.rooms[&room_id].team_choices());
+            self.metrics.send_all(&mut self.network, endpoints.iter(), message);
+        }
+    }
+
+    fn process_choose_team(&mut self, endpoint: Endpoint, team: TeamId) {
+        let room_id = self.room_of(endpoint);
+        let room = self.rooms.get_mut(&room_id).unwrap();
+        let player_symbol = match room.sessions.session_by_endpoint(endpoint) {
+            Some(session) => *session.user(),
+            None => return log::warn!("Team choice from not logged {} ignored", endpoint.addr()),
+        };
+
+        if room.game.is_some() || !self.config.mode.has_teams() || team.0 > self.config.teams as usize {
+            return log::warn!("Team choice {:?} of player '{}' ignored", team, player_symbol)
+        }
+
+        match team {
+            TeamId::NONE => room.teams.remove(&player_symbol),
+            team => room.teams.insert(player_symbol, team),
+        };
+
+        let team_name = match team {
+            TeamId::NONE => "any".into(),
+            team => team.0.to_string(),
+        };
+        logger::record!(
+            Trace, {endpoint: endpoint, player: player_symbol},
+            "Player '{}' has chosen the team: {}", player_symbol, team_name
+        );
+        self.send_team_choices(room_id);
+    }
+
+    fn room_list_message(&self) -> ServerMessage {
+        let rooms = self.rooms
+            .iter()
+            .sorted_by_key(|(room_id, _)| room_id.0)
+            .map(|(_, room)| room.info())
+            .collect();
+
+        ServerMessage::RoomList(rooms)
+    }
+
+    /// Sends the room list to the subscribed clients that understand the rooms.
+    fn send_room_list(&mut self) {
+        let endpoints = self.subscriptions
+            .iter()
+            .filter(|endpoint| self.has_capability(**endpoint, Capability::Rooms))
+            .copied()
+            .collect::<Vec<_>>();
+
+        if !endpoints.is_empty() {
+            let message = self.room_list_message();
+            self.metrics.send_all(&mut self.network, endpoints.iter(), message);
+        }
+    }
+
+    fn is_logged(&self, endpoint: Endpoint) -> bool {
+        self.rooms[&self.room_of(endpoint)]
+            .sessions
+            .session_by_endpoint(endpoint)
+            .is_some()
     }
 
-    fn process_login(&mut self, endpoint: Endpoint, player_symbol: char) {
+    fn process_list_rooms(&mut self, endpoint: Endpoint) {
+        // The answer is bigger than the request: not sent to udp sources that could be spoofed.
+        if !self.connections.contains_key(&endpoint) {
+            return log::warn!("Room list request from {} by udp ignored", endpoint.addr())
+        }
+
+        let message = self.room_list_message();
+        self.metrics.send(&mut self.network, endpoint, message);
+    }
+
+    fn process_create_room(&mut self, endpoint: Endpoint, name: String, settings: RoomSettings) {
+        if !self.connections.contains_key(&endpoint) {
+            return log::warn!("Room creation from {} by udp ignored", endpoint.addr())
+        }
+
+        let status =
+        if self.is_logged(endpoint) {
+            RoomStatus::Logged
+        }
+        else if !Room::is_valid_name(&name) {
+            RoomStatus::InvalidName
+        }
+        else if self.find_room(&name).is_some() {
+            RoomStatus::AlreadyExists
+        }
+        else if !settings.is_valid() {
+            RoomStatus::InvalidSettings
+        }
+        else if self.rooms.len() > self.config.max_rooms { // The main room is not counted
+            logger::record!(
+                Warn, {endpoint: endpoint, room: name.as_str()},
+                "Room '{}' not created, the limit of {} rooms has been reached",
+                name,
+                self.config.max_rooms,
+            );
+            RoomStatus::RoomLimit
+        }
+        else {
+            logger::record!(
+                Info, {endpoint: endpoint, room: name.as_str()},
+                "Room '{}' created for {} players, map {} and {} winner points",
+                name,
+                settings.players_number,
+                settings.map_size,
+                settings.winner_points,
+            );
+            self.last_room = RoomId::next(self.last_room);
+            self.rooms.insert(self.last_room, Room::new(name.clone(), settings));
+            self.process_join_room(endpoint, name);
+            return self.send_room_list()
+        };
+
+        log::trace!("{} attempts to create the room '{}'. Status: {:?}", endpoint.addr(), name, status);
+        self.metrics.send(&mut self.network, endpoint, ServerMessage::RoomStatus(name, status));
+    }
+
+    fn process_join_room(&mut self, endpoint: Endpoint, name: String) {
+        let previous_room_id = match self.connections.get(&endpoint) {
+            Some(connection) => connection.room(),
+            None => return log::warn!("Room join from {} by udp ignored", endpoint.addr()),
+        };
+        self.matchmaking.leave(endpoint);
+
+        let room_id = self.find_room(&name);
+        let status = match room_id {
+            _ if self.is_logged(endpoint) => RoomStatus::Logged,
+            Some(room_id) => {
+                self.connections.get_mut(&endpoint).unwrap().set_room(room_id);
+                RoomStatus::Joined
+            },
+            None => RoomStatus::NotFound,
+        };
+
+        log::trace!("{} attempts to join the room '{}'. Status: {:?}", endpoint.addr(), name, status);
+        self.metrics.send(&mut self.network, endpoint, ServerMessage::RoomStatus(name, status));
+
+        if let (RoomStatus::Joined, Some(room_id)) = (status, room_id) {
+            if self.subscriptions.contains(&endpoint) {
+                self.send_server_info(endpoint, room_id);
+            }
+            self.remove_unused_room(previous_room_id);
+        }
+    }
+
+    fn process_join_queue(&mut self, endpoint: Endpoint, character: char, preferences: QueuePreferences) {
+        if !self.connections.contains_key(&endpoint) {
+            return log::warn!("Queue request from {} by udp ignored", endpoint.addr())
+        }
+
+        let status =
+        if self.config.max_rooms == 0 {
+            QueueStatus::Unavailable
+        }
+        else if self.is_logged(endpoint) {
+            QueueStatus::Logged
+        }
+        else if !util::is_valid_character(character) {
+            QueueStatus::InvalidPlayerName
+        }
+        else if !preferences.is_valid() {
+            QueueStatus::InvalidPreferences
+        }
+        else {
+            let waiting = self.matchmaking.join(endpoint, character, preferences);
+            let rating = self.matchmaking.rating(character);
+            logger::record!(
+                Info, {endpoint: endpoint, player: character},
+                "Player '{}' queued for {} players (rating: {}). Waiting: {}",
+                character,
+                preferences.players_number,
+                rating.map(|rating| rating.to_string()).unwrap_or_else(|| "-".into()),
+                waiting,
+            );
+            QueueStatus::Queued { waiting, rating }
+        };
+
+        log::trace!("{} attempts to join the queue. Status: {:?}", endpoint.addr(), status);
+        let queued = matches!(status, QueueStatus::Queued { .. });
+        self.metrics.send(&mut self.network, endpoint, ServerMessage::QueueStatus(status));
+
+        if queued {
+            self.process_matchmaking();
+        }
+    }
+
+    /// Creates a room for each group of queued clients that can play together.
+    fn process_matchmaking(&mut self) {
+        // The match rooms count as rooms created by the clients.
+        while self.closing.is_none() && self.rooms.len() <= self.config.max_rooms {
+            match self.matchmaking.next_match(Instant::now()) {
+                Some(found) => self.start_match(found),
+                None => break,
+            }
+        }
+    }
+
+    /// Moves the clients of the match to a new room. They login there by themselves.
+    fn start_match(&mut self, found: Match) {
+        let name = loop {
+            self.last_match += 1;
+            let name = format!("match-{}", self.last_match);
+            if self.find_room(&name).is_none() {
+                break name
+            }
+        };
+
+        let settings = RoomSettings {
+            players_number: found.preferences.players_number,
+            map_size: found.preferences.map_size.unwrap_or(self.config.map_size as u16),
+            winner_points: self.config.winner_points as u16,
+        };
+
+        let ratings = found.players
+            .iter()
+            .map(|(_, character)| {
+                let rating = match self.matchmaking.rating(*character) {
+                    Some(rating) => rating.to_string(),
+                    None => "-".into(),
+                };
+                (character, rating)
+            })
+            .collect::<Vec<_>>();
+
+        logger::record!(
+            Info, {room: name.as_str()},
+            "Match found for {} players in the room '{}'. Ratings: {}",
+            settings.players_number,
+            name,
+            util::format::pair_items_to_string(ratings),
+        );
+
+        self.last_room = RoomId::next(self.last_room);
+        let room_id = self.last_room;
+        self.rooms.insert(room_id, Room::new(name.clone(), settings));
+
+        for (endpoint, _) in found.players {
+            let previous_room_id = self.room_of(endpoint);
+            if let Some(connection) = self.connections.get_mut(&endpoint) {
+                connection.set_room(room_id);
+            }
+
+            let message = ServerMessage::QueueStatus(QueueStatus::Matched(name.clone()));
+            self.metrics.send(&mut self.network, endpoint, message);
+
+            let message = ServerMessage::RoomStatus(name.clone(), RoomStatus::Joined);
+            self.metrics.send(&mut self.network, endpoint, message);
+
+            if self.subscriptions.contains(&endpoint) {
+                self.send_server_info(endpoint, room_id);
+            }
+            self.remove_unused_room(previous_room_id);
+        }
+
+        self.send_room_list();
+    }
+
+    /// Removes a room created by the clients once it has no sessions, game nor clients in it.
+    fn remove_unused_room(&mut self, room_id: RoomId) {
+        let unused = room_id != MAIN_ROOM
+            && self.rooms.get(&room_id).is_some_and(|room| room.is_unused())
+            && !self.connections.values().any(|connection| connection.room() == room_id);
+
+        if unused {
+            let room = self.rooms.remove(&room_id).unwrap();
+            logger::record!(Info, {room: room.name.as_str()}, "Room '{}' removed", room.name);
+            self.send_room_list();
+        }
+    }
+
+    fn process_login(&mut self, endpoint: Endpoint, player_symbol: char, password: Option<String>) {
+        // A logged client can not be moved to a match.
+        self.matchmaking.leave(endpoint);
+
+        let peer_ip = util::canonical_ip(endpoint.addr().ip());
+        let ban = self.ban_list
+            .find(Target::Player(player_symbol))
+            .or_else(|| self.ban_list.find(Target::Ip(peer_ip)));
+
+        let room_id = self.room_of(endpoint);
+        let room = self.rooms.get_mut(&room_id).unwrap();
+
         let status =
         if !util::is_valid_character(player_symbol) {
-            log::warn!("Invalid character symbol '{}' has tried to login", player_symbol);
+            logger::record!(
+                Warn, {endpoint: endpoint, player: player_symbol},
+                "Invalid character symbol '{}' has tried to login", player_symbol
+            );
             LoginStatus::InvalidPlayerName
         }
+        else if let Some(ban) = ban {
+            logger::record!(
+                Warn, {endpoint: endpoint, player: player_symbol},
+                "Banned player '{}' from {} has tried to login ({})", player_symbol, peer_ip, ban
+            );
+            LoginStatus::Banned(ban.remaining())
+        }
+        else if self.config.password.is_some() && password != self.config.password {
+            logger::record!(
+                Warn, {endpoint: endpoint, player: player_symbol},
+                "Player '{}' has tried to login with a wrong password", player_symbol
+            );
+            LoginStatus::WrongPassword
+        }
+        else if room.game.is_some()
+        && !room.sessions.sessions().any(|session| *session.user() == player_symbol) {
+            // Only possible if the game was started by the admin without the room full.
+            logger::record!(
+                Warn, {endpoint: endpoint, player: player_symbol},
+                "Player '{}' has tried to login but the game is already running", player_symbol
+            );
+            LoginStatus::PlayerLimit
+        }
         else {
-            match self.room.create_session(player_symbol, endpoint) {
+            match room.sessions.create_session(player_symbol, endpoint, peer_ip) {
                 SessionStatus::Created(token) => {
-                    let player_symbols = self.room
-                        .sessions()
-                        .map(|session| session.user())
-                        .sorted();
+                    let player_symbols = room.player_symbols().into_iter().sorted();
 
-                    log::info!(
+                    logger::record!(
+                        Info,
+                        {endpoint: endpoint, player: player_symbol, session: token, room: room.name.as_str()},
                         "New player logged: {}, current players: {}",
                         player_symbol,
                         util::format::items_to_string(player_symbols)
@@ -220,18 +908,23 @@ impl<'a> ServerManager<'a> {
                     LoginStatus::Logged(token, LoggedKind::FirstTime)
                 },
                 SessionStatus::Recycled(token) => {
-                    log::info!("Player '{}' reconnected", player_symbol);
+                    logger::record!(
+                        Info, {endpoint: endpoint, player: player_symbol, session: token},
+                        "Player '{}' reconnected", player_symbol
+                    );
                     LoginStatus::Logged(token, LoggedKind::Reconnection)
                 },
                 SessionStatus::AlreadyLogged => {
-                    log::warn!(
+                    logger::record!(
+                        Warn, {endpoint: endpoint, player: player_symbol},
                         "Player '{}' has tried to login but the character symbol is already logged",
                         player_symbol
                     );
                     LoginStatus::AlreadyLogged
                 },
                 SessionStatus::Full => {
-                    log::warn!(
+                    logger::record!(
+                        Warn, {endpoint: endpoint, player: player_symbol},
                         "Player '{}' has tried to login but the player limit has been reached",
                         player_symbol
                     );
@@ -247,39 +940,40 @@ impl<'a> ServerManager<'a> {
             status
         );
 
-        self.network.send(endpoint, ServerMessage::LoginStatus(player_symbol, status));
+        self.metrics.record_login(&status);
+        let message = ServerMessage::LoginStatus(player_symbol, status);
+        self.metrics.send(&mut self.network, endpoint, message);
 
         if let LoginStatus::Logged(_, kind) = status {
             match kind {
                 LoggedKind::FirstTime => {
-                    let player_symbols = self.room
-                        .sessions()
-                        .map(|session| *session.user())
-                        .collect();
+                    // A choice of a previous session with the same symbol is forgotten.
+                    self.rooms.get_mut(&room_id).unwrap().teams.remove(&player_symbol);
+                    self.send_room_players(room_id);
 
-                    let message = ServerMessage::DynamicServerInfo(player_symbols);
-                    self.network.send_all(self.subscriptions.iter(), message);
-
-                    if self.game.is_none() && self.room.is_full() {
-                        self.event_queue.sender().send(Event::AsyncCreateGame);
+                    let room = &self.rooms[&room_id];
+                    if room.game.is_none() && room.sessions.is_full() {
+                        self.event_queue.sender().send(Event::AsyncCreateGame(room_id));
                     }
                 },
                 LoggedKind::Reconnection => {
-                    if let Some(game) = &self.game {
+                    let room = &self.rooms[&room_id];
+                    if let Some(game) = &room.game {
                         let message = Self::create_start_game_message(game);
-                        self.network.send(endpoint, message);
+                        self.metrics.send(&mut self.network, endpoint, message);
 
-                        if let Some(waiting_from) = self.waiting_arena_from {
+                        if let Some(waiting_from) = room.waiting_arena_from {
                             let duration = Instant::now().duration_since(waiting_from);
                             let waiting = self.config.arena_waiting
                                 .checked_sub(duration)
                                 .unwrap_or(Duration::new(0, 0));
-                            self.network.send(endpoint, ServerMessage::WaitArena(waiting));
+                            let message = ServerMessage::WaitArena(waiting);
+                            self.metrics.send(&mut self.network, endpoint, message);
                         }
 
                         if let Some(_) = game.arena() {
                             let message = Self::create_start_arena_message(game);
-                            self.network.send(endpoint, message);
+                            self.metrics.send(&mut self.network, endpoint, message);
                         }
                     }
                 }
@@ -288,104 +982,733 @@ impl<'a> ServerManager<'a> {
     }
 
     fn process_logout(&mut self, endpoint: Endpoint) {
-        if self.game.is_some() {
-            if let Some(session) = self.room.session_by_endpoint_mut(endpoint) {
+        let room_id = self.room_of(endpoint);
+        let room = self.rooms.get_mut(&room_id).unwrap();
+        if room.game.is_some() {
+            if let Some(session) = room.sessions.session_by_endpoint_mut(endpoint) {
                 session.disconnect();
-                log::info!("Player '{}' disconnected", session.user());
+                logger::record!(
+                    Info, {endpoint: endpoint, player: *session.user()},
+                    "Player '{}' disconnected", session.user()
+                );
+            }
+
+            // Nobody would finish the game of an abandoned room, that could not be removed.
+            if room_id != MAIN_ROOM && room.sessions.safe_endpoints().next().is_none() {
+                logger::record!(Info, {room: room.name.as_str()}, "All the players left the game");
+                self.process_reset(room_id);
             }
         }
         else {
-            if let Some(session) = self.room.remove_session_by_endpoint(endpoint) {
-                let player_symbols = self.room
-                    .sessions()
-                    .map(|session| *session.user())
-                    .collect::<Vec<_>>();
-
-                log::info!(
+            if let Some(session) = room.sessions.remove_session_by_endpoint(endpoint) {
+                logger::record!(
+                    Info, {endpoint: endpoint, player: *session.user()},
                     "Player '{}' logout, current players: {} ",
                     session.user(),
-                    util::format::items_to_string(player_symbols.iter().sorted())
+                    util::format::items_to_string(room.player_symbols().iter().sorted())
                 );
 
-                let message = ServerMessage::DynamicServerInfo(player_symbols);
-                self.network.send_all(self.subscriptions.iter(), message);
+                self.send_room_players(room_id);
             }
         }
     }
 
     fn process_connect_udp(&mut self, udp_endpoint: Endpoint, session_token: SessionToken) {
-        match self.room.session_mut(session_token) {
-            Some(session) => {
-                log::trace!("Attached udp endpoint to session '{}'", session_token);
-                session.set_untrusted_fast_endpoint(udp_endpoint);
-                self.network.send(udp_endpoint, ServerMessage::UdpConnected);
+        let ip = util::canonical_ip(udp_endpoint.addr().ip());
+        let attachment = match self.rooms.values_mut().find(|room| room.sessions.has_token(session_token)) {
+            Some(room) => room.sessions.attach_fast_endpoint(session_token, udp_endpoint, ip),
+            None => Err(TokenRejection::Unknown),
+        };
+
+        match attachment {
+            Ok(session) => {
+                logger::record!(
+                    Trace, {endpoint: udp_endpoint, player: *session.user(), session: session_token},
+                    "Attached udp endpoint {} to session of player '{}'",
+                    udp_endpoint.addr(),
+                    session.user()
+                );
+                self.metrics.send(&mut self.network, udp_endpoint, ServerMessage::UdpConnected);
+            }
+            Err(rejection) => {
+                self.counters.udp_rejections += 1;
+                let reason = match rejection {
+                    TokenRejection::Unknown => "unknown token".into(),
+                    TokenRejection::Expired => "expired token".into(),
+                    TokenRejection::ForeignAddress(expected_ip) =>
+                        format!("the session belongs to {}", expected_ip),
+                };
+                logger::record!(
+                    Warn, {endpoint: udp_endpoint, session: session_token},
+                    "Rejected udp endpoint {} for session {:?}: {}. Total rejections: {}",
+                    udp_endpoint.addr(),
+                    session_token,
+                    reason,
+                    self.counters.udp_rejections,
+                );
             }
-            None =>
-                log::warn!(
-                    "Attempt to attach udp endpoint to non-existent session '{}'",
-                    session_token
-                )
         }
     }
 
     fn process_trust_udp(&mut self, related_tcp_endpoint: Endpoint) {
-        match self.room.session_by_endpoint_mut(related_tcp_endpoint) {
+        let room_id = self.room_of(related_tcp_endpoint);
+        match self.rooms.get_mut(&room_id).unwrap().sessions.session_by_endpoint_mut(related_tcp_endpoint) {
             Some(session) => match session.trust_in_fast_endpoint() {
                 Some(_) =>
                     log::trace!(
-                        "Trusted udp endpoint for session '{}'",
-                        session.token()
+                        "Trusted udp endpoint for session of player '{}'",
+                        session.user()
                     ),
                 None =>
                     log::error!(
-                        "Attempt to trust into a non-existent udp endpoint. Session '{}'",
-                        session.token()
+                        "Attempt to trust into a non-existent udp endpoint. Player '{}'",
+                        session.user()
                     ),
             },
             None => log::error!("Attempt to trust an udp endpoint in an non-existent session"),
         }
     }
 
-    fn process_create_game(&mut self) {
-        log::info!("Starting new game");
-        let player_symbols = self.room.sessions().map(|session| *session.user());
+    fn process_ping(&mut self) {
+        let (last_id, _) = self.last_ping;
+        let id = PingId::next(last_id);
+        self.last_ping = (id, Instant::now());
+        let endpoints = self.connections
+            .keys()
+            .filter(|endpoint| self.has_capability(**endpoint, Capability::Latency))
+            .copied()
+            .collect::<Vec<_>>();
+        self.metrics.send_all(&mut self.network, endpoints.iter(), ServerMessage::Ping(id));
+
+        let room_pings = self.rooms
+            .values()
+            .filter_map(|room| room.game.as_ref().map(|game| (room, game)))
+            .map(|(room, game)| {
+                let pings = game
+                    .players()
+                    .keys()
+                    .map(|symbol| self.player_latency(room, *symbol))
+                    .collect();
+
+                let endpoints = room.sessions
+                    .safe_endpoints()
+                    .filter(|endpoint| self.has_capability(**endpoint, Capability::Latency))
+                    .copied()
+                    .collect::<Vec<_>>();
+
+                (pings, endpoints)
+            })
+            .collect::<Vec<_>>();
+
+        for (pings, endpoints) in room_pings {
+            let event = GameEvent::PlayerPingsUpdated(pings);
+            let message = ServerMessage::GameEvent(event);
+            self.metrics.send_all(&mut self.network, endpoints.iter(), message);
+        }
+
+        if id.0.is_multiple_of(LATENCY_LOG_PERIOD) {
+            for room in self.rooms.values().filter(|room| !room.is_unused()) {
+                let player_latencies = room
+                    .player_symbols()
+                    .into_iter()
+                    .sorted()
+                    .map(|symbol| {
+                        let latency = match self.player_latency(room, symbol) {
+                            Some(latency) => format!("{}ms", latency.as_millis()),
+                            None => "-".into(),
+                        };
+                        (symbol, latency)
+                    })
+                    .collect::<Vec<_>>();
+
+                if !player_latencies.is_empty() {
+                    logger::record!(
+                        Info, {room: room.name.as_str()},
+                        "Latencies in the room '{}': {}",
+                        room.name,
+                        util::format::pair_items_to_string(player_latencies)
+                    );
+                }
+            }
+        }
+
+        self.event_queue.sender().send_with_timer(Event::Ping, PING_INTERVAL);
+    }
+
+    fn process_pong(&mut self, endpoint: Endpoint, id: PingId) {
+        let (last_id, ping_time) = self.last_ping;
+        if id == last_id {
+            if let Some(connection) = self.connections.get_mut(&endpoint) {
+                let latency = Instant::now().duration_since(ping_time);
+                connection.set_latency(latency);
+                log::trace!("Latency with {}: {}ms", endpoint.addr(), latency.as_millis());
+            }
+        }
+    }
+
+    /// Returns false if the message should be discarded because of the rate limit.
+    /// Abusive tcp connections are closed. For udp, only its messages are discarded.
+    fn process_message_rate(&mut self, endpoint: Endpoint) -> bool {
+        let now = Instant::now();
+        match self.connections.get_mut(&endpoint) {
+            Some(connection) => {
+                if connection.receive_message(now) {
+                    return true
+                }
+                logger::record!(
+                    Warn, {endpoint: endpoint},
+                    "{} exceeded the rate limit of {} messages per second. Connection closed",
+                    endpoint.addr(),
+                    self.config.message_rate,
+                );
+                self.network.remove_resource(endpoint.resource_id());
+                self.process_disconnection(endpoint);
+                false
+            }
+            None => {
+                let message_rate = self.config.message_rate;
+                let connection = self.udp_connections
+                    .entry(endpoint)
+                    .or_insert_with(|| Connection::new(message_rate));
+
+                let was_throttled = connection.is_throttled();
+                if connection.receive_message(now) {
+                    return true
+                }
+                if !was_throttled {
+                    logger::record!(
+                        Warn, {endpoint: endpoint},
+                        "{} exceeded the rate limit of {} messages per second by udp. \
+                        Its messages will be discarded",
+                        endpoint.addr(),
+                        message_rate,
+                    );
+                }
+                false
+            }
+        }
+    }
+
+    fn process_check_idle(&mut self) {
+        let now = Instant::now();
+        let idle_endpoints = self.connections
+            .iter()
+            .filter(|(_, connection)| match connection.capabilities() {
+                // Without latency messages the client has no heartbeats.
+                Some(capabilities) => capabilities.contains(Capability::Latency),
+                None => true,
+            })
+            .filter(|(_, connection)| connection.idle_time(now) > self.config.idle_timeout)
+            .map(|(endpoint, _)| *endpoint)
+            .collect::<Vec<_>>();
+
+        for endpoint in idle_endpoints {
+            logger::record!(
+                Warn, {endpoint: endpoint},
+                "{} has not sent any message in {} seconds. Connection closed",
+                endpoint.addr(),
+                self.config.idle_timeout.as_secs_f32(),
+            );
+            self.network.remove_resource(endpoint.resource_id());
+            self.process_disconnection(endpoint);
+        }
+
+        // The udp endpoints are only forgotten, the resource is shared by all of them.
+        let idle_timeout = self.config.idle_timeout;
+        self.udp_connections.retain(|_, connection| connection.idle_time(now) <= idle_timeout);
+
+        self.event_queue.sender().send_with_timer(Event::CheckIdle, IDLE_CHECK_INTERVAL);
+    }
+
+    fn player_latency(&self, room: &Room, symbol: char) -> Option<Duration> {
+        room.sessions
+            .sessions()
+            .find(|session| *session.user() == symbol)
+            .and_then(|session| session.safe_endpoint().as_ref())
+            .and_then(|endpoint| self.connections.get(endpoint))
+            .and_then(|connection| connection.latency())
+    }
+
+    fn status_json(&self) -> String {
+        let rooms = self.rooms
+            .iter()
+            .sorted_by_key(|(room_id, _)| room_id.0)
+            .map(|(_, room)| self.room_state(room))
+            .collect();
+
+        let status = ServerStatus {
+            name: self.config.name.clone(),
+            version: version::current().into(),
+            protocol: version::PROTOCOL_VERSION,
+            uptime: self.start_time.elapsed().as_secs(),
+            rooms,
+            queued: self.matchmaking.len(),
+            connections: self.connections.len(),
+            counters: self.counters,
+        };
+
+        serde_json::to_string_pretty(&status).unwrap()
+    }
+
+    fn room_state(&self, room: &Room) -> RoomState {
+        let game = room.game.as_ref().map(|game| GameState {
+            arena_number: game.arena_number(),
+            waiting_arena: room.waiting_arena_from.is_some(),
+            winner_points: game.winner_points(),
+            living_players: game.living_players().into_iter().collect(),
+            points: game
+                .pole()
+                .iter()
+                .map(|player| PlayerPoints {
+                    player: player.character().symbol(),
+                    points: player.points(),
+                })
+                .collect(),
+        });
+
+        let sessions = room.sessions
+            .sessions()
+            .sorted_by_key(|session| *session.user())
+            .map(|session| SessionState {
+                player: *session.user(),
+                ip: session.peer_ip().to_string(),
+                connected: session.safe_endpoint().is_some(),
+                udp_attached: session.fast_endpoint().is_some(),
+                udp_trusted: session.is_fast_endpoint_trusted(),
+                latency: self.player_latency(room, *session.user())
+                    .map(|latency| latency.as_millis() as u64),
+            })
+            .collect();
+
+        RoomState {
+            name: room.name.clone(),
+            players_number: room.settings.players_number,
+            map_size: room.settings.map_size,
+            winner_points: room.settings.winner_points,
+            game,
+            sessions,
+        }
+    }
+
+    fn process_check_config(&mut self) {
+        if self.config_loader.is_file_modified() {
+            match self.config_loader.load() {
+                Ok(config) => {
+                    let restart_settings = self.config.restart_settings_changed(&config);
+                    if !restart_settings.is_empty() {
+                        log::warn!(
+                            "Changes in {} will be applied restarting the server",
+                            util::format::items_to_string(restart_settings)
+                        );
+                    }
+
+                    // The running games of all the rooms read the config in each step.
+                    let game_running = self.is_game_running();
+                    match game_running {
+                        true => log::info!("Config reloaded, it will be applied when no game is running"),
+                        false => log::info!("Config reloaded"),
+                    }
+                    self.next_config = Some(config);
+                    if !game_running {
+                        self.apply_next_config();
+                    }
+                },
+                Err(error) => log::error!("{}. The previous config is kept", error),
+            }
+        }
+
+        self.event_queue.sender().send_with_timer(Event::CheckConfig, CONFIG_CHECK_INTERVAL);
+    }
+
+    fn is_game_running(&self) -> bool {
+        self.rooms.values().any(|room| room.game.is_some())
+    }
+
+    fn apply_next_config(&mut self) {
+        if let Some(next_config) = self.next_config.take() {
+            self.config.apply_game_settings(&next_config);
+            self.rooms.get_mut(&MAIN_ROOM).unwrap().settings = self.config.room_settings();
+            log::trace!(
+                "Game settings: map {} ({:?}), {} winner points, {} steps per second",
+                self.config.map_size,
+                self.config.map_generator,
+                self.config.winner_points,
+                self.config.tick_rate,
+            );
+        }
+    }
+
+    fn process_admin_command(&mut self, command: AdminCommand) {
+        match command {
+            AdminCommand::Sessions => self.print_sessions(),
+            AdminCommand::Endpoints => self.print_endpoints(),
+            AdminCommand::Kick(target) => match self.kick(target) {
+                true => log::info!("Admin kicked the {}", target),
+                false => println!("There are no sessions or connections of the {}", target),
+            },
+            AdminCommand::Ban(target, duration) => {
+                self.ban_list.ban(target, duration);
+                self.kick(target);
+                match duration {
+                    Some(duration) => log::info!(
+                        "Admin banned the {} for {} minutes",
+                        target,
+                        duration.as_secs() / 60
+                    ),
+                    None => log::info!("Admin banned the {}", target),
+                }
+            },
+            AdminCommand::Unban(target) => match self.ban_list.unban(target) {
+                true => log::info!("Admin unbanned the {}", target),
+                false => println!("The {} is not banned", target),
+            },
+            AdminCommand::Bans => {
+                let bans = self.ban_list
+                    .bans()
+                    .map(|(target, ban)| format!("  {} ({})", target, ban))
+                    .sorted()
+                    .collect::<Vec<_>>();
+
+                match bans.is_empty() {
+                    true => println!("No bans"),
+                    false => println!("Bans:\n{}", bans.join("\n")),
+                }
+            },
+            AdminCommand::Rooms => self.print_rooms(),
+            AdminCommand::Start(room) => {
+                if let Some(room_id) = self.admin_room(room) {
+                    self.process_force_start(room_id);
+                }
+            },
+            AdminCommand::EndArena(room) => {
+                if let Some(room_id) = self.admin_room(room) {
+                    self.process_end_arena(room_id);
+                }
+            },
+            AdminCommand::WinnerPoints(points, room) => {
+                if let Some(room_id) = self.admin_room(room) {
+                    self.process_winner_points(room_id, points);
+                }
+            },
+            AdminCommand::Broadcast(message) => {
+                log::info!("Admin broadcast: {}", message);
+                let endpoints = self.connections
+                    .iter()
+                    .filter(|(_, connection)| connection.capabilities().is_some()) // Version checked
+                    .map(|(endpoint, _)| *endpoint)
+                    .collect::<Vec<_>>();
+                let message = ServerMessage::Broadcast(message);
+                self.metrics.send_all(&mut self.network, endpoints.iter(), message);
+            },
+            AdminCommand::Stop(reason) => {
+                let reason = reason.unwrap_or_else(|| CLOSING_REASON.into());
+                self.process_shutdown(reason, None);
+            },
+            AdminCommand::Restart(restart_in, reason) => {
+                let reason = reason.unwrap_or_else(|| RESTARTING_REASON.into());
+                self.process_shutdown(reason, Some(restart_in));
+            },
+        }
+    }
+
+    /// Room named in an admin command, the main room if no name is given.
+    fn admin_room(&self, name: Option<String>) -> Option<RoomId> {
+        match name {
+            Some(name) => {
+                let room_id = self.find_room(&name);
+                if room_id.is_none() {
+                    println!("There is no room '{}'", name);
+                }
+                room_id
+            },
+            None => Some(MAIN_ROOM),
+        }
+    }
+
+    fn print_rooms(&self) {
+        println!(
+            "Rooms: {} of {} created by the clients. Queued clients: {}",
+            self.rooms.len() - 1,
+            self.config.max_rooms,
+            self.matchmaking.len()
+        );
+        for (room_id, room) in self.rooms.iter().sorted_by_key(|(room_id, _)| room_id.0) {
+            let game = match &room.game {
+                Some(_) if room.waiting_arena_from.is_some() => "waiting arena".into(),
+                Some(game) => format!("arena {}", game.arena_number()),
+                None => "-".into(),
+            };
+            let clients = self.connections
+                .values()
+                .filter(|connection| connection.room() == *room_id)
+                .count();
+            println!(
+                "  '{}' players: {} of {}, map: {}, points: {}, game: {}, clients: {}",
+                room.name,
+                room.sessions.sessions().count(),
+                room.settings.players_number,
+                room.settings.map_size,
+                room.settings.winner_points,
+                game,
+                clients,
+            );
+        }
+    }
+
+    fn print_sessions(&self) {
+        for (_, room) in self.rooms.iter().sorted_by_key(|(room_id, _)| room_id.0) {
+            let sessions = room.sessions.sessions().count();
+            if sessions == 0 && room.name != MAIN_ROOM_NAME {
+                continue
+            }
+            println!(
+                "Sessions in the room '{}': {} of {} players",
+                room.name,
+                sessions,
+                room.settings.players_number
+            );
+            for session in room.sessions.sessions().sorted_by_key(|session| *session.user()) {
+                let tcp = match session.safe_endpoint() {
+                    Some(endpoint) => endpoint.addr().to_string(),
+                    None => "disconnected".into(),
+                };
+                let udp = match session.fast_endpoint() {
+                    Some(endpoint) if session.is_fast_endpoint_trusted() => endpoint.addr().to_string(),
+                    Some(endpoint) => format!("{} (untrusted)", endpoint.addr()),
+                    None => "-".into(),
+                };
+                let latency = match self.player_latency(room, *session.user()) {
+                    Some(latency) => format!("{}ms", latency.as_millis()),
+                    None => "-".into(),
+                };
+                println!(
+                    "  '{}' ip: {}, tcp: {}, udp: {}, latency: {}",
+                    session.user(),
+                    session.peer_ip(),
+                    tcp,
+                    udp,
+                    latency,
+                );
+            }
+        }
+    }
+
+    fn print_endpoints(&self) {
+        let now = Instant::now();
+        println!("Tcp connections: {}", self.connections.len());
+        for (endpoint, connection) in self.connections.iter().sorted_by_key(|(endpoint, _)| endpoint.addr()) {
+            let room = &self.rooms[&connection.room()];
+            let player = match room.sessions.session_by_endpoint(*endpoint) {
+                Some(session) => format!("'{}'", session.user()),
+                None => "-".into(),
+            };
+            let latency = match connection.latency() {
+                Some(latency) => format!("{}ms", latency.as_millis()),
+                None => "-".into(),
+            };
+            let capabilities = match connection.capabilities() {
+                Some(capabilities) => format!("{:?}", capabilities),
+                None => "not negotiated".into(),
+            };
+            println!(
+                "  {} room: '{}', player: {}, subscribed: {}, latency: {}, idle: {:.1}s, capabilities: {}",
+                endpoint.addr(),
+                room.name,
+                player,
+                self.subscriptions.contains(endpoint),
+                latency,
+                connection.idle_time(now).as_secs_f32(),
+                capabilities,
+            );
+        }
+
+        println!("Udp endpoints: {}", self.udp_connections.len());
+        for (endpoint, connection) in self.udp_connections.iter().sorted_by_key(|(endpoint, _)| endpoint.addr()) {
+            println!("  {} idle: {:.1}s", endpoint.addr(), connection.idle_time(now).as_secs_f32());
+        }
+    }
+
+    /// Removes the sessions and closes the connections of the target.
+    /// Returns false if the target has neither sessions nor connections.
+    fn kick(&mut self, target: Target) -> bool {
+        let players = self.rooms
+            .iter()
+            .flat_map(|(room_id, room)| {
+                room.sessions
+                    .sessions()
+                    .filter(|session| match target {
+                        Target::Player(symbol) => *session.user() == symbol,
+                        Target::Ip(ip) => session.peer_ip() == ip,
+                    })
+                    .map(move |session| (*room_id, *session.user()))
+            })
+            .collect::<Vec<_>>();
+
+        let mut endpoints = self.connections
+            .keys()
+            .filter(|endpoint| match target {
+                Target::Player(_) => false,
+                Target::Ip(ip) => util::canonical_ip(endpoint.addr().ip()) == ip,
+            })
+            .copied()
+            .collect::<HashSet<_>>();
+
+        // Removed before the disconnection to avoid keeping them for a reconnection.
+        for (room_id, symbol) in &players {
+            let room = self.rooms.get_mut(room_id).unwrap();
+            let session = room.sessions.remove_session_by_user(symbol).unwrap();
+            endpoints.extend(session.safe_endpoint());
+            logger::record!(
+                Info, {player: *symbol, room: room.name.as_str()},
+                "Player '{}' kicked", symbol
+            );
+        }
+
+        for &endpoint in &endpoints {
+            self.network.remove_resource(endpoint.resource_id());
+            self.process_disconnection(endpoint);
+        }
+
+        for room_id in players.iter().map(|(room_id, _)| *room_id).unique() {
+            if self.rooms.get(&room_id).is_some_and(|room| room.game.is_none()) {
+                self.send_room_players(room_id);
+            }
+        }
+
+        !players.is_empty() || !endpoints.is_empty()
+    }
+
+    fn process_force_start(&mut self, room_id: RoomId) {
+        let room = &self.rooms[&room_id];
+        let players = room.sessions.sessions().count();
+        if room.game.is_some() {
+            println!("The game is already running");
+        }
+        else if players < 2 {
+            println!("At least 2 logged players are needed to start the game");
+        }
+        else {
+            logger::record!(
+                Info, {room: room.name.as_str()},
+                "Admin started the game with {} of {} players", players, room.settings.players_number
+            );
+            self.event_queue.sender().send(Event::AsyncCreateGame(room_id));
+        }
+    }
+
+    fn process_end_arena(&mut self, room_id: RoomId) {
+        let room = &self.rooms[&room_id];
+        match room.running_arena() {
+            Some(number) => {
+                logger::record!(
+                    Info, {room: room.name.as_str(), arena: number},
+                    "Admin ended the arena {}", number
+                );
+                self.process_wait_arena(room_id);
+            },
+            None => println!("There is no arena running"),
+        }
+    }
+
+    fn process_winner_points(&mut self, room_id: RoomId, points: usize) {
+        // The main room follows the server config, the rest of rooms have their own settings.
+        if room_id == MAIN_ROOM {
+            self.config.winner_points = points;
+            if let Some(next_config) = &mut self.next_config {
+                next_config.winner_points = points;
+            }
+        }
+
+        let room = self.rooms.get_mut(&room_id).unwrap();
+        logger::record!(
+            Info, {room: room.name.as_str()},
+            "Admin changed the winner points of the room '{}' to {}", room.name, points
+        );
+        room.settings.winner_points = points as u16;
+        if let Some(game) = &mut room.game {
+            game.set_winner_points(points);
+            let event = GameEvent::WinnerPointsChanged(points);
+            let message = ServerMessage::GameEvent(event);
+            self.metrics.send_all(&mut self.network, room.sessions.safe_endpoints(), message);
+        }
+    }
+
+    fn process_create_game(&mut self, room_id: RoomId) {
+        match self.rooms.get(&room_id) {
+            Some(room) if room.game.is_none() => (),
+            _ => return, // Already started by the admin
+        }
+
+        if !self.is_game_running() {
+            self.apply_next_config();
+        }
+
+        let room = self.rooms.get_mut(&room_id).unwrap();
+        let player_symbols = room.player_symbols();
+        let teams = match self.config.mode.has_teams() {
+            true => mode::assign_teams(&player_symbols, &room.teams, self.config.teams),
+            false => player_symbols.into_iter().map(|symbol| (symbol, TeamId::NONE)).collect(),
+        };
         let game = Game::new(
-            self.config.map_size,
-            self.config.winner_points,
-            player_symbols
+            room.settings.map_size as usize,
+            self.config.map_generator,
+            room.settings.winner_points as usize,
+            mode::create(self.config.mode),
+            self.config.friendly_fire,
+            teams.into_iter()
         );
+        logger::record!(Info, {room: room.name.as_str()}, "Starting new {} game", game.mode().name());
 
         let message = Self::create_start_game_message(&game);
-        self.network.send_all(self.room.safe_endpoints(), message);
+        self.metrics.send_all(&mut self.network, room.sessions.safe_endpoints(), message);
 
-        self.game = Some(game);
-        self.process_wait_arena();
+        room.game = Some(game);
+        self.metrics.record_game();
+        self.send_room_list();
+        self.process_wait_arena(room_id);
 
-        self.event_queue.sender().send(Event::GameStep);
+        self.event_queue.sender().send(Event::GameStep(room_id));
     }
 
-    fn process_wait_arena(&mut self) {
+    fn process_wait_arena(&mut self, room_id: RoomId) {
         log::trace!(
             "Initializing next arena in {} seconds...",
             self.config.arena_waiting.as_secs_f32()
         );
 
+        let room = self.rooms.get_mut(&room_id).unwrap();
         let message = ServerMessage::WaitArena(self.config.arena_waiting);
-        self.network.send_all(self.room.safe_endpoints(), message);
+        self.metrics.send_all(&mut self.network, room.sessions.safe_endpoints(), message);
+
+        if let Some(game) = &mut room.game {
+            game.end_arena();
+        }
 
+        let waiting_from = Instant::now();
         self.event_queue
             .sender()
-            .send_with_timer(Event::AsyncStartArena, self.config.arena_waiting);
+            .send_with_timer(Event::AsyncStartArena(room_id, waiting_from), self.config.arena_waiting);
 
-        self.waiting_arena_from = Some(Instant::now());
+        room.waiting_arena_from = Some(waiting_from);
+        self.process_pending_shutdown();
     }
 
-    fn process_start_arena(&mut self) {
-        self.waiting_arena_from = None;
-        self.game.as_mut().unwrap().create_new_arena();
-        let game = self.game.as_ref().unwrap();
+    fn process_start_arena(&mut self, room_id: RoomId, waiting_from: Instant) {
+        // The game that was waiting could have finished or been replaced meanwhile.
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) if room.game.is_some() && room.waiting_arena_from == Some(waiting_from) => room,
+            _ => return,
+        };
+
+        room.waiting_arena_from = None;
+        room.game.as_mut().unwrap().create_new_arena();
+        self.metrics.record_arena();
+        let game = room.game.as_ref().unwrap();
         let arena = game.arena().unwrap();
-        log::info!("Start arena {}", game.arena_number());
+        logger::record!(
+            Info, {room: room.name.as_str(), arena: game.arena_number()},
+            "Start arena {}", game.arena_number()
+        );
 
         let entities = arena.entities();
         let player_positions = game
@@ -405,114 +1728,308 @@ impl<'a> ServerManager<'a> {
         log::trace!("Player positions: {}", util::format::pair_items_to_string(player_positions));
 
         let message = Self::create_start_arena_message(game);
-        self.network.send_all(self.room.safe_endpoints(), message);
+        self.metrics.send_all(&mut self.network, room.sessions.safe_endpoints(), message);
     }
 
-    fn process_game_step(&mut self) {
+    fn process_game_step(&mut self, room_id: RoomId) {
         log::trace!("Processing step");
+        let step_start = Instant::now();
 
-        let game = self.game.as_mut().unwrap();
-        let previous_players = game.living_players().len();
+        // The game could have been reset while the step was scheduled.
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) if room.game.is_some() => room,
+            _ => return,
+        };
+
+        let game = room.game.as_mut().unwrap();
+        let previous_points = game.players().values().map(|player| player.points()).collect::<Vec<_>>();
+        let previous_entities = game.players().values().map(|player| player.entity_id()).collect::<Vec<_>>();
 
-        game.step();
+        let events = game.step();
+        self.metrics.record_arena_events(&events);
 
         if let Some(arena) = game.arena() {
             let message = Self::create_game_step_message(&arena);
-            self.network.send_all(self.room.faster_endpoints(), message);
+            self.metrics.send_all(&mut self.network, room.sessions.faster_endpoints(), message);
+            self.counters.frames_sent += room.sessions.faster_endpoints().count() as u64;
+        }
+
+        for event in &events {
+            match *event {
+                ArenaEvent::SpellCast { spell, spec, caster } => {
+                    log::trace!("Spell {:?} ({:?}) cast by entity {:?}", spell, spec, caster);
+                },
+                ArenaEvent::SpellDestroyed(spell) => {
+                    log::trace!("Spell {:?} destroyed", spell);
+                },
+                ArenaEvent::EntityMoved { entity, from, to } => {
+                    log::trace!("Entity {:?} moved from {:?} to {:?}", entity, from, to);
+                },
+                ArenaEvent::EntityDamaged { entity, caster, spell, damage } => {
+                    log::trace!("Entity {:?} damaged by {:?} with {:?}: -{}", entity, caster, spell, damage);
+                },
+                ArenaEvent::EntityHealed { entity, caster, spell, health } => {
+                    log::trace!("Entity {:?} healed by {:?} with {:?}: +{}", entity, caster, spell, health);
+                },
+                ArenaEvent::EntityDied { entity, killer, spell } => {
+                    if let Some(symbol) = game.player_of_entity(entity) {
+                        match game.player_of_entity(killer) {
+                            Some(killer_symbol) => logger::record!(
+                                Debug, {room: room.name.as_str(), player: symbol},
+                                "Player '{}' killed by '{}'", symbol, killer_symbol
+                            ),
+                            None => logger::record!(
+                                Debug, {room: room.name.as_str(), player: symbol},
+                                "Player '{}' died", symbol
+                            ),
+                        }
+                    }
+
+                    let event = GameEvent::EntityKilled { victim: entity, killer, spell };
+                    let message = ServerMessage::GameEvent(event);
+                    self.metrics.send_all(&mut self.network, room.sessions.safe_endpoints(), message);
+                },
+            }
+        }
+
+        let respawned_players = game
+            .players()
+            .iter()
+            .enumerate()
+            .filter(|(index, (_, player))| {
+                player.is_alive() && player.entity_id() != previous_entities[*index]
+            })
+            .map(|(index, (symbol, player))| (index, *symbol, player.entity_id()))
+            .collect::<Vec<_>>();
+
+        for (index, symbol, entity_id) in respawned_players {
+            log::trace!("Player '{}' respawned", symbol);
+            let event = GameEvent::PlayerRespawned(index, entity_id);
+            let message = ServerMessage::GameEvent(event);
+            self.metrics.send_all(&mut self.network, room.sessions.safe_endpoints(), message);
         }
 
-        let current_players = game.living_players().len();
+        let points = game
+            .players()
+            .values()
+            .map(|player| player.points())
+            .collect::<Vec<_>>();
 
-        if current_players < previous_players {
+        if points != previous_points {
             let player_total_points_pairs = game
                 .pole()
                 .iter()
                 .map(|player| (player.character().symbol(), player.points()))
                 .collect::<Vec<_>>();
 
-            log::info!(
+            logger::record!(
+                Info, {room: room.name.as_str()},
                 "Points: {}",
                 util::format::pair_items_to_string(player_total_points_pairs)
             );
 
-            let points = game
-                .players()
-                .values()
-                .map(|player| player.points())
-                .collect();
-
             let event = GameEvent::PlayerPointsUpdated(points);
-            self.network.send_all(self.room.safe_endpoints(), ServerMessage::GameEvent(event));
+            let message = ServerMessage::GameEvent(event);
+            self.metrics.send_all(&mut self.network, room.sessions.safe_endpoints(), message);
         }
 
-        let game = self.game.as_ref().unwrap();
+        self.metrics.record_step(step_start.elapsed(), self.config.step_duration());
+
+        let room = &self.rooms[&room_id];
+        let game = room.game.as_ref().unwrap();
         if game.has_finished() {
-            log::info!("End game");
-            self.network.send_all(self.room.safe_endpoints(), ServerMessage::FinishGame);
-            self.process_reset();
+            logger::record!(Info, {room: room.name.as_str()}, "End game");
+            let points = game
+                .pole()
+                .iter()
+                .map(|player| (player.character().symbol(), player.points()))
+                .collect::<Vec<_>>();
+
+            self.matchmaking.record_game(&points);
+            let ratings = points
+                .iter()
+                .filter_map(|(symbol, _)| Some((symbol, self.matchmaking.rating(*symbol)?)))
+                .collect::<Vec<_>>();
+
+            if !ratings.is_empty() {
+                logger::record!(
+                    Info, {room: room.name.as_str()},
+                    "Ratings: {}", util::format::pair_items_to_string(ratings)
+                );
+            }
+
+            let message = ServerMessage::FinishGame;
+            self.metrics.send_all(&mut self.network, room.sessions.safe_endpoints(), message);
+            self.process_reset(room_id);
+            self.process_pending_shutdown();
         }
         else {
-            if current_players <= 1 && self.waiting_arena_from.is_none() {
-                log::info!("End arena");
-                self.process_wait_arena();
+            if game.has_arena_finished() && room.waiting_arena_from.is_none() {
+                logger::record!(Info, {room: room.name.as_str(), arena: game.arena_number()}, "End arena");
+                self.process_wait_arena(room_id);
             }
-            self.event_queue.sender().send_with_timer(Event::GameStep, *GAME_STEP_DURATION);
+            let step_duration = self.config.step_duration();
+            self.event_queue.sender().send_with_timer(Event::GameStep(room_id), step_duration);
         }
     }
 
     fn process_move_player(&mut self, endpoint: Endpoint, direction: Direction) {
-        match self.room.session_by_endpoint(endpoint) {
-            Some(session) => match self.game.as_mut() {
+        let room = self.rooms.get_mut(&self.room_of(endpoint)).unwrap();
+        match room.sessions.session_by_endpoint(endpoint) {
+            Some(session) => match room.game.as_mut() {
                 Some(game) => {
                     let player = game.player_mut(*session.user()).unwrap();
                     if player.is_alive() {
                         player.walk(direction);
                     }
                 }
-                None => log::warn!("Client attempted to move a player without a created game")
+                None => logger::record!(
+                    Warn, {endpoint: endpoint, player: *session.user()},
+                    "Client attempted to move a player without a created game"
+                ),
             }
-            None => log::warn!("Unlogged client attempted to move a player. Maybe an attack?")
+            None => logger::record!(
+                Warn, {endpoint: endpoint},
+                "Unlogged client attempted to move a player. Maybe an attack?"
+            ),
         };
     }
 
     fn process_cast_skill(&mut self, endpoint: Endpoint, direction: Direction, id: SkillId) {
-        match self.room.session_by_endpoint(endpoint) {
-            Some(session) => match self.game.as_mut() {
+        let room = self.rooms.get_mut(&self.room_of(endpoint)).unwrap();
+        match room.sessions.session_by_endpoint(endpoint) {
+            Some(session) => match room.game.as_mut() {
                 Some(game) => {
                     let player = game.player_mut(*session.user()).unwrap();
                     if player.is_alive() {
                         player.cast(direction, id);
                     }
                 }
-                None => log::warn!("Client attempted to cast a skill without a created game")
+                None => logger::record!(
+                    Warn, {endpoint: endpoint, player: *session.user()},
+                    "Client attempted to cast a skill without a created game"
+                ),
             }
-            None => log::warn!("Unlogged client attempted to cast a skill. Maybe an attack?")
+            None => logger::record!(
+                Warn, {endpoint: endpoint},
+                "Unlogged client attempted to cast a skill. Maybe an attack?"
+            ),
         };
     }
 
-    fn process_reset(&mut self) {
-        log::info!("Reset server");
-        self.game = None;
-        self.room.clear();
+    fn process_reset(&mut self, room_id: RoomId) {
+        let room = self.rooms.get_mut(&room_id).unwrap();
+        logger::record!(Info, {room: room.name.as_str()}, "Reset the room '{}'", room.name);
+        room.game = None;
+        room.waiting_arena_from = None;
+        room.sessions.clear();
 
-        let player_symbols = self.room
-            .sessions()
-            .map(|session| *session.user())
-            .collect();
+        if !self.is_game_running() {
+            self.apply_next_config();
+        }
 
-        let message = ServerMessage::DynamicServerInfo(player_symbols);
-        self.network.send_all(self.subscriptions.iter(), message);
+        self.send_room_players(room_id);
+        self.remove_unused_room(room_id);
     }
 
     fn process_disconnection(&mut self, endpoint: Endpoint) {
+        let room_id = self.room_of(endpoint);
         if self.subscriptions.remove(&endpoint) {
             log::trace!("Client {} has unsubscribed to server info", endpoint.addr());
         }
         self.process_logout(endpoint);
+        self.matchmaking.leave(endpoint);
+        self.connections.remove(&endpoint);
+        self.remove_unused_room(room_id);
+
+        if let Some(Closing::Draining) = self.closing {
+            if self.connections.is_empty() {
+                self.event_queue.sender().send_with_priority(Event::Close);
+            }
+        }
+    }
+
+    /// Closes the server informing the clients, after the current arena if configured.
+    /// Requested again while the server is closing, the waiting is skipped.
+    fn process_shutdown(&mut self, reason: String, restart_in: Option<Duration>) {
+        match self.closing.take() {
+            Some(Closing::AfterArena(reason, restart_in)) => {
+                log::info!("Closing server without waiting for the end of the arenas");
+                return self.announce_shutdown(reason, restart_in)
+            }
+            Some(Closing::Draining) => {
+                log::info!("Closing server without waiting for the clients");
+                self.closing = Some(Closing::Draining);
+                return self.event_queue.sender().send_with_priority(Event::Close)
+            }
+            None => (),
+        }
+
+        let running_arenas = self.rooms
+            .values()
+            .filter(|room| room.running_arena().is_some())
+            .count();
+
+        if running_arenas > 0 && self.config.finish_arena_on_close {
+            log::info!(
+                "The server will close at the end of the running arenas ({}). Repeat to close it now",
+                running_arenas
+            );
+            self.closing = Some(Closing::AfterArena(reason, restart_in));
+        }
+        else {
+            self.announce_shutdown(reason, restart_in);
+        }
+    }
+
+    fn process_pending_shutdown(&mut self) {
+        let running_arena = self.rooms.values().any(|room| room.running_arena().is_some());
+        match self.closing.take() {
+            Some(Closing::AfterArena(reason, restart_in)) if !running_arena =>
+                self.announce_shutdown(reason, restart_in),
+            closing => self.closing = closing,
+        }
+    }
+
+    /// Sends the shutdown to the clients and waits for them to close the connections,
+    /// so the messages already sent are received before closing the server.
+    fn announce_shutdown(&mut self, reason: String, restart_in: Option<Duration>) {
+        match restart_in {
+            Some(restart_in) => log::info!(
+                "Announcing the shutdown, restarting in {} seconds: {}",
+                restart_in.as_secs(),
+                reason
+            ),
+            None => log::info!("Announcing the shutdown: {}", reason),
+        }
+
+        // The clients without the version checked could not understand the message.
+        let (checked, unchecked): (Vec<_>, Vec<_>) = self.connections
+            .iter()
+            .partition(|(_, connection)| connection.capabilities().is_some());
+
+        let checked = checked.into_iter().map(|(endpoint, _)| *endpoint).collect::<Vec<_>>();
+        let unchecked = unchecked.into_iter().map(|(endpoint, _)| *endpoint).collect::<Vec<_>>();
+
+        let message = ServerMessage::Shutdown { reason, restart_in };
+        self.metrics.send_all(&mut self.network, checked.iter(), message);
+
+        self.closing = Some(Closing::Draining);
+        for endpoint in unchecked {
+            self.network.remove_resource(endpoint.resource_id());
+            self.process_disconnection(endpoint);
+        }
+
+        match self.connections.is_empty() {
+            true => self.event_queue.sender().send_with_priority(Event::Close),
+            false => self.event_queue.sender().send_with_timer(Event::Close, SHUTDOWN_DRAIN_TIMEOUT),
+        }
     }
 
     fn create_start_game_message(game: &Game) -> ServerMessage {
         let game_info = GameInfo {
+            map_size: game.map_size() as u16,
+            winner_points: game.winner_points() as u16,
             characters: game.characters()
                 .iter()
                 .map(|(_, character)| (**character).clone())
@@ -523,7 +2040,11 @@ impl<'a> ServerManager<'a> {
                     player.character().id(),
                     player.points()
                 ))
-                .collect()
+                .collect(),
+            teams: game.players()
+                .values()
+                .map(|player| player.team())
+                .collect(),
         };
 
         ServerMessage::StartGame(game_info)
@@ -561,7 +2082,16 @@ impl<'a> ServerManager<'a> {
             }
         }).collect();
 
-        ServerMessage::GameStep(Frame { entities, spells })
+        let objects = arena.objects().values().map(|object| {
+            ObjectData {
+                id: object.id(),
+                kind: object.kind(),
+                position: object.position(),
+                carrier: object.carrier(),
+            }
+        }).collect();
+
+        ServerMessage::GameStep(Frame { entities, spells, objects })
     }
 }
 
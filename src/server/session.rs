@@ -1,8 +1,11 @@
 use crate::ids::{SessionToken};
 
-use rand::prelude::*;
-
 use std::collections::HashMap;
+use std::net::{IpAddr};
+use std::time::{Duration, Instant};
+
+/// Time while a token can be used to attach the fast endpoint since it was issued.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(30);
 
 pub enum SessionStatus {
     Created(SessionToken),
@@ -11,6 +14,13 @@ pub enum SessionStatus {
     Full,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub enum TokenRejection {
+    Unknown,
+    Expired,
+    ForeignAddress(IpAddr), // The expected ip
+}
+
 pub struct RoomSession<E, U> {
     sessions: HashMap<SessionToken, Session<E, U>>,
     size: usize,
@@ -38,8 +48,29 @@ where E: Eq, U: Eq
         self.sessions.values()
     }
 
-    pub fn session_mut(&mut self, token: SessionToken) -> Option<&mut Session<E, U>> {
-        self.sessions.get_mut(&token)
+    pub fn has_token(&self, token: SessionToken) -> bool {
+        self.sessions.contains_key(&token)
+    }
+
+    /// Attaches the fast endpoint to the session of the token.
+    /// The token must not be expired and the fast endpoint must share the ip of the safe endpoint.
+    pub fn attach_fast_endpoint(
+        &mut self,
+        token: SessionToken,
+        fast_endpoint: E,
+        ip: IpAddr,
+    ) -> Result<&Session<E, U>, TokenRejection> {
+        let session = self.sessions.get_mut(&token).ok_or(TokenRejection::Unknown)?;
+        if Instant::now() > session.token_expiration {
+            Err(TokenRejection::Expired)
+        }
+        else if session.peer_ip != ip {
+            Err(TokenRejection::ForeignAddress(session.peer_ip))
+        }
+        else {
+            session.set_untrusted_fast_endpoint(fast_endpoint);
+            Ok(session)
+        }
     }
 
     pub fn remove_session_by_endpoint(&mut self, endpoint: E) -> Option<Session<E, U>> {
@@ -50,6 +81,15 @@ where E: Eq, U: Eq
         else { None }
     }
 
+    pub fn remove_session_by_user(&mut self, user: &U) -> Option<Session<E, U>> {
+        let token = self.sessions
+            .values()
+            .find(|session| session.user() == user)
+            .map(|session| session.token())?;
+
+        self.sessions.remove(&token)
+    }
+
     pub fn session_by_endpoint(&self, safe_endpoint: E) -> Option<&Session<E, U>> {
         self.sessions.values().find(|session| {
             match session.safe_endpoint() {
@@ -68,17 +108,23 @@ where E: Eq, U: Eq
         })
     }
 
-    pub fn create_session(&mut self, user: U, safe_endpoint: E) -> SessionStatus {
-        let existing_session = self.sessions
-            .values_mut()
-            .find(|session| *session.user() == user);
+    /// The session is bound to the ip of the safe endpoint.
+    /// A recycled session receives a new token, the previous one is no longer valid.
+    pub fn create_session(&mut self, user: U, safe_endpoint: E, peer_ip: IpAddr) -> SessionStatus {
+        let existing_token = self.sessions
+            .values()
+            .find(|session| *session.user() == user)
+            .map(|session| session.token());
 
-        if let Some(session) = existing_session {
-            match session.safe_endpoint() {
+        if let Some(token) = existing_token {
+            match self.sessions[&token].safe_endpoint() {
                 Some(_) => SessionStatus::AlreadyLogged,
                 None => {
-                    session.set_safe_endpoint(safe_endpoint);
-                    SessionStatus::Recycled(session.token())
+                    let mut session = self.sessions.remove(&token).unwrap();
+                    let new_token = self.generate_unique_token();
+                    session.renew(new_token, safe_endpoint, peer_ip);
+                    self.sessions.insert(new_token, session);
+                    SessionStatus::Recycled(new_token)
                 }
             }
         }
@@ -87,7 +133,7 @@ where E: Eq, U: Eq
         }
         else {
             let new_token = self.generate_unique_token();
-            let new_session = Session::new(new_token, user, safe_endpoint);
+            let new_session = Session::new(new_token, user, safe_endpoint, peer_ip);
             self.sessions.insert(new_token, new_session);
             SessionStatus::Created(new_token)
         }
@@ -113,8 +159,7 @@ where E: Eq, U: Eq
 
     fn generate_unique_token(&self) -> SessionToken {
         loop {
-            let mut rng = rand::thread_rng();
-            let token: SessionToken = rng.gen();
+            let token = SessionToken::random();
             if !self.sessions.contains_key(&token) {
                 break token;
             }
@@ -124,6 +169,8 @@ where E: Eq, U: Eq
 
 pub struct Session<E, U> {
     token: SessionToken,
+    token_expiration: Instant,
+    peer_ip: IpAddr,
     user: U,
     safe_endpoint: Option<E>,
     fast_endpoint: Option<E>,
@@ -131,9 +178,11 @@ pub struct Session<E, U> {
 }
 
 impl<E, U> Session<E, U> {
-    fn new(token: SessionToken, user: U, safe_endpoint: E) -> Session<E, U> {
+    fn new(token: SessionToken, user: U, safe_endpoint: E, peer_ip: IpAddr) -> Session<E, U> {
         Session {
             token,
+            token_expiration: Instant::now() + TOKEN_LIFETIME,
+            peer_ip,
             user,
             safe_endpoint: Some(safe_endpoint),
             fast_endpoint: None,
@@ -149,10 +198,23 @@ impl<E, U> Session<E, U> {
         self.token
     }
 
+    pub fn peer_ip(&self) -> IpAddr {
+        self.peer_ip
+    }
+
     pub fn safe_endpoint(&self) -> &Option<E> {
         &self.safe_endpoint
     }
 
+    /// The fast endpoint attached, trusted or not.
+    pub fn fast_endpoint(&self) -> &Option<E> {
+        &self.fast_endpoint
+    }
+
+    pub fn is_fast_endpoint_trusted(&self) -> bool {
+        self.is_fast_endpoint_trusted
+    }
+
     pub fn trusted_fast_endpoint(&self) -> &Option<E> {
         match self.is_fast_endpoint_trusted {
             true => &self.fast_endpoint,
@@ -160,11 +222,14 @@ impl<E, U> Session<E, U> {
         }
     }
 
-    fn set_safe_endpoint(&mut self, endpoint: E) {
-        self.safe_endpoint = Some(endpoint);
+    fn renew(&mut self, token: SessionToken, safe_endpoint: E, peer_ip: IpAddr) {
+        self.token = token;
+        self.token_expiration = Instant::now() + TOKEN_LIFETIME;
+        self.peer_ip = peer_ip;
+        self.safe_endpoint = Some(safe_endpoint);
     }
 
-    pub fn set_untrusted_fast_endpoint(&mut self, endpoint: E) {
+    fn set_untrusted_fast_endpoint(&mut self, endpoint: E) {
         self.fast_endpoint = Some(endpoint);
         self.is_fast_endpoint_trusted = false;
     }
@@ -0,0 +1,154 @@
+use serde::{Serialize};
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, Ipv4Addr};
+use std::time::{Duration};
+use std::thread::{self};
+
+/// Max time that a monitor can take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_REQUEST_LEN: usize = 4096;
+
+/// Pages served by the status server.
+#[derive(Debug, Clone, Copy)]
+pub enum Page {
+    Status, // Json
+    Metrics, // Prometheus text format
+}
+
+impl Page {
+    fn content_type(&self) -> &'static str {
+        match self {
+            Page::Status => "application/json",
+            Page::Metrics => "text/plain; version=0.0.4",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ServerStatus {
+    pub name: String,
+    pub version: String,
+    pub protocol: u16,
+    pub uptime: u64, // In seconds
+    pub rooms: Vec<RoomState>, // The main room first
+    pub queued: usize, // Clients waiting for a match
+    pub connections: usize,
+    pub counters: MessageCounters,
+}
+
+#[derive(Serialize)]
+pub struct RoomState {
+    pub name: String,
+    pub players_number: u8,
+    pub map_size: u16,
+    pub winner_points: u16,
+    pub game: Option<GameState>,
+    pub sessions: Vec<SessionState>,
+}
+
+#[derive(Serialize)]
+pub struct GameState {
+    pub arena_number: usize,
+    pub waiting_arena: bool,
+    pub winner_points: usize,
+    pub living_players: Vec<char>,
+    pub points: Vec<PlayerPoints>, // Sorted by points
+}
+
+#[derive(Serialize)]
+pub struct PlayerPoints {
+    pub player: char,
+    pub points: usize,
+}
+
+#[derive(Serialize)]
+pub struct SessionState {
+    pub player: char,
+    pub ip: String,
+    pub connected: bool,
+    pub udp_attached: bool,
+    pub udp_trusted: bool,
+    pub latency: Option<u64>, // In milliseconds
+}
+
+/// Messages counted since the server started.
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct MessageCounters {
+    pub received_tcp: u64,
+    pub received_udp: u64,
+    pub rate_limited: u64,
+    pub unknown: u64,
+    pub udp_rejections: u64,
+    pub frames_sent: u64,
+}
+
+/// Serves the server status and metrics by HTTP in the localhost.
+/// `page` is called by each request and could return `None` if the page is not available.
+pub fn run_http_server(
+    port: u16,
+    page: impl Fn(Page) -> Option<String> + Send + 'static,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    thread::Builder::new()
+        .name("asciiarena: status server".into())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(error) = answer_request(stream, &page) {
+                            log::warn!("Status request failed: {}", error);
+                        }
+                    }
+                    Err(error) => log::warn!("Status connection failed: {}", error),
+                }
+            }
+        })?;
+    Ok(())
+}
+
+fn answer_request(
+    mut stream: TcpStream,
+    page: &impl Fn(Page) -> Option<String>,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+    // Only the request line is needed, the headers are read to not reset the connection.
+    let mut request = Vec::new();
+    let mut buffer = [0; 512];
+    while !request.windows(4).any(|end| end == b"\r\n\r\n") && request.len() < MAX_REQUEST_LEN {
+        match stream.read(&mut buffer)? {
+            0 => break,
+            size => request.extend_from_slice(&buffer[..size]),
+        }
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+    let requested_page = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/")) | (Some("GET"), Some("/status")) => Ok(Page::Status),
+        (Some("GET"), Some("/metrics")) => Ok(Page::Metrics),
+        (Some("GET"), _) => Err(("404 Not Found", "Only /status and /metrics exist")),
+        _ => Err(("405 Method Not Allowed", "Only GET is allowed")),
+    };
+
+    let response = match requested_page {
+        Ok(requested_page) => match page(requested_page) {
+            Some(content) => http_response("200 OK", requested_page.content_type(), &content),
+            None => http_response("503 Service Unavailable", "text/plain", "Server not available"),
+        },
+        Err((status, message)) => http_response(status, "text/plain", message),
+    };
+
+    stream.write_all(response.as_bytes())
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body,
+    )
+}
@@ -1,3 +1,6 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Instant};
+
 pub fn is_valid_character_name(name: &str) -> bool {
     name.len() == 1 && name.chars().all(|c| c.is_ascii_uppercase())
 }
@@ -6,6 +9,70 @@ pub fn is_valid_character(character: char) -> bool {
     character.is_ascii_uppercase()
 }
 
+/// Ipv4 addresses mapped into ipv6 (e.g. from dual-stack sockets) are returned as ipv4.
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ipv6) => match ipv6.to_ipv4_mapped() {
+            Some(ipv4) => IpAddr::V4(ipv4),
+            None => ip,
+        },
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// Address usable to connect by udp, that only can be done from ipv4 sockets
+/// (the udp connections of message-io are bound to an ipv4 address).
+/// Returns `None` for pure ipv6 addresses.
+pub fn udp_connectable_addr(addr: SocketAddr) -> Option<SocketAddr> {
+    match canonical_ip(addr.ip()) {
+        IpAddr::V4(ipv4) => Some(SocketAddr::new(IpAddr::V4(ipv4), addr.port())),
+        IpAddr::V6(_) => None,
+    }
+}
+
+/// Rate limit that allows bursts up to its capacity.
+pub struct TokenBucket {
+    capacity: f32,
+    refill_rate: f32, // Tokens per second
+    tokens: f32,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: f32, refill_rate: f32) -> TokenBucket {
+        TokenBucket {
+            capacity,
+            refill_rate,
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token. Returns false if there are no tokens and the action should be discarded.
+    pub fn consume(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    /// A full bucket behaves as a new one, so it can be dropped.
+    pub fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.capacity
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f32();
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.last_refill = now;
+    }
+}
+
 pub mod format {
     pub fn items_to_string<I>(items: I) -> String
     where I: IntoIterator,
@@ -1,6 +1,15 @@
 use clap::{crate_version};
 use serde::{Serialize, Deserialize};
 
+/// Version of the messages layout.
+/// It only increases when a change in the messages breaks the communication.
+/// The `ProtocolVersion` struct and the position of the version messages must never change
+/// in order to understand the peers of other versions.
+pub const PROTOCOL_VERSION: u16 = 11;
+
+/// Oldest protocol version that this build is able to talk with.
+pub const MIN_PROTOCOL_VERSION: u16 = 11;
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Compatibility {
     Fully = 2,
@@ -14,47 +23,85 @@ impl Compatibility {
     }
 }
 
-enum VersionNumber {
-    Major = 0,
-    Minor = 1,
-    Patch = 2,
+/// Optional features of the protocol.
+/// Only the capabilities supported by both sides are used.
+#[derive(Debug, Clone, Copy)]
+pub enum Capability {
+    UdpFrames = 0, // Udp handshake and game frames by udp.
+    Latency = 1, // Ping messages, that also work as heartbeats, and player pings.
+    Rooms = 2, // Several rooms in the same server, each one with its own game.
+    Matchmaking = 3, // Queue that groups the clients into new rooms.
 }
 
-use VersionNumber::*;
-
-trait Version {
-    fn number(&self, number: VersionNumber) -> usize;
-    fn is_unstable(&self) -> bool;
+impl Capability {
+    const ALL: [Capability; 4] = [
+        Capability::UdpFrames,
+        Capability::Latency,
+        Capability::Rooms,
+        Capability::Matchmaking,
+    ];
 }
 
-impl Version for Vec<&str> {
-    fn number(&self, number: VersionNumber) -> usize {
-        self.get(number as usize).unwrap_or(&"0").parse().unwrap_or(0)
+/// Set of capabilities. The unknown capabilities of newer versions are ignored.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    /// Capabilities supported by this build.
+    pub fn supported() -> Capabilities {
+        Capability::ALL
+            .iter()
+            .fold(Capabilities::default(), |set, &capability| set.with(capability))
+    }
+
+    pub fn with(self, capability: Capability) -> Capabilities {
+        Capabilities(self.0 | 1 << capability as u64)
+    }
+
+    pub fn contains(&self, capability: Capability) -> bool {
+        self.0 & 1 << capability as u64 != 0
+    }
+
+    pub fn intersection(&self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 & other.0)
     }
+}
 
-    fn is_unstable(&self) -> bool {
-        // It is an alpha version that could break the compatibility at any version change.
-        self.number(Major) == 0 && self.number(Minor) == 0
+impl std::fmt::Debug for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set()
+            .entries(Capability::ALL.iter().filter(|&&capability| self.contains(capability)))
+            .finish()
     }
 }
 
-pub fn check(client_tag: &str, server_tag: &str) -> Compatibility {
-    let server_version: Vec<_> = server_tag.split('.').collect();
-    let client_version: Vec<_> = client_tag.split('.').collect();
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProtocolVersion {
+    pub tag: String, // Crate version, only informative
+    pub protocol: u16,
+    pub min_protocol: u16,
+    pub capabilities: Capabilities,
+}
 
-    if client_version.number(Major) != server_version.number(Major) {
-        Compatibility::None
+impl ProtocolVersion {
+    pub fn current() -> ProtocolVersion {
+        ProtocolVersion {
+            tag: current().into(),
+            protocol: PROTOCOL_VERSION,
+            min_protocol: MIN_PROTOCOL_VERSION,
+            capabilities: Capabilities::supported(),
+        }
     }
-    else if client_version.number(Minor) != server_version.number(Minor) {
+}
+
+/// Both sides must understand the protocol of the other one.
+/// The versions are fully compatible if they share the protocol and capabilities.
+pub fn check(local: &ProtocolVersion, remote: &ProtocolVersion) -> Compatibility {
+    if remote.protocol < local.min_protocol || local.protocol < remote.min_protocol {
         Compatibility::None
     }
-    else if client_version.number(Patch) != server_version.number(Patch) {
-        if client_version.is_unstable() || server_version.is_unstable() {
-            Compatibility::None
-        }
-        else {
-            Compatibility::NotExact
-        }
+    else if remote.protocol != local.protocol || remote.capabilities != local.capabilities {
+        Compatibility::NotExact
     }
     else {
         Compatibility::Fully
@@ -64,3 +111,68 @@ pub fn check(client_tag: &str, server_tag: &str) -> Compatibility {
 pub const fn current() -> &'static str {
     crate_version!()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(protocol: u16, min_protocol: u16, capabilities: Capabilities) -> ProtocolVersion {
+        ProtocolVersion { tag: "test".into(), protocol, min_protocol, capabilities }
+    }
+
+    #[test]
+    fn same_version_fully_compatible() {
+        let current = ProtocolVersion::current();
+        assert_eq!(check(&current, &current), Compatibility::Fully);
+    }
+
+    #[test]
+    fn older_than_min_protocol() {
+        let local = version(5, 4, Capabilities::supported());
+        let remote = version(3, 3, Capabilities::supported());
+        assert_eq!(check(&local, &remote), Compatibility::None);
+        assert_eq!(check(&remote, &local), Compatibility::None);
+    }
+
+    #[test]
+    fn different_protocol_in_range() {
+        let local = version(5, 4, Capabilities::supported());
+        let remote = version(4, 4, Capabilities::supported());
+        assert_eq!(check(&local, &remote), Compatibility::NotExact);
+        assert_eq!(check(&remote, &local), Compatibility::NotExact);
+    }
+
+    #[test]
+    fn newer_peer_requiring_a_newer_protocol() {
+        let local = version(5, 4, Capabilities::supported());
+        let remote = version(7, 6, Capabilities::supported());
+        assert_eq!(check(&local, &remote), Compatibility::None);
+    }
+
+    #[test]
+    fn different_capabilities() {
+        let local = version(5, 5, Capabilities::supported());
+        let remote = version(5, 5, Capabilities::default().with(Capability::Latency));
+        assert_eq!(check(&local, &remote), Compatibility::NotExact);
+        assert!(Compatibility::NotExact.is_compatible());
+        assert!(!Compatibility::None.is_compatible());
+    }
+
+    #[test]
+    fn capabilities_intersection() {
+        let local = Capabilities::default().with(Capability::UdpFrames).with(Capability::Rooms);
+        let remote = Capabilities::default().with(Capability::Rooms).with(Capability::Latency);
+        let shared = local.intersection(remote);
+        assert!(shared.contains(Capability::Rooms));
+        assert!(!shared.contains(Capability::UdpFrames));
+        assert!(!shared.contains(Capability::Latency));
+        assert!(!shared.contains(Capability::Matchmaking));
+    }
+
+    #[test]
+    fn unknown_capabilities_ignored() {
+        let newer = Capabilities(Capabilities::supported().0 | 1 << 63);
+        assert_eq!(newer.intersection(Capabilities::supported()), Capabilities::supported());
+        assert!(Capability::ALL.iter().all(|&capability| newer.contains(capability)));
+    }
+}